@@ -0,0 +1,522 @@
+// Animations of the days that simulate something over time - the breakout game, the oxygen flooding the ship, the bugs
+// on Eris, the moons going round. A simulation feeds frames to a Recorder as it goes, and when it's done the frames are
+// saved as an animated GIF, as a directory of numbered PNGs and/or played back in the terminal, depending on which of
+// --gif, --frames and --play the runner was given. Without any of those a Recorder does nothing, and never even asks
+// for the frames.
+//
+// Like the PNGs in image, the GIFs are written by hand. GIF has to be compressed with LZW (there's no equivalent of
+// stored blocks), but that's a lot simpler than DEFLATE. Every frame shares one global colour table, so an animation
+// can only use 256 colours between all its frames - plenty for pictures of puzzle maps.
+#![allow(dead_code)]
+
+use crate::grid::Point;
+use crate::image::{Colour, Image};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+static OUTPUTS: Mutex<Vec<Output>> = Mutex::new(vec![]);
+static SAVED: Mutex<Vec<PathBuf>> = Mutex::new(vec![]);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Output {
+    Gif(PathBuf),    //Saves name.gif in the directory
+    Frames(PathBuf), //Saves name/0000.png, name/0001.png etc. in the directory
+    Play(f64),       //Plays in the terminal, this many times faster than normal
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Animation {
+    frames: Vec<Image>,
+    delay: Duration, //Between frames
+}
+
+impl Animation {
+    pub fn new(delay: Duration) -> Animation {
+        Animation {
+            frames: vec![],
+            delay,
+        }
+    }
+
+    // Every frame has to be the same size as the first.
+    pub fn push(&mut self, frame: Image) {
+        if let Some(first) = self.frames.first() {
+            assert!(
+                (first.width(), first.height()) == (frame.width(), frame.height()),
+                "{}x{} frame in a {}x{} animation",
+                frame.width(),
+                frame.height(),
+                first.width(),
+                first.height()
+            );
+        }
+        self.frames.push(frame);
+    }
+
+    pub fn frames(&self) -> &[Image] {
+        &self.frames
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    pub fn scaled(&self, factor: usize) -> Animation {
+        Animation {
+            frames: self
+                .frames
+                .iter()
+                .map(|frame| frame.scaled(factor))
+                .collect(),
+            delay: self.delay,
+        }
+    }
+
+    // GIF89a that loops forever. Panics if the frames use more than 256 colours between them.
+    // See https://www.w3.org/Graphics/GIF/spec-gif89a.txt
+    pub fn to_gif(&self) -> Vec<u8> {
+        let (width, height) = self
+            .frames
+            .first()
+            .map_or((0, 0), |frame| (frame.width(), frame.height()));
+
+        let mut palette: Vec<Colour> = vec![];
+        let mut indices: HashMap<Colour, u8> = HashMap::new();
+        let frames: Vec<Vec<u8>> = self
+            .frames
+            .iter()
+            .map(|frame| {
+                //Mostly there are runs of the same colour, so save looking most of them up.
+                let mut previous: Option<(Colour, u8)> = None;
+                frame
+                    .pixels()
+                    .iter()
+                    .map(|colour| match previous {
+                        Some((previous_colour, index)) if previous_colour == *colour => index,
+                        _ => {
+                            let index = *indices.entry(*colour).or_insert_with(|| {
+                                palette.push(*colour);
+                                u8::try_from(palette.len() - 1)
+                                    .unwrap_or_else(|_| panic!("GIFs can only have 256 colours"))
+                            });
+                            previous = Some((*colour, index));
+                            index
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        //The colour table has 2^bits entries, and needs at least two.
+        let bits = (1..=8).find(|bits| palette.len() <= 1 << bits).unwrap();
+
+        let mut gif = b"GIF89a".to_vec();
+        gif.extend((width as u16).to_le_bytes());
+        gif.extend((height as u16).to_le_bytes());
+        gif.push(0b1111_0000 | (bits - 1)); //Global colour table, 8 bit colour resolution, unsorted, size
+        gif.extend([0, 0]); //Background colour index and pixel aspect ratio
+        palette.resize(1 << bits, Colour::BLACK);
+        for colour in &palette {
+            gif.extend([colour.r, colour.g, colour.b]);
+        }
+
+        //Loop forever.
+        gif.extend([0x21, 0xFF, 11]);
+        gif.extend(b"NETSCAPE2.0");
+        gif.extend([3, 1, 0, 0, 0]);
+
+        let delay = (self.delay.as_millis() / 10).clamp(2, u16::MAX as u128) as u16; //Hundredths of a second
+        let min_code_size = bits.max(2);
+        for frame in frames {
+            //Graphic control extension, just for the delay.
+            gif.extend([0x21, 0xF9, 4, 0]);
+            gif.extend(delay.to_le_bytes());
+            gif.extend([0, 0]);
+
+            //Image descriptor covering the whole screen, with no colour table of its own.
+            gif.push(0x2C);
+            gif.extend([0, 0, 0, 0]);
+            gif.extend((width as u16).to_le_bytes());
+            gif.extend((height as u16).to_le_bytes());
+            gif.push(0);
+
+            gif.push(min_code_size);
+            for block in lzw_encode(&frame, min_code_size).chunks(255) {
+                gif.push(block.len() as u8);
+                gif.extend(block);
+            }
+            gif.push(0);
+        }
+        gif.push(0x3B);
+        gif
+    }
+
+    pub fn save_gif(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_gif())
+    }
+
+    // Each frame as a PNG in directory, numbered from 0 with enough digits that they sort in order. Returns the paths.
+    pub fn save_frames(&self, directory: impl AsRef<Path>) -> io::Result<Vec<PathBuf>> {
+        let directory = directory.as_ref();
+        fs::create_dir_all(directory)?;
+        let digits = self.frames.len().saturating_sub(1).to_string().len().max(4);
+        self.frames
+            .iter()
+            .enumerate()
+            .map(|(number, frame)| {
+                let path = directory.join(format!("{number:0digits$}.png"));
+                frame.save(&path).map(|_| path)
+            })
+            .collect()
+    }
+
+    // Draws each frame over the last, waiting delay / speed in between.
+    pub fn play(&self, speed: f64, out: &mut impl Write) -> io::Result<()> {
+        write!(out, "\x1b[2J")?;
+        for frame in &self.frames {
+            write!(out, "\x1b[H{}", to_terminal(frame))?;
+            out.flush()?;
+            thread::sleep(self.delay.div_f64(speed));
+        }
+        Ok(())
+    }
+}
+
+// Two rows of pixels to a line, as upper half blocks coloured with the top pixel and with the bottom pixel behind,
+// using 24 bit ANSI colours.
+pub fn to_terminal(image: &Image) -> String {
+    let mut text = String::new();
+    for y in (0..image.height() as isize).step_by(2) {
+        for x in 0..image.width() as isize {
+            let top = image.get(Point::new(x, y));
+            let bottom = if y + 1 < image.height() as isize {
+                image.get(Point::new(x, y + 1))
+            } else {
+                Colour::BLACK
+            };
+            write!(
+                text,
+                "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}",
+                top.r, top.g, top.b, bottom.r, bottom.g, bottom.b
+            )
+            .unwrap();
+        }
+        text.push_str("\x1b[0m\n");
+    }
+    text
+}
+
+// Variable length LZW as GIF does it. Codes start one bit wider than the colour indices, with room for the first
+// 2^min_code_size codes to stand for themselves plus a clear code and an end code, and grow a bit at a time up to 12
+// bits as the table fills. When it's full, the encoder sends a clear code and starts again. The codes are packed least
+// significant bit first. The table is flat, with a slot for every (code, next index) pair, which is much quicker than a
+// HashMap; 0 means no code yet, as the first codes are never anything's continuation.
+fn lzw_encode(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear: u16 = 1 << min_code_size;
+    let end = clear + 1;
+    let mut bits = BitWriter::default();
+    let mut code_size = min_code_size + 1;
+    let mut next_code = end + 1;
+    let mut table = vec![0_u16; 4096 << min_code_size];
+    let slot = |prefix: u16, index: u8| ((prefix as usize) << min_code_size) + index as usize;
+
+    bits.write(clear, code_size);
+    if let Some((first, rest)) = indices.split_first() {
+        let mut prefix = *first as u16;
+        for index in rest {
+            let code = table[slot(prefix, *index)];
+            if code != 0 {
+                prefix = code;
+                continue;
+            }
+            bits.write(prefix, code_size);
+            if next_code < 4096 {
+                table[slot(prefix, *index)] = next_code;
+                next_code += 1;
+                //The decoder's a code behind, so only needs the extra bit once it's made this one too.
+                if next_code > 1 << code_size && code_size < 12 {
+                    code_size += 1;
+                }
+            } else {
+                bits.write(clear, code_size);
+                table.fill(0);
+                code_size = min_code_size + 1;
+                next_code = end + 1;
+            }
+            prefix = *index as u16;
+        }
+        bits.write(prefix, code_size);
+    }
+    bits.write(end, code_size);
+    bits.finish()
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    length: u8, //Bits in the buffer
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= (code as u32) << self.length;
+        self.length += size;
+        while self.length >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.length -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.length > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+// Collects the frames of one animation, if we're recording.
+pub struct Recorder {
+    name: String,
+    scale: usize, //For the GIF and the PNGs - the terminal gets a character per pixel (well, half of one)
+    animation: Option<Animation>,
+}
+
+pub fn recorder(name: &str, delay: Duration, scale: usize) -> Recorder {
+    Recorder {
+        name: name.to_string(),
+        scale,
+        animation: recording().then(|| Animation::new(delay)),
+    }
+}
+
+impl Recorder {
+    pub fn is_recording(&self) -> bool {
+        self.animation.is_some()
+    }
+
+    // Adds the image make makes, if we're recording.
+    pub fn frame(&mut self, make: impl FnOnce() -> Image) {
+        if let Some(animation) = &mut self.animation {
+            animation.push(make());
+        }
+    }
+
+    // Saves and/or plays the animation, however we're recording.
+    pub fn finish(self) {
+        let animation = match self.animation {
+            Some(animation) if !animation.is_empty() => animation,
+            _ => return,
+        };
+        let outputs = OUTPUTS
+            .lock()
+            .unwrap_or_else(|err| panic!("Animation outputs poisoned: {err}"))
+            .clone();
+        let scaled = outputs
+            .iter()
+            .any(|output| !matches!(output, Output::Play(_)))
+            .then(|| animation.scaled(self.scale));
+        for output in outputs {
+            let saved = match output {
+                Output::Gif(directory) => {
+                    let path = directory.join(format!("{}.gif", self.name));
+                    scaled.as_ref().unwrap().save_gif(&path).map(|_| path)
+                }
+                Output::Frames(directory) => {
+                    let path = directory.join(&self.name);
+                    scaled.as_ref().unwrap().save_frames(&path).map(|_| path)
+                }
+                Output::Play(speed) => {
+                    animation
+                        .play(speed, &mut io::stdout().lock())
+                        .unwrap_or_else(|err| panic!("Failed to play {}: {err}", self.name));
+                    continue;
+                }
+            }
+            .unwrap_or_else(|err| panic!("Failed to save {}: {err}", self.name));
+            SAVED
+                .lock()
+                .unwrap_or_else(|err| panic!("Saved animations poisoned: {err}"))
+                .push(saved);
+        }
+    }
+}
+
+pub fn start_recording(output: Output) {
+    OUTPUTS
+        .lock()
+        .unwrap_or_else(|err| panic!("Animation outputs poisoned: {err}"))
+        .push(output);
+}
+
+pub fn recording() -> bool {
+    !OUTPUTS
+        .lock()
+        .unwrap_or_else(|err| panic!("Animation outputs poisoned: {err}"))
+        .is_empty()
+}
+
+// Paths of the GIFs and frame directories saved since last asked.
+pub fn take_saved() -> Vec<PathBuf> {
+    std::mem::take(
+        &mut *SAVED
+            .lock()
+            .unwrap_or_else(|err| panic!("Saved animations poisoned: {err}")),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Reads back what lzw_encode wrote, the way a GIF decoder would.
+    fn lzw_decode(bytes: &[u8], min_code_size: u8) -> Vec<u8> {
+        let clear = 1 << min_code_size;
+        let end = clear + 1;
+        let mut table: Vec<Vec<u8>> = vec![];
+        let mut code_size = min_code_size + 1;
+        let mut previous: Option<usize> = None;
+        let mut output = vec![];
+        let mut position = 0; //In bits
+        loop {
+            let mut code = 0;
+            for bit in 0..code_size as usize {
+                let byte = bytes[(position + bit) / 8];
+                code |= (((byte >> ((position + bit) % 8)) & 1) as usize) << bit;
+            }
+            position += code_size as usize;
+
+            if code == clear {
+                table = (0..clear).map(|index| vec![index as u8]).collect();
+                table.extend([vec![], vec![]]); //The clear and end codes
+                code_size = min_code_size + 1;
+                previous = None;
+                continue;
+            }
+            if code == end {
+                break;
+            }
+            let entry = if code < table.len() {
+                table[code].clone()
+            } else {
+                //The code the encoder's only just made: the previous string plus its own first index.
+                let mut entry = table[previous.unwrap()].clone();
+                entry.push(entry[0]);
+                entry
+            };
+            if let Some(previous) = previous {
+                if table.len() < 4096 {
+                    let mut new_entry = table[previous].clone();
+                    new_entry.push(entry[0]);
+                    table.push(new_entry);
+                    if table.len() == 1 << code_size && code_size < 12 {
+                        code_size += 1;
+                    }
+                }
+            }
+            output.extend(&entry);
+            previous = Some(code);
+        }
+        assert_eq!(bytes.len(), position.div_ceil(8));
+        output
+    }
+
+    #[test]
+    fn test_lzw() {
+        //A 10x10 image of four colours, with plenty of runs to build up codes from.
+        let indices: Vec<u8> = (0..100).map(|i| ((i / 5 + i / 30) % 4) as u8).collect();
+        assert_eq!(indices, lzw_decode(&lzw_encode(&indices, 2), 2));
+        assert_eq!([0b101_100], lzw_encode(&[], 2)[..]); //Clear then end, three bits each
+
+        //Long and varied enough to fill the table and clear it a few times.
+        for min_code_size in [2, 5, 8] {
+            let mut x: u32 = 1;
+            let indices: Vec<u8> = (0..50_000)
+                .map(|i| {
+                    x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    if i % 1000 < 300 {
+                        0
+                    } else {
+                        ((x >> 16) % (1 << min_code_size)) as u8
+                    }
+                })
+                .collect();
+            assert_eq!(
+                indices,
+                lzw_decode(&lzw_encode(&indices, min_code_size), min_code_size)
+            );
+        }
+    }
+
+    #[test]
+    fn test_gif() {
+        let mut animation = Animation::new(Duration::from_millis(250));
+        let mut frame = Image::new(3, 2, Colour::BLACK);
+        animation.push(frame.clone());
+        frame.set(Point::new(2, 1), Colour::RED);
+        frame.set(Point::new(0, 0), Colour::GREEN);
+        animation.push(frame);
+
+        let gif = animation.to_gif();
+        assert_eq!(b"GIF89a", &gif[..6]);
+        assert_eq!([3, 0, 2, 0, 0b1111_0001, 0, 0], gif[6..13]); //Three colours fit a table of 4
+        assert_eq!([0, 0, 0, 40, 180, 40, 220, 40, 40, 0, 0, 0], gif[13..25]);
+        assert_eq!(b"NETSCAPE2.0", &gif[28..39]);
+
+        //Each frame: control extension, descriptor, then the image data in blocks.
+        let mut position = 44;
+        let mut frames = vec![];
+        for _ in 0..2 {
+            assert_eq!([0x21, 0xF9, 4, 0, 25, 0, 0, 0], gif[position..position + 8]);
+            assert_eq!(0x2C, gif[position + 8]);
+            assert_eq!([3, 0, 2, 0, 0], gif[position + 13..position + 18]);
+            let min_code_size = gif[position + 18];
+            assert_eq!(2, min_code_size);
+            position += 19;
+            let mut data = vec![];
+            while gif[position] != 0 {
+                let length = gif[position] as usize;
+                data.extend(&gif[position + 1..position + 1 + length]);
+                position += 1 + length;
+            }
+            position += 1;
+            frames.push(lzw_decode(&data, min_code_size));
+        }
+        assert_eq!(vec![vec![0; 6], vec![1, 0, 0, 0, 0, 2]], frames);
+        assert_eq!([0x3B], gif[position..]);
+    }
+
+    #[test]
+    #[should_panic(expected = "2x2 frame in a 3x2 animation")]
+    fn test_frame_sizes_must_match() {
+        let mut animation = Animation::new(Duration::from_millis(100));
+        animation.push(Image::new(3, 2, Colour::BLACK));
+        animation.push(Image::new(2, 2, Colour::BLACK));
+    }
+
+    #[test]
+    fn test_to_terminal() {
+        let mut image = Image::new(1, 3, Colour::WHITE);
+        image.set(Point::new(0, 1), Colour::new(1, 2, 3));
+        assert_eq!(
+            "\x1b[38;2;255;255;255m\x1b[48;2;1;2;3m\u{2580}\x1b[0m\n\
+             \x1b[38;2;255;255;255m\x1b[48;2;0;0;0m\u{2580}\x1b[0m\n",
+            to_terminal(&image)
+        );
+    }
+}
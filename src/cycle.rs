@@ -0,0 +1,165 @@
+// Cycle detection for simulations that are stepped over and over until they repeat. The state after n steps is
+// x_n = step(x_{n-1}); sooner or later (if there are finitely many states) some x_n turns up again, and from then on
+// the states go round in a loop. find_cycle remembers every state it's seen in a HashMap, so it's quick but needs the
+// memory and a hashable state; brent only ever holds two states, at the cost of stepping rather more.
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+// x_start is the first state that repeats, and it comes round again every length steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycle {
+    pub start: usize,
+    pub length: usize,
+}
+
+impl Cycle {
+    // The earliest step with the same state as step n.
+    pub fn equivalent_step(&self, n: usize) -> usize {
+        if n < self.start {
+            n
+        } else {
+            self.start + (n - self.start) % self.length
+        }
+    }
+}
+
+pub fn find_cycle<S: Clone + Eq + Hash>(initial: S, mut step: impl FnMut(&S) -> S) -> Cycle {
+    let mut seen: HashMap<S, usize> = HashMap::new();
+    let mut state = initial;
+    for n in 0.. {
+        if let Some(start) = seen.insert(state.clone(), n) {
+            return Cycle {
+                start,
+                length: n - start,
+            };
+        }
+        state = step(&state);
+    }
+    unreachable!()
+}
+
+// Brent's algorithm. The hare runs ahead in stretches of doubling length with the tortoise waiting at the start of each
+// stretch; once the hare catches it up, the stretch length gives the cycle length. Then starting a tortoise at the
+// beginning and a hare that many steps ahead, they first meet at the start of the cycle.
+// See https://en.wikipedia.org/wiki/Cycle_detection#Brent's_algorithm
+pub fn brent<S: Clone + PartialEq>(initial: S, mut step: impl FnMut(&S) -> S) -> Cycle {
+    let mut power = 1;
+    let mut length = 1;
+    let mut tortoise = initial.clone();
+    let mut hare = step(&initial);
+    while tortoise != hare {
+        if power == length {
+            tortoise = hare.clone();
+            power *= 2;
+            length = 0;
+        }
+        hare = step(&hare);
+        length += 1;
+    }
+
+    let mut tortoise = initial.clone();
+    let mut hare = initial;
+    for _ in 0..length {
+        hare = step(&hare);
+    }
+    let mut start = 0;
+    while tortoise != hare {
+        tortoise = step(&tortoise);
+        hare = step(&hare);
+        start += 1;
+    }
+
+    Cycle { start, length }
+}
+
+// The state after n steps, going round the cycle only as far as needed rather than n times.
+pub fn state_after<S>(initial: S, mut step: impl FnMut(&S) -> S, n: usize, cycle: &Cycle) -> S {
+    let mut state = initial;
+    for _ in 0..cycle.equivalent_step(n) {
+        state = step(&state);
+    }
+    state
+}
+
+// The state after n steps, finding the cycle along the way if it gets that far. Every state up to the repeat is kept,
+// so this never steps more than it has to.
+pub fn nth_state<S: Clone + Eq + Hash>(initial: S, mut step: impl FnMut(&S) -> S, n: usize) -> S {
+    let mut seen: HashMap<S, usize> = HashMap::new();
+    let mut states = vec![];
+    let mut state = initial;
+    while states.len() < n {
+        if let Some(start) = seen.get(&state) {
+            let cycle = Cycle {
+                start: *start,
+                length: states.len() - start,
+            };
+            return states.swap_remove(cycle.equivalent_step(n));
+        }
+        seen.insert(state.clone(), states.len());
+        let next = step(&state);
+        states.push(state);
+        state = next;
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //0 -> 1 -> 2 -> 5 -> 26 -> 6 -> 37 -> 28 -> 53 -> 4 -> 17 -> 46 -> 43 -> 20 -> 35 -> 6, so 6 is the first repeat.
+    fn step(x: &u32) -> u32 {
+        (x * x + 1) % 61
+    }
+
+    #[test]
+    fn test_find_cycle() {
+        let expected = Cycle {
+            start: 5,
+            length: 10,
+        };
+        assert_eq!(expected, find_cycle(0, step));
+        assert_eq!(expected, brent(0, step));
+
+        //Starting inside the cycle.
+        let expected = Cycle {
+            start: 0,
+            length: 10,
+        };
+        assert_eq!(expected, find_cycle(37, step));
+        assert_eq!(expected, brent(37, step));
+
+        //A fixed point.
+        let expected = Cycle {
+            start: 3,
+            length: 1,
+        };
+        assert_eq!(expected, find_cycle(10, |x| (x / 2).max(1)));
+        assert_eq!(expected, brent(10, |x| (x / 2).max(1)));
+    }
+
+    #[test]
+    fn test_state_after() {
+        let cycle = brent(0, step);
+        assert_eq!(1, cycle.equivalent_step(1));
+        assert_eq!(14, cycle.equivalent_step(14));
+        assert_eq!(5, cycle.equivalent_step(15));
+        assert_eq!(7, cycle.equivalent_step(1_000_000_000_007));
+
+        //Checking by brute force.
+        let mut states = vec![0];
+        for _ in 0..100 {
+            states.push(step(states.last().unwrap()));
+        }
+        for (n, state) in states.iter().enumerate() {
+            assert_eq!(*state, state_after(0, step, n, &cycle));
+            assert_eq!(*state, nth_state(0, step, n));
+        }
+        assert_eq!(
+            states[cycle.equivalent_step(1_000_000_000_007)],
+            nth_state(0, step, 1_000_000_000_007)
+        );
+    }
+}
@@ -1,6 +1,7 @@
+use crate::grid::{Direction, Point, SparseGrid};
+use crate::image::{self, Colour, Image};
 use crate::intcode;
 use crate::utils;
-use std::collections::HashMap;
 
 #[derive(Debug, Copy, Clone)]
 enum Color {
@@ -29,47 +30,31 @@ impl TryFrom<isize> for Color {
 }
 
 #[derive(Debug, Copy, Clone)]
-enum Orientation {
-    North,
-    South,
-    East,
-    West,
-}
-
-#[derive(Debug, Copy, Clone)]
-enum Direction {
+enum Turn {
     Left,
     Right,
 }
 
-impl From<Direction> for isize {
-    fn from(direction: Direction) -> isize {
-        match direction {
-            Direction::Left => 0,
-            Direction::Right => 1,
+impl From<Turn> for isize {
+    fn from(turn: Turn) -> isize {
+        match turn {
+            Turn::Left => 0,
+            Turn::Right => 1,
         }
     }
 }
 
-impl TryFrom<isize> for Direction {
+impl TryFrom<isize> for Turn {
     type Error = String;
     fn try_from(number: isize) -> Result<Self, Self::Error> {
         match number {
-            0 => Ok(Direction::Left),
-            1 => Ok(Direction::Right),
-            other => Err(format!("No match to Direction for {other}")),
+            0 => Ok(Turn::Left),
+            1 => Ok(Turn::Right),
+            other => Err(format!("No match to Turn for {other}")),
         }
     }
 }
 
-#[derive(Hash, PartialEq, Eq, PartialOrd, Copy, Clone, Debug)]
-struct Position(i32, i32);
-impl Position {
-    pub fn new(x: i32, y: i32) -> Position {
-        Position(x, y)
-    }
-}
-
 #[derive(Debug, Copy, Clone)]
 enum BrainState {
     AwaitingInput,
@@ -93,15 +78,15 @@ impl TryFrom<intcode::ProgramResult> for BrainState {
 }
 
 struct RobotOutput {
-    direction: Direction,
+    turn: Turn,
     color: Color,
     brain_state: BrainState,
 }
 
 impl RobotOutput {
-    pub fn new(direction: Direction, color: Color, brain_state: BrainState) -> RobotOutput {
+    pub fn new(turn: Turn, color: Color, brain_state: BrainState) -> RobotOutput {
         RobotOutput {
-            direction,
+            turn,
             color,
             brain_state,
         }
@@ -116,7 +101,7 @@ impl Robot {
     pub fn process_input(&mut self, color: Color) -> Result<RobotOutput, String> {
         self.program.add_input(color.into());
         let brain_state = BrainState::try_from(self.program.run())?;
-        let direction = Direction::try_from(
+        let turn = Turn::try_from(
             self.program
                 .remove_last_output()
                 .ok_or("Robot malfunction: output contains no direction")?,
@@ -126,7 +111,7 @@ impl Robot {
                 .remove_last_output()
                 .ok_or("Robot malfunction: output contains no color to paint")?,
         )?;
-        Ok(RobotOutput::new(direction, new_color, brain_state))
+        Ok(RobotOutput::new(turn, new_color, brain_state))
     }
 
     pub fn new(program: intcode::Program) -> Robot {
@@ -156,44 +141,44 @@ pub fn day11() -> (usize, usize) {
     let mut robot = Robot::new(program);
 
     //Map of painted panels position to color and number of times painted
-    let mut panels: HashMap<Position, PanelProperties> = HashMap::new();
+    let mut panels: SparseGrid<PanelProperties> = SparseGrid::new();
     run_robot(&mut panels, &mut robot);
     let part1 = panels.len();
 
     //Reinit the panels
-    panels = HashMap::new();
-    panels.insert(Position::new(0, 0), PanelProperties::new(Color::White, 0));
+    panels = SparseGrid::new();
+    panels.insert(Point::ORIGIN, PanelProperties::new(Color::White, 0));
     let program = intcode::Program::new(&initial_state);
     robot = Robot::new(program);
     run_robot(&mut panels, &mut robot);
-    let (min_x, max_x, min_y, max_y) = (
-        panels.keys().map(|p| p.0).min().unwrap(),
-        panels.keys().map(|p| p.0).max().unwrap(),
-        panels.keys().map(|p| p.1).min().unwrap(),
-        panels.keys().map(|p| p.1).max().unwrap(),
+    print!(
+        "{}",
+        panels.render(' ', |panel_properties| match panel_properties.color {
+            Color::Black => ' ',
+            Color::White => '*',
+        })
     );
 
-    for y in (min_y..(max_y + 1)).rev() {
-        for x in min_x..(max_x + 1) {
-            match panels.get(&Position::new(x, y)) {
-                Some(pp) => match pp.color {
-                    Color::Black => print!(" "),
-                    Color::White => print!("*"),
-                },
-                None => print!(" "),
-            }
-        }
-        println!();
-    }
+    image::visualise("day11", || {
+        Image::from_sparse_grid(
+            &panels,
+            Colour::BLACK,
+            |panel_properties| match panel_properties.color {
+                Color::Black => Colour::BLACK,
+                Color::White => Colour::WHITE,
+            },
+        )
+        .scaled(10)
+    });
 
     (part1, 0)
 }
 
-fn run_robot(panels: &mut HashMap<Position, PanelProperties>, robot: &mut Robot) {
-    let mut orientation = Orientation::North;
-    let mut position = Position::new(0, 0);
+fn run_robot(panels: &mut SparseGrid<PanelProperties>, robot: &mut Robot) {
+    let mut direction = Direction::Up;
+    let mut position = Point::ORIGIN;
     loop {
-        let input = match panels.get(&position) {
+        let input = match panels.get(position) {
             Some(panel_properties) => panel_properties.color,
             None => {
                 panels.insert(position, PanelProperties::new(Color::Black, 0));
@@ -208,27 +193,12 @@ fn run_robot(panels: &mut HashMap<Position, PanelProperties>, robot: &mut Robot)
             panel_properties.times_painted += 1;
         });
         //Turn
-        orientation = match output.direction {
-            Direction::Left => match orientation {
-                Orientation::North => Orientation::West,
-                Orientation::South => Orientation::East,
-                Orientation::East => Orientation::North,
-                Orientation::West => Orientation::South,
-            },
-            Direction::Right => match orientation {
-                Orientation::North => Orientation::East,
-                Orientation::South => Orientation::West,
-                Orientation::East => Orientation::South,
-                Orientation::West => Orientation::North,
-            },
+        direction = match output.turn {
+            Turn::Left => direction.turn_left(),
+            Turn::Right => direction.turn_right(),
         };
         //Move
-        position = match orientation {
-            Orientation::North => Position::new(position.0, position.1 + 1),
-            Orientation::South => Position::new(position.0, position.1 - 1),
-            Orientation::East => Position::new(position.0 + 1, position.1),
-            Orientation::West => Position::new(position.0 - 1, position.1),
-        };
+        position = position.step(direction);
 
         //Halt condition?
         match output.brain_state {
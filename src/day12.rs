@@ -1,7 +1,11 @@
+use crate::animation;
+use crate::cycle;
+use crate::grid::Point;
+use crate::image::{Colour, Image};
+use crate::math;
 use crate::utils;
-use num::integer::lcm;
 use regex::Regex;
-use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 
 #[derive(Clone, Copy, Debug, Hash, PartialEq, PartialOrd, Ord, Eq)]
 struct Vector(isize, isize, isize);
@@ -41,26 +45,20 @@ impl Moon {
     }
 }
 
-#[derive(Debug, Hash, PartialEq, Eq, Ord, PartialOrd, Clone, Copy)]
-struct OneDState {
-    // Tuples of a single coordinate of position and velocity of each of the 4 moons,
-    moon0: (isize, isize),
-    moon1: (isize, isize),
-    moon2: (isize, isize),
-    moon3: (isize, isize),
-}
+// Position and velocity of each of the moons along one axis.
+type OneDState = Vec<(isize, isize)>;
 
 pub fn day12() -> (usize, usize) {
     let moon_positions: Vec<String> = utils::parse_input("input/day12.txt");
     let mut moons = parse_moons(&moon_positions);
     let time_steps: usize = 1000;
-    (moons, _) = simulate_motion(moons, time_steps, false);
+    record_orbits(&moons, time_steps);
+    moons = simulate_motion(moons, time_steps);
 
     let part1 = calculate_energy(&moons);
 
     moons = parse_moons(&moon_positions);
-    let (_, periods) = simulate_motion(moons, 1_000_000, true);
-    let part2 = lcm(lcm(periods[0], periods[1]), periods[2]);
+    let part2 = math::lcm_all(&periods(&moons));
 
     (part1 as usize, part2)
 }
@@ -75,73 +73,8 @@ fn calculate_energy(moons: &[Moon]) -> isize {
         .sum()
 }
 
-fn simulate_motion(
-    mut moons: Vec<Moon>,
-    max_time_steps: usize,
-    find_periods: bool,
-) -> (Vec<Moon>, Vec<usize>) {
-    let mut moonsets: Vec<HashSet<OneDState>> = vec![HashSet::new(); 3];
-    let mut period_count: HashMap<usize, usize> = HashMap::new();
-    let mut period_figurator: Vec<HashMap<OneDState, Vec<usize>>> = vec![HashMap::new(); 3];
-    let mut counter = 0;
-
-    'outer: while counter < max_time_steps {
-        if find_periods {
-            for i in 0..3 {
-                //x, y and z coordinates are independent, so figure out if there's a regular period when
-                //the x coordinates are the same for positions and velocities across the 4 moons.
-                let one_d_state: OneDState = match i {
-                    //Grrr - can't dynamically index into tuple!
-                    0 => OneDState {
-                        moon0: (moons[0].position.0, moons[0].velocity.0),
-                        moon1: (moons[1].position.0, moons[1].velocity.0),
-                        moon2: (moons[2].position.0, moons[2].velocity.0),
-                        moon3: (moons[3].position.0, moons[3].velocity.0),
-                    },
-                    1 => OneDState {
-                        moon0: (moons[0].position.1, moons[0].velocity.1),
-                        moon1: (moons[1].position.1, moons[1].velocity.1),
-                        moon2: (moons[2].position.1, moons[2].velocity.1),
-                        moon3: (moons[3].position.1, moons[3].velocity.1),
-                    },
-                    2 => OneDState {
-                        moon0: (moons[0].position.2, moons[0].velocity.2),
-                        moon1: (moons[1].position.2, moons[1].velocity.2),
-                        moon2: (moons[2].position.2, moons[2].velocity.2),
-                        moon3: (moons[3].position.2, moons[3].velocity.2),
-                    },
-                    other => panic!("Should only be iterating up to 2. Got {other}"),
-                };
-
-                if moonsets[i].contains(&one_d_state) {
-                    //Seen this configuration before for the i coordinates of the moons
-
-                    period_figurator[i]
-                        .entry(one_d_state)
-                        .and_modify(|x| x.push(counter))
-                        .or_insert_with(|| vec![counter]);
-                    if period_figurator[i].get(&one_d_state).unwrap().len() == 3 {
-                        let diff = period_figurator[i].get(&one_d_state).unwrap()[2]
-                            - period_figurator[i].get(&one_d_state).unwrap()[1];
-                        let diff2 = period_figurator[i].get(&one_d_state).unwrap()[1]
-                            - period_figurator[i].get(&one_d_state).unwrap()[0];
-                        if diff == diff2 {
-                            //Cycle found
-                            period_count.insert(i, diff);
-                        } else {
-                            panic!("Cycle not found :-(   first: {:?} second: {:?}. Moon: {:#?}\nConfigurator: {:#?}", diff, diff2, moons[i], period_figurator[i].get(&one_d_state).unwrap());
-                        }
-
-                        if period_count.len() == 3 {
-                            break 'outer;
-                        }
-                    }
-                } else {
-                    moonsets[i].insert(one_d_state);
-                }
-            }
-        }
-
+fn simulate_motion(mut moons: Vec<Moon>, time_steps: usize) -> Vec<Moon> {
+    for _ in 0..time_steps {
         //Get velocity deltas due to gravity
         let mut deltas: Vec<Vector> = vec![];
         for moon in &moons {
@@ -163,14 +96,98 @@ fn simulate_motion(
             moon.velocity = Vector::add(&moon.velocity, &delta);
             moon.position = Vector::add(&moon.position, &moon.velocity);
         }
+    }
+
+    moons
+}
 
-        counter += 1;
+//The moons going round for time_steps steps, looking down the z axis, with a trail behind each of them.
+fn record_orbits(moons: &[Moon], time_steps: usize) {
+    const STEPS_PER_FRAME: usize = 5;
+    const TRAIL: usize = 100; //Steps
+    const SIZE: usize = 160; //Pixels across the picture
+    const COLOURS: [Colour; 4] = [Colour::RED, Colour::GREEN, Colour::BLUE, Colour::YELLOW];
+
+    let mut recorder = animation::recorder("day12", Duration::from_millis(40), 3);
+    if !recorder.is_recording() {
+        return;
+    }
+
+    let mut history = vec![moons.to_vec()];
+    for _ in 0..time_steps {
+        history.push(simulate_motion(history.last().unwrap().clone(), 1));
     }
 
-    (
-        moons,
-        period_count.values().copied().collect::<Vec<usize>>(),
-    )
+    //Fit everywhere the moons get to into the picture.
+    let positions = || history.iter().flatten().map(|moon| moon.position);
+    let min_x = positions().map(|position| position.0).min().unwrap();
+    let max_x = positions().map(|position| position.0).max().unwrap();
+    let min_y = positions().map(|position| position.1).min().unwrap();
+    let max_y = positions().map(|position| position.1).max().unwrap();
+    let span = (max_x - min_x).max(max_y - min_y).max(1);
+    let pixel = |position: Vector| {
+        Point::new(
+            (position.0 - min_x) * (SIZE as isize - 1) / span,
+            (position.1 - min_y) * (SIZE as isize - 1) / span,
+        )
+    };
+
+    for step in (0..=time_steps).step_by(STEPS_PER_FRAME) {
+        recorder.frame(|| {
+            let mut frame = Image::new(SIZE, SIZE, Colour::BLACK);
+            for moon in history[step.saturating_sub(TRAIL)..step].iter().flatten() {
+                let colour = COLOURS[moon.id % COLOURS.len()];
+                let faded = Colour::new(colour.r / 3, colour.g / 3, colour.b / 3);
+                frame.set(pixel(moon.position), faded);
+            }
+            for moon in &history[step] {
+                frame.set(pixel(moon.position), COLOURS[moon.id % COLOURS.len()]);
+            }
+            frame
+        });
+    }
+    recorder.finish();
+}
+
+//x, y and z coordinates are independent, so each axis goes round in a cycle of its own, and everything lines up
+//again at the LCM of those periods. The motion is reversible (you can work out the previous state from the current
+//one), so each cycle goes right back to the start.
+fn periods(moons: &[Moon]) -> Vec<usize> {
+    (0..3)
+        .map(|axis| {
+            let cycle = cycle::brent(one_d_state(moons, axis), step_one_d);
+            assert_eq!(0, cycle.start);
+            cycle.length
+        })
+        .collect()
+}
+
+fn one_d_state(moons: &[Moon], axis: usize) -> OneDState {
+    moons
+        .iter()
+        .map(|moon| match axis {
+            //Grrr - can't dynamically index into tuple!
+            0 => (moon.position.0, moon.velocity.0),
+            1 => (moon.position.1, moon.velocity.1),
+            2 => (moon.position.2, moon.velocity.2),
+            other => panic!("Should only be iterating up to 2. Got {other}"),
+        })
+        .collect()
+}
+
+//Same as a step of simulate_motion, but along one axis.
+fn step_one_d(state: &OneDState) -> OneDState {
+    state
+        .iter()
+        .map(|&(position, velocity)| {
+            let velocity = velocity
+                + state
+                    .iter()
+                    .map(|(other, _)| (other - position).signum())
+                    .sum::<isize>();
+            (position + velocity, velocity)
+        })
+        .collect()
 }
 
 fn parse_moons(moon_positions: &[String]) -> Vec<Moon> {
@@ -209,7 +226,7 @@ mod tests {
         assert_eq!(Vector(3, 5, -1), moons[3].position);
         assert_eq!(Vector(0, 0, 0), moons[3].velocity);
 
-        (moons, _) = simulate_motion(moons, 1, false);
+        moons = simulate_motion(moons, 1);
         assert_eq!(Vector(2, -1, 1), moons[0].position);
         assert_eq!(Vector(3, -1, -1), moons[0].velocity);
         assert_eq!(Vector(3, -7, -4), moons[1].position);
@@ -219,7 +236,7 @@ mod tests {
         assert_eq!(Vector(2, 2, 0), moons[3].position);
         assert_eq!(Vector(-1, -3, 1), moons[3].velocity);
 
-        (moons, _) = simulate_motion(moons, 2, false);
+        moons = simulate_motion(moons, 2);
         assert_eq!(Vector(5, -6, -1), moons[0].position);
         assert_eq!(Vector(0, -3, 0), moons[0].velocity);
         assert_eq!(Vector(0, 0, 6), moons[1].position);
@@ -229,7 +246,7 @@ mod tests {
         assert_eq!(Vector(1, -8, 2), moons[3].position);
         assert_eq!(Vector(0, -4, 0), moons[3].velocity);
 
-        (moons, _) = simulate_motion(moons, 7, false);
+        moons = simulate_motion(moons, 7);
         assert_eq!(Vector(2, 1, -3), moons[0].position);
         assert_eq!(Vector(-3, -2, 1), moons[0].velocity);
         assert_eq!(Vector(1, -8, 0), moons[1].position);
@@ -252,7 +269,7 @@ mod tests {
         ];
 
         let mut moons = parse_moons(&moon_positions);
-        (moons, _) = simulate_motion(moons, 100, false);
+        moons = simulate_motion(moons, 100);
 
         assert_eq!(Vector(8, -12, -9), moons[0].position);
         assert_eq!(Vector(-7, 3, 0), moons[0].velocity);
@@ -274,7 +291,15 @@ mod tests {
             "<x=3, y=5, z=-1>".to_string(),
         ];
         let moons = parse_moons(&moon_positions);
-        let (_, periods) = simulate_motion(moons, 3000, true);
-        assert_eq!(2772, lcm(lcm(periods[0], periods[1]), periods[2]));
+        assert_eq!(2772, math::lcm_all(&periods(&moons)));
+
+        let moon_positions: Vec<String> = vec![
+            "<x=-8, y=-10, z=0>".to_string(),
+            "<x=5, y=5, z=10>".to_string(),
+            "<x=2, y=-7, z=3>".to_string(),
+            "<x=9, y=-8, z=-3>".to_string(),
+        ];
+        let moons = parse_moons(&moon_positions);
+        assert_eq!(4686774924, math::lcm_all(&periods(&moons)));
     }
 }
@@ -1,9 +1,12 @@
+use crate::animation;
+use crate::grid::{Point, SparseGrid};
+use crate::image::{self, Colour, Image};
 use crate::intcode;
 use crate::utils;
-use std::collections::HashMap;
 use std::fmt;
 #[allow(unused_imports)]
 use std::io::stdin;
+use std::time::Duration;
 
 #[derive(Clone, Copy, Debug, Hash, PartialEq, PartialOrd, Ord, Eq)]
 struct Tile {
@@ -23,18 +26,6 @@ impl Tile {
     }
 }
 
-#[derive(Clone, Copy, Debug, Hash, PartialEq, PartialOrd, Ord, Eq)]
-struct Position {
-    x: isize,
-    y: isize,
-}
-
-impl Position {
-    pub fn new(x: isize, y: isize) -> Position {
-        Position { x, y }
-    }
-}
-
 #[derive(Clone, Copy, Debug, Hash, PartialEq, PartialOrd, Ord, Eq)]
 enum TileType {
     Empty,
@@ -58,47 +49,49 @@ impl TryFrom<isize> for TileType {
     }
 }
 
+impl From<TileType> for char {
+    fn from(tile_type: TileType) -> char {
+        match tile_type {
+            TileType::Empty => ' ',
+            TileType::Wall => 'W',
+            TileType::Block => 'B',
+            TileType::Paddle => '_',
+            TileType::Ball => 'O',
+        }
+    }
+}
+
 impl fmt::Display for TileType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let tile = match self {
-            Self::Empty => " ",
-            Self::Wall => "W",
-            Self::Block => "B",
-            Self::Paddle => "_",
-            Self::Ball => "O",
-        };
-        write!(f, "{}", tile)
+        write!(f, "{}", char::from(*self))
+    }
+}
+
+fn tile_colour(tile_type: TileType) -> Colour {
+    match tile_type {
+        TileType::Empty => Colour::BLACK,
+        TileType::Wall => Colour::GREY,
+        TileType::Block => Colour::BLUE,
+        TileType::Paddle => Colour::WHITE,
+        TileType::Ball => Colour::RED,
     }
 }
 
-#[allow(unused_variables)]
-fn draw(
-    program: &intcode::Program,
-    tiles: &mut HashMap<Position, Tile>,
-    min_x: isize,
-    max_x: isize,
-    min_y: isize,
-    max_y: isize,
-) {
+fn draw(program: &intcode::Program, tiles: &mut SparseGrid<Tile>) {
     //As the output grows this function becomes increasingly inefficient as each time the program
     //is run the new output is a delta to the previous one. Maybe fix this later.
     program.outputs().chunks(3).for_each(|chunk| {
         if chunk[0] != -1 {
-            tiles.insert(Position::new(chunk[0], chunk[1]), Tile::new(chunk[2]));
+            tiles.insert(Point::new(chunk[0], chunk[1]), Tile::new(chunk[2]));
         }
     });
 
-    //Uncomment this to see the game in action
-
-    // for y in min_y..max_y {
-    //     for x in min_x..max_x {
-    //         match tiles.get(&Position::new(x, y)) {
-    //             Some(tile) => print!("{}", tile.tile_type),
-    //             None => print!("{}", TileType::Empty),
-    //         }
-    //     }
-    //     println!();
-    // }
+    //Uncomment this to see the game in action (or run with --play)
+    // print!("{}", tiles.render(char::from(TileType::Empty), |tile| tile.tile_type.into()));
+}
+
+fn game_image(tiles: &SparseGrid<Tile>) -> Image {
+    Image::from_sparse_grid(tiles, Colour::BLACK, |tile| tile_colour(tile.tile_type))
 }
 
 pub fn day13() -> (usize, usize) {
@@ -115,31 +108,21 @@ pub fn day13() -> (usize, usize) {
     //First learn the boundaries of the game max/min x and y - we assume this doesn't change for the lifetime of
     //the game (note that the scoreboard instruction doesn't appear until you "insert quarters", set the first
     //element of program to 2 - this means we don't accidentally set min_x = -1.)
-    let mut tiles: HashMap<Position, Tile> = HashMap::new();
+    let mut tiles: SparseGrid<Tile> = SparseGrid::new();
     program.outputs().chunks(3).for_each(|chunk| {
-        tiles.insert(Position::new(chunk[0], chunk[1]), Tile::new(chunk[2]));
+        tiles.insert(Point::new(chunk[0], chunk[1]), Tile::new(chunk[2]));
     });
 
-    let (min_x, max_x, min_y, max_y) = tiles.keys().fold(
-        (isize::MAX, isize::MIN, isize::MAX, isize::MIN),
-        |(min_x, max_x, min_y, max_y), position| {
-            (
-                min_x.min(position.x),
-                max_x.max(position.x),
-                min_y.min(position.y),
-                max_y.max(position.y),
-            )
-        },
-    );
-
     // Assert the thing I said above
-    assert!(min_x >= 0);
+    assert!(tiles.bounds().unwrap().min.x >= 0);
 
     program.initialize(&initial_state);
     program.set_value_at(0, 2);
     program.run();
 
-    draw(&program, &mut tiles, min_x, max_x, min_y, max_y);
+    draw(&program, &mut tiles);
+    let mut recorder = animation::recorder("day13", Duration::from_millis(20), 10);
+    recorder.frame(|| game_image(&tiles));
 
     //Now set the game loop
     let mut remaining_blocks = part1;
@@ -189,7 +172,8 @@ pub fn day13() -> (usize, usize) {
         program.clear_outputs();
         program.add_input(program_input);
         program.run();
-        draw(&program, &mut tiles, min_x, max_x, min_y, max_y);
+        draw(&program, &mut tiles);
+        recorder.frame(|| game_image(&tiles));
 
         remaining_blocks = tiles
             .iter()
@@ -200,6 +184,9 @@ pub fn day13() -> (usize, usize) {
             .count();
     }
 
+    recorder.finish();
+    image::visualise("day13", || game_image(&tiles).scaled(10));
+
     //Extract the score from the outputs
     let part2 = program
         .outputs()
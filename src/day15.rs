@@ -1,32 +1,10 @@
+use crate::animation;
+use crate::grid::{Direction, Point, SparseGrid};
+use crate::image::{self, Colour, Image};
 use crate::intcode;
+use crate::search;
 use crate::utils;
-use std::collections::HashMap;
-use std::collections::HashSet;
-use std::collections::VecDeque;
-
-static ALL_DIRECTIONS: [Direction; 4] = [
-    Direction::North,
-    Direction::South,
-    Direction::East,
-    Direction::West,
-];
-
-#[derive(Debug, Clone)]
-struct Location {
-    position: Position,
-    contents: LocationContents,
-    path: Path, //Shortest path to location from origin.
-}
-
-impl Location {
-    pub fn new(position: Position, contents: LocationContents, path: Path) -> Location {
-        Location {
-            position,
-            contents,
-            path,
-        }
-    }
-}
+use std::time::Duration;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 enum LocationContents {
@@ -57,81 +35,23 @@ impl TryFrom<isize> for LocationContents {
     }
 }
 
-#[derive(Debug, Clone)]
-struct Path {
-    path: Vec<Direction>,
-    reverse_path: Vec<Direction>,
-}
-
-impl Path {
-    pub fn new(path: Vec<Direction>) -> Path {
-        let mut reverse_path = vec![];
-        for direction in &path {
-            reverse_path.push(direction.opposite());
-        }
-        reverse_path.reverse();
-
-        Path { path, reverse_path }
-    }
-
-    pub fn forwards(&self) -> &Vec<Direction> {
-        &self.path
-    }
-
-    pub fn reverse(&self) -> &Vec<Direction> {
-        &self.reverse_path
-    }
-}
-
-#[derive(Debug, Copy, Clone)]
-enum Direction {
-    North,
-    South,
-    East,
-    West,
-}
-
-impl Direction {
-    pub fn opposite(&self) -> Direction {
-        match self {
-            Direction::North => Direction::South,
-            Direction::South => Direction::North,
-            Direction::East => Direction::West,
-            Direction::West => Direction::East,
-        }
-    }
-}
-
-impl From<Direction> for isize {
-    fn from(direction: Direction) -> isize {
-        match direction {
-            Direction::North => 1,
-            Direction::South => 2,
-            Direction::West => 3,
-            Direction::East => 4,
-        }
-    }
-}
-
-impl TryFrom<isize> for Direction {
-    type Error = String;
-    fn try_from(number: isize) -> Result<Self, Self::Error> {
-        match number {
-            1 => Ok(Direction::North),
-            2 => Ok(Direction::South),
-            3 => Ok(Direction::West),
-            4 => Ok(Direction::East),
-            other => Err(format!("No match to MovementCommand for {other}")),
+impl From<LocationContents> for char {
+    fn from(location_contents: LocationContents) -> char {
+        match location_contents {
+            LocationContents::Wall => '#',
+            LocationContents::Empty => '.',
+            LocationContents::Oxygen => 'O',
         }
     }
 }
 
-#[derive(Hash, PartialEq, Eq, PartialOrd, Copy, Clone, Debug)]
-struct Position(i32, i32);
-
-impl Position {
-    pub fn new(x: i32, y: i32) -> Position {
-        Position(x, y)
+//Movement command the droid understands for each direction. North is up.
+fn movement_command(direction: Direction) -> isize {
+    match direction {
+        Direction::Up => 1,
+        Direction::Down => 2,
+        Direction::Left => 3,
+        Direction::Right => 4,
     }
 }
 
@@ -147,227 +67,172 @@ pub fn day15() -> (usize, usize) {
     //current location and backtrack only to that point along the droid's current path and then jump
     //to that path.
 
-    //This stores known locations - we've not necessarily explored these yet, but they are guaranteed either to
-    //have been explored or they've been added to the explore queue.
-    let mut known_locations: HashMap<Position, Location> = HashMap::new();
-
-    //This implements the search and returns the shortest path
-    let part1 = find_shortest_path_to_oxygen_system(&mut program, &mut known_locations);
-
-    //Adapt the print logic from day 11.
-    let (min_x, max_x, min_y, max_y) = (
-        known_locations.keys().map(|p| p.0).min().unwrap(),
-        known_locations.keys().map(|p| p.0).max().unwrap(),
-        known_locations.keys().map(|p| p.1).min().unwrap(),
-        known_locations.keys().map(|p| p.1).max().unwrap(),
-    );
-
-    for y in (min_y..(max_y + 1)).rev() {
-        for x in min_x..(max_x + 1) {
-            match known_locations.get(&Position::new(x, y)) {
-                Some(location) => match location.contents {
-                    LocationContents::Wall => print!("#"),
-                    LocationContents::Empty => {
-                        if location.position == Position::new(0, 0) {
-                            print!("X")
-                        } else {
-                            print!(".")
-                        }
-                    }
-                    LocationContents::Oxygen => print!("O"),
-                },
-                None => print!(" "),
-            }
-        }
-        println!();
-    }
+    //Explore the whole map depth-first, then search it.
+    let map = explore(|direction| {
+        program.add_input(movement_command(direction));
+        program.run();
+        LocationContents::try_from(program.remove_last_output().unwrap())
+            .unwrap_or_else(|err| panic!("Output doesn't correspond to location! Message: {err}"))
+    });
 
-    let oxygen_position = known_locations
+    //Mark the origin with an X
+    let picture: SparseGrid<char> = map
         .iter()
-        .filter(|(_, location)| location.contents == LocationContents::Oxygen)
-        .last()
-        .unwrap()
-        .0;
-
-    let mut part2 = 0;
-    let mut oxygenated_areas: HashSet<Position> = HashSet::from([*oxygen_position]);
-    loop {
-        part2 += 1;
-
-        let mut newly_oxygenated_areas: Vec<Position> = vec![];
-        for position in &oxygenated_areas {
-            for direction in &ALL_DIRECTIONS {
-                let new_position = match direction {
-                    Direction::North => Position::new(position.0, position.1 + 1),
-                    Direction::South => Position::new(position.0, position.1 - 1),
-                    Direction::West => Position::new(position.0 - 1, position.1),
-                    Direction::East => Position::new(position.0 + 1, position.1),
-                };
-                known_locations.entry(new_position).and_modify(|e| {
-                    if let LocationContents::Empty = e.contents {
-                        e.contents = LocationContents::Oxygen;
-                        newly_oxygenated_areas.push(new_position);
-                    }
-                });
-            }
-        }
+        .map(|(position, contents)| {
+            let c = if position == Point::ORIGIN {
+                'X'
+            } else {
+                char::from(*contents)
+            };
+            (position, c)
+        })
+        .collect();
+    print!("{}", picture.render(' ', |c| *c));
+    image::visualise("day15", || {
+        Image::from_sparse_grid(&picture, Colour::BLACK, |c| match c {
+            '#' => Colour::GREY,
+            'O' => Colour::BLUE,
+            'X' => Colour::RED,
+            _ => Colour::WHITE,
+        })
+        .scaled(8)
+    });
 
-        oxygenated_areas.extend(newly_oxygenated_areas);
+    record_flood(&map);
 
-        if !known_locations
-            .values()
-            .any(|location| location.contents == LocationContents::Empty)
-        {
-            //All empty space now oxygenated
-            break;
-        }
-    }
-
-    (part1, part2)
+    (shortest_path_to_oxygen_system(&map), minutes_to_fill(&map))
 }
 
-fn find_shortest_path_to_oxygen_system(
-    program: &mut intcode::Program,
-    known_locations: &mut HashMap<Position, Location>,
-) -> usize {
-    //Stores a queue of locations to explore. Push new locations onto the back, pop the next one from the front
-    let mut explore_queue: VecDeque<Position> = VecDeque::new();
-
-    //Create the initial origin location. It's empty.
-    let origin = Location::new(
-        Position::new(0, 0),
-        LocationContents::Empty,
-        Path::new(vec![]),
-    );
-    explore_queue.push_back(origin.position);
-    known_locations.insert(Position::new(0, 0), origin);
-
-    //println!("Initial explore queue: {:?}", explore_queue);
-    //println!("Initial known locations: {:?}", known_locations);
-    //println!();
-
-    //Now the main loop
-    loop {
-        let position_to_explore = explore_queue.pop_front().unwrap();
-        //println!("Exploring {:?}", position_to_explore);
-
-        follow_path(program, position_to_explore, known_locations, true);
-
-        for direction in &ALL_DIRECTIONS {
-            let found_oxygen_system = check_in_direction(
-                position_to_explore,
-                direction,
-                program,
-                known_locations,
-                &mut explore_queue,
-            );
+//Explores everywhere the droid can get to from where it starts (the origin), returning what's at each location
+//found. try_move moves the droid one step in a direction, returning what's there - it stays put if that's a wall.
+fn explore(
+    mut try_move: impl FnMut(Direction) -> LocationContents,
+) -> SparseGrid<LocationContents> {
+    let mut map = SparseGrid::new();
+    map.insert(Point::ORIGIN, LocationContents::Empty);
+    explore_from(Point::ORIGIN, &mut map, &mut try_move);
+    map
+}
 
-            if found_oxygen_system {
-                return known_locations
-                    .get(&position_to_explore)
-                    .unwrap()
-                    .path
-                    .path
-                    .len()
-                    + 1;
-            }
+//Tries every direction we don't know about yet from position, exploring beyond anything that isn't a wall and then
+//stepping back again, so the droid always finishes where it started.
+fn explore_from(
+    position: Point,
+    map: &mut SparseGrid<LocationContents>,
+    try_move: &mut impl FnMut(Direction) -> LocationContents,
+) {
+    for direction in Direction::ALL {
+        let new_position = position.step(direction);
+        if map.contains(new_position) {
+            continue;
         }
 
-        //retrace steps
-        follow_path(program, position_to_explore, known_locations, false);
-
-        //println!("Explore queue now: {:?}", explore_queue);
-        //println!("Known locations now: {:?}", known_locations);
-        //println!();
+        let contents = try_move(direction);
+        map.insert(new_position, contents);
+        if contents != LocationContents::Wall {
+            explore_from(new_position, map, try_move);
+            assert_ne!(LocationContents::Wall, try_move(direction.reverse()));
+        }
     }
 }
 
-fn follow_path(
-    program: &mut intcode::Program,
-    position: Position,
-    known_locations: &HashMap<Position, Location>,
-    forwards: bool,
-) {
-    let location = known_locations.get(&position).unwrap();
-
-    let path = if forwards {
-        location.path.forwards()
-    } else {
-        location.path.reverse()
-    };
-
-    //println!("  Following path forwards? {}. Path: {:?}", forwards, path);
-
-    for direction in path {
-        program.add_input((*direction).into());
-        program.run();
-        assert_eq!(
-            LocationContents::Empty,
-            LocationContents::try_from(program.remove_last_output().unwrap()).unwrap()
+fn open_neighbours(
+    map: &SparseGrid<LocationContents>,
+    position: Point,
+) -> impl Iterator<Item = Point> + '_ {
+    position.neighbours().filter(|neighbour| {
+        matches!(
+            map.get(*neighbour),
+            Some(LocationContents::Empty | LocationContents::Oxygen)
         )
+    })
+}
+
+fn shortest_path_to_oxygen_system(map: &SparseGrid<LocationContents>) -> usize {
+    search::bfs(
+        [Point::ORIGIN],
+        |position| open_neighbours(map, *position),
+        |position| map[*position] == LocationContents::Oxygen,
+    )
+    .goal_distance()
+    .expect("Couldn't find the oxygen system")
+}
+
+//How far oxygen has to spread from the oxygen system to get everywhere.
+fn oxygen_spread(map: &SparseGrid<LocationContents>) -> search::Search<Point> {
+    let oxygen_system = map
+        .find(|contents| *contents == LocationContents::Oxygen)
+        .expect("Couldn't find the oxygen system");
+    search::bfs(
+        [oxygen_system],
+        |position| open_neighbours(map, *position),
+        |_| false,
+    )
+}
+
+//Oxygen spreads one location a minute, so the time to fill the area is the distance to the furthest location from the
+//oxygen system.
+fn minutes_to_fill(map: &SparseGrid<LocationContents>) -> usize {
+    *oxygen_spread(map).distances().values().max().unwrap()
+}
+
+//A frame a minute of the oxygen filling the area.
+fn record_flood(map: &SparseGrid<LocationContents>) {
+    let mut recorder = animation::recorder("day15", Duration::from_millis(50), 8);
+    if !recorder.is_recording() {
+        return;
     }
-}
-
-//Checks in specified direction and returns True if we've found the Oxygen System, false otherwise
-fn check_in_direction(
-    position_to_explore: Position,
-    direction: &Direction,
-    program: &mut intcode::Program,
-    known_locations: &mut HashMap<Position, Location>,
-    explore_queue: &mut VecDeque<Position>,
-) -> bool {
-    // println!(
-    //     "  Checking around position: {:?} in direction: {:?}",
-    //     position_to_explore, direction
-    // );
-
-    let new_position = match direction {
-        Direction::North => Position::new(position_to_explore.0, position_to_explore.1 + 1),
-        Direction::South => Position::new(position_to_explore.0, position_to_explore.1 - 1),
-        Direction::West => Position::new(position_to_explore.0 - 1, position_to_explore.1),
-        Direction::East => Position::new(position_to_explore.0 + 1, position_to_explore.1),
-    };
-
-    let mut path = known_locations
-        .get(&position_to_explore)
-        .unwrap()
-        .path
-        .path
-        .clone();
-    path.push(*direction);
-
-    let mut found_oxygen_system = false;
-
-    //This closure does a lot of heavy lifting - i.e. it has side effects such as running the program
-    //and adding the new location to the explore queue if it's empty.
-    known_locations.entry(new_position).or_insert_with(|| {
-        program.add_input((*direction).into());
-        program.run();
 
-        match LocationContents::try_from(program.remove_last_output().unwrap()) {
-            Ok(LocationContents::Wall) => {
-                //println!("  Found wall!");
-                Location::new(new_position, LocationContents::Wall, Path::new(path))
-            }
-            Ok(LocationContents::Empty) => {
-                //Droid has moved forward - move back
-                program.add_input(direction.opposite().into());
-                program.run();
-                explore_queue.push_back(new_position);
-                //println!("  Found empty!");
-                Location::new(new_position, LocationContents::Empty, Path::new(path))
+    let mut by_minute: Vec<(Point, usize)> = oxygen_spread(map)
+        .distances()
+        .iter()
+        .map(|(position, minutes)| (*position, *minutes))
+        .collect();
+    by_minute.sort_by_key(|(_, minutes)| *minutes);
+    let origin = map.bounds().unwrap().min;
+    let mut frame = Image::from_sparse_grid(map, Colour::BLACK, |contents| match contents {
+        LocationContents::Wall => Colour::GREY,
+        _ => Colour::WHITE,
+    });
+    for filled in by_minute.chunk_by(|(_, a), (_, b)| a == b) {
+        for (position, _) in filled {
+            frame.set(*position - origin, Colour::BLUE);
+        }
+        recorder.frame(|| frame.clone());
+    }
+    recorder.finish();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::Grid;
+
+    //Explores a map drawn as text, with the droid starting at D. Anything off the map is a wall.
+    fn explore_map(lines: &[&str]) -> SparseGrid<LocationContents> {
+        let maze: Grid<char> = lines.join("\n").parse().unwrap();
+        let mut droid = maze.find(|c| *c == 'D').unwrap();
+        explore(|direction| match maze.get(droid.step(direction)) {
+            Some('.') | Some('D') => {
+                droid = droid.step(direction);
+                LocationContents::Empty
             }
-            Ok(LocationContents::Oxygen) => {
-                //Droid has moved forward - move back.
-                program.add_input(direction.opposite().into());
-                program.run();
-                found_oxygen_system = true;
-                //println!("  Found oxygen system!!!");
-                Location::new(new_position, LocationContents::Oxygen, Path::new(path))
+            Some('O') => {
+                droid = droid.step(direction);
+                LocationContents::Oxygen
             }
-            Err(err) => panic!("Output doesn't correspond to location! Message: {err}"),
-        }
-    });
+            _ => LocationContents::Wall,
+        })
+    }
 
-    found_oxygen_system
+    #[test]
+    fn test_explore() {
+        let map = explore_map(&[" ##   ", "#D.## ", "#.#..#", "#.O.# ", " ###  "]);
+        assert_eq!(
+            " ##   \n#..## \n#.#..#\n#.O.# \n ###  \n",
+            map.render(' ', |contents| char::from(*contents))
+        );
+        assert_eq!(3, shortest_path_to_oxygen_system(&map));
+        assert_eq!(4, minutes_to_fill(&map));
+    }
 }
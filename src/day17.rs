@@ -1,72 +1,42 @@
+use crate::grid::{Direction, Grid, Point};
+use crate::image::{self, Colour, Image};
 use crate::intcode;
 use crate::utils;
-use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Display;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum Orientation {
-    Up, //Up is pointing in negative y direction (yes, I know)
-    Down,
-    Left,
-    Right,
-    Tumbling,
-}
-
-impl Display for Orientation {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let output: &str = match self {
-            Orientation::Down => "v",
-            Orientation::Up => "^",
-            Orientation::Left => "<",
-            Orientation::Right => ">",
-            Orientation::Tumbling => "X",
-        };
-        write!(f, "{output}")
-    }
-}
-
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
-struct Position {
-    //Coordinates start at (0, 0) in upper left. y increases down the way.
-    x: isize,
-    y: isize,
-}
-
-impl Position {
-    pub fn new(x: isize, y: isize) -> Position {
-        Position { x, y }
-    }
-}
-
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum Item {
     Scaffold,
     Empty,
-    Robot(Orientation),
+    Robot(Option<Direction>), //None if it's tumbling through space
+}
+
+impl From<Item> for char {
+    fn from(item: Item) -> char {
+        match item {
+            Item::Scaffold => '#',
+            Item::Empty => '.',
+            Item::Robot(Some(direction)) => direction.into(),
+            Item::Robot(None) => 'X',
+        }
+    }
 }
 
 impl Display for Item {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Item::Scaffold => write!(f, "#"),
-            Item::Empty => write!(f, "."),
-            Item::Robot(orientation) => orientation.fmt(f),
-        }
+        write!(f, "{}", char::from(*self))
     }
 }
 
-impl TryFrom<isize> for Item {
+impl TryFrom<char> for Item {
     type Error = String;
-    fn try_from(item: isize) -> Result<Self, Self::Error> {
+    fn try_from(item: char) -> Result<Self, Self::Error> {
         match item {
-            35 => Ok(Item::Scaffold),
-            46 => Ok(Item::Empty),
-            94 => Ok(Item::Robot(Orientation::Up)),
-            118 => Ok(Item::Robot(Orientation::Down)),
-            60 => Ok(Item::Robot(Orientation::Left)),
-            62 => Ok(Item::Robot(Orientation::Right)),
-            88 => Ok(Item::Robot(Orientation::Tumbling)),
+            '#' => Ok(Item::Scaffold),
+            '.' => Ok(Item::Empty),
+            'X' => Ok(Item::Robot(None)),
+            '^' | 'v' | '<' | '>' => Ok(Item::Robot(Some(Direction::try_from(item)?))),
             other => Err(format!("Bad item identifier: {}", other)),
         }
     }
@@ -77,46 +47,34 @@ pub fn day17() -> (usize, usize) {
     let mut program = intcode::Program::new(&input);
     program.run();
 
-    let mut scaffold_map: HashMap<Position, Item> = HashMap::new();
-    let mut robot_start = Position::new(0, 0);
-    let mut x = 0;
-    let mut y = 0;
-
-    //Visualize the map and assign locations to coordinate map
-    for output in program.outputs() {
-        if *output == 10 {
-            //newline
-            println!();
-            y += 1;
-            x = 0;
-        } else {
-            let item = Item::try_from(*output).expect("Bad item");
-            scaffold_map.insert(Position::new(x, y), item);
-            print!("{}", item);
-            if let Item::Robot(_) = item {
-                robot_start = Position::new(x, y);
-            }
-            x += 1;
-        }
-    }
+    //Visualize the map and assign locations to coordinate map. Coordinates start at (0, 0) in upper left.
+    let camera: String = program.outputs().iter().map(|&c| c as u8 as char).collect();
+    print!("{}", camera);
+    let scaffold_map: Grid<Item> = camera.parse().expect("Bad item");
+    image::visualise("day17", || {
+        Image::from_grid(&scaffold_map, |item| match item {
+            Item::Scaffold => Colour::WHITE,
+            Item::Empty => Colour::BLACK,
+            Item::Robot(_) => Colour::RED,
+        })
+        .scaled(8)
+    });
+    let robot_start = scaffold_map
+        .find(|item| matches!(item, Item::Robot(_)))
+        .expect("No robot");
 
     //The above should have found a non-zero position for the robot starting point.
-    assert_ne!(robot_start, Position::new(0, 0));
+    assert_ne!(robot_start, Point::ORIGIN);
 
     //Ok, now do part1. Intersection is any scaffold in the scaffold map whose immediate non-diagonal neighbours
     //are also scaffold. For any intersection, multiply x and y, then sum over intersections. Easy!
     let part1: isize = scaffold_map
         .iter()
         .filter(|(position, item)| {
-            **item == Item::Scaffold && {
-                let directions = [(0, 1), (0, -1), (1, 0), (-1, 0)];
-                directions.iter().all(|(dx, dy)| {
-                    check_scaffold(
-                        &scaffold_map,
-                        &Position::new(position.x + *dx, position.y + *dy),
-                    )
-                })
-            }
+            **item == Item::Scaffold
+                && position
+                    .neighbours()
+                    .all(|neighbour| check_scaffold(&scaffold_map, neighbour))
         })
         .map(|(position, _)| position.x * position.y)
         .sum();
@@ -187,22 +145,23 @@ fn set_inputs_and_run(
 }
 
 #[allow(dead_code)]
-fn calculate_path(scaffold_map: &HashMap<Position, Item>, robot_start: Position) -> Vec<String> {
-    //Initialize the output path, current orientation, current position and move counter
+fn calculate_path(scaffold_map: &Grid<Item>, robot_start: Point) -> Vec<String> {
+    //Initialize the output path, current direction, current position and move counter
     let mut path = vec![];
-    let mut current_orientation = match scaffold_map.get(&robot_start).unwrap() {
-        Item::Robot(orientation) => *orientation,
+    let mut current_direction = match scaffold_map[robot_start] {
+        Item::Robot(Some(direction)) => direction,
+        Item::Robot(None) => panic!("AAAAAAGH! Tumbling through space"),
         _ => panic!("Robot not at start location!!!"),
     };
     let mut current_position = robot_start;
     let mut move_counter = 0;
 
     loop {
-        //Update orientation if required
-        if !update_orientation(
-            &mut current_orientation,
+        //Update direction if required
+        if !update_direction(
+            &mut current_direction,
             scaffold_map,
-            &mut current_position,
+            current_position,
             &mut move_counter,
             &mut path,
         ) {
@@ -211,22 +170,13 @@ fn calculate_path(scaffold_map: &HashMap<Position, Item>, robot_start: Position)
 
         //Now we're pointing in the right direction, let's move - increment the move counter and update the position!
         move_counter += 1;
-
-        let (dx, dy) = match current_orientation {
-            Orientation::Up => (0, -1),
-            Orientation::Down => (0, 1),
-            Orientation::Left => (-1, 0),
-            Orientation::Right => (1, 0),
-            Orientation::Tumbling => panic!("AAAAAAGH! Tumbling through space"),
-        };
-
-        current_position = Position::new(current_position.x + dx, current_position.y + dy);
+        current_position = current_position.step(current_direction);
     }
 
     path
 }
 
-fn check_scaffold(scaffold_map: &HashMap<Position, Item>, position: &Position) -> bool {
+fn check_scaffold(scaffold_map: &Grid<Item>, position: Point) -> bool {
     matches!(scaffold_map.get(position), Some(&Item::Scaffold))
 }
 
@@ -237,62 +187,32 @@ fn update_path_and_reset_counter(move_counter: &mut isize, path: &mut Vec<String
     }
 }
 
-fn update_orientation(
-    current_orientation: &mut Orientation,
-    scaffold_map: &HashMap<Position, Item>,
-    current_position: &mut Position,
+fn update_direction(
+    current_direction: &mut Direction,
+    scaffold_map: &Grid<Item>,
+    current_position: Point,
     move_counter: &mut isize,
     path: &mut Vec<String>,
 ) -> bool {
-    let (dx, dy) = match current_orientation {
-        Orientation::Up => (0, -1),
-        Orientation::Down => (0, 1),
-        Orientation::Left => (-1, 0),
-        Orientation::Right => (1, 0),
-        Orientation::Tumbling => panic!("Aaaargh! Tumbling into space"),
-    };
-
-    let forward_pos = Position::new(current_position.x + dx, current_position.y + dy);
-
-    if check_scaffold(scaffold_map, &forward_pos) {
+    if check_scaffold(scaffold_map, current_position.step(*current_direction)) {
         //Scaffold straight ahead - carry on
         return true;
     }
 
-    // No scaffolding ahead; need to change orientation. Check to the left and right (no going back!)
-    let (right_dx, right_dy, left_dx, left_dy) = match current_orientation {
-        Orientation::Up => (1, 0, -1, 0),
-        Orientation::Down => (-1, 0, 1, 0),
-        Orientation::Left => (0, -1, 0, 1),
-        Orientation::Right => (0, 1, 0, -1),
-        Orientation::Tumbling => panic!("Aaaargh! Tumbling into space"),
-    };
+    // No scaffolding ahead; need to change direction. Check to the left and right (no going back!)
+    let right = current_direction.turn_right();
+    let left = current_direction.turn_left();
 
-    let right_pos = Position::new(current_position.x + right_dx, current_position.y + right_dy);
-    let left_pos = Position::new(current_position.x + left_dx, current_position.y + left_dy);
-
-    if check_scaffold(scaffold_map, &right_pos) {
+    if check_scaffold(scaffold_map, current_position.step(right)) {
         //Scaffolding to the right
         update_path_and_reset_counter(move_counter, path);
         path.push("R".to_string());
-        *current_orientation = match *current_orientation {
-            Orientation::Up => Orientation::Right,
-            Orientation::Down => Orientation::Left,
-            Orientation::Left => Orientation::Up,
-            Orientation::Right => Orientation::Down,
-            Orientation::Tumbling => panic!("AAAAARGH! Tumbling into space"),
-        }
-    } else if check_scaffold(scaffold_map, &left_pos) {
+        *current_direction = right;
+    } else if check_scaffold(scaffold_map, current_position.step(left)) {
         //Scaffolding to left
         update_path_and_reset_counter(move_counter, path);
         path.push("L".to_string());
-        *current_orientation = match *current_orientation {
-            Orientation::Up => Orientation::Left,
-            Orientation::Down => Orientation::Right,
-            Orientation::Left => Orientation::Down,
-            Orientation::Right => Orientation::Up,
-            Orientation::Tumbling => panic!("AAAAARGH! Tumbling into space"),
-        }
+        *current_direction = left;
     } else {
         //No scaffolding! Must be end
         update_path_and_reset_counter(move_counter, path);
@@ -1,11 +1,14 @@
 use std::collections::HashMap;
 
-use crate::intcode;
+use crate::grid::Grid;
+use crate::image::{self, Colour, Image};
+use crate::intcode_threaded::ThreadedProgram;
 use crate::utils;
 
 pub fn day19() -> (usize, usize) {
     let initial_state: Vec<isize> = utils::parse_input_by_sep("input/day19.txt", ',');
-    let mut program = intcode::Program::new(&initial_state);
+    //The program is reset and re-run for every coordinate probed, so use the compiled backend.
+    let mut program = ThreadedProgram::new(&initial_state);
 
     let tractor_beam_map: HashMap<(usize, usize), usize> = (0..100)
         .flat_map(|x| (0..100).map(move |y| (x, y)))
@@ -29,6 +32,17 @@ pub fn day19() -> (usize, usize) {
         println!();
     }
 
+    image::visualise("day19", || {
+        let beam = Grid::from_fn(100, 100, |point| {
+            tractor_beam_map[&(point.x as usize, point.y as usize)]
+        });
+        Image::from_grid(&beam, |pulled| match pulled {
+            1 => Colour::YELLOW,
+            _ => Colour::BLACK,
+        })
+        .scaled(4)
+    });
+
     //Approximate the gradient of the left edge of the tractor beam (it might be curved, but this is just to get an approximate location to search from)
     //From that printout I can see that if I start at (100, 70) and increase x I should soon hit a 1.
     let mut x = 100;
@@ -129,7 +143,7 @@ pub fn day19() -> (usize, usize) {
 
 fn calculate_beam(
     initial_state: &[isize],
-    program: &mut intcode::Program,
+    program: &mut ThreadedProgram,
     x: isize,
     y: isize,
 ) -> isize {
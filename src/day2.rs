@@ -1,34 +1,73 @@
-use crate::intcode;
+use crate::intcode::ProgramResult;
+use crate::intcode_search::{Knob, Search, Strategy, Target};
+use crate::intcode_symbolic::{SymbolicProgram, Value};
+use crate::intcode_threaded::ThreadedProgram;
 use crate::utils;
 
 ///Day 2 solution
 pub fn day2() -> (usize, usize) {
     let initial_state: Vec<isize> = utils::parse_input_by_sep("input/day2.txt", ',');
-    let mut program = intcode::Program::new(&initial_state);
+    let mut program = ThreadedProgram::new(&initial_state);
     program.set_noun_verb_inputs(12, 2);
     program.run();
     let part1 = program.output_deprecated();
-    let mut noun = 0;
-    let mut verb = 0;
-
-    // assume noun and verb are pretty small
-    'outer: for ii in 0..100 {
-        for jj in 0..100 {
-            program.initialize(&initial_state);
-            program.set_noun_verb_inputs(ii, jj);
-            program.run();
-            let output = program.output_deprecated();
-            if output == 19690720 {
-                noun = ii;
-                verb = jj;
-                break 'outer;
-            }
-        }
+
+    if let Some((noun, verb)) = solve_symbolically(&initial_state, 19690720) {
+        return (part1 as usize, (100 * noun + verb) as usize);
     }
 
+    //The output isn't linear in the noun and verb, so nothing can be assumed about its shape - try every combination.
+    let search = Search::new(
+        &initial_state,
+        vec![
+            Knob::Memory {
+                address: 1,
+                range: 0..=99,
+            },
+            Knob::Memory {
+                address: 2,
+                range: 0..=99,
+            },
+        ],
+        Target::Equals {
+            measure: Box::new(|program| program.output_deprecated()),
+            value: 19690720,
+        },
+    );
+    let (noun, verb) = match search.run(Strategy::Exhaustive).first() {
+        Some(values) => (values[0], values[1]),
+        None => panic!("No noun and verb give 19690720"),
+    };
+
     (part1 as usize, (100 * noun + verb) as usize)
 }
 
+// The output is normally a linear function of the noun and verb, in which case we can just solve for them. None if it
+// isn't, or there's no solution.
+fn solve_symbolically(initial_state: &[isize], target: isize) -> Option<(isize, isize)> {
+    let mut program = SymbolicProgram::new(initial_state);
+    program.set_symbolic(1, "noun");
+    program.set_symbolic(2, "verb");
+    if program.run() != Ok(ProgramResult::Halted) {
+        return None;
+    }
+    let formula = match program.get_value_at(0) {
+        Value::Linear(formula) => formula,
+        Value::Unknown { .. } => return None,
+    };
+    let (a, b) = (formula.coefficient("noun"), formula.coefficient("verb"));
+    (0..=99).find_map(|noun| {
+        let remainder = target - formula.constant - a * noun;
+        let verb = match b {
+            0 if remainder == 0 => 0,
+            0 => return None,
+            b if remainder % b == 0 => remainder / b,
+            _ => return None,
+        };
+        (0..=99).contains(&verb).then_some((noun, verb))
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -44,4 +83,14 @@ mod tests {
             utils::parse_input_by_sep::<String>("input/day2_test2.txt", '-')
         );
     }
+
+    #[test]
+    fn test_solve_symbolically() {
+        //Address 0 ends up as 6 * noun + verb.
+        let image = [1, 0, 0, 3, 1, 1, 2, 3, 2, 1, 19, 0, 1, 0, 3, 0, 99, 0, 0, 5];
+        assert_eq!(Some((2, 94)), solve_symbolically(&image, 106));
+        assert_eq!(None, solve_symbolically(&image, 1000));
+        //Squares the noun, so there's no formula.
+        assert_eq!(None, solve_symbolically(&[2, 0, 0, 0, 99], 4));
+    }
 }
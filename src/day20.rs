@@ -1,29 +1,20 @@
+use crate::grid::{Direction, Grid, Point};
+use crate::image::{self, Colour, Image};
+use crate::search;
 use std::cmp::max;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::HashMap;
 use std::fs;
 
 use itertools::Itertools;
 
-#[derive(Clone, Copy, Ord, PartialEq, PartialOrd, Eq, Hash, Debug)]
-struct Position {
-    row: isize,
-    col: isize,
-}
-
-impl Position {
-    fn new(row: isize, col: isize) -> Position {
-        Position { row, col }
-    }
-}
-
 #[derive(Clone, Copy, Ord, PartialEq, PartialOrd, Eq, Hash, Debug)]
 struct HyperPosition {
-    position: Position,
+    position: Point,
     level: isize,
 }
 
 impl HyperPosition {
-    fn new(position: Position, level: isize) -> HyperPosition {
+    fn new(position: Point, level: isize) -> HyperPosition {
         HyperPosition { position, level }
     }
 }
@@ -52,11 +43,11 @@ impl From<char> for NodeType {
 #[derive(Clone, Debug)]
 struct Node {
     node_type: NodeType,
-    portal_partner: Option<Position>,
+    portal_partner: Option<Point>,
 }
 
 impl Node {
-    fn new(node_type: NodeType, portal_partner: Option<Position>) -> Node {
+    fn new(node_type: NodeType, portal_partner: Option<Point>) -> Node {
         Node {
             node_type,
             portal_partner,
@@ -66,59 +57,49 @@ impl Node {
 
 pub fn day20() -> (usize, usize) {
     let input = fs::read_to_string("input/day20.txt").expect("Could not read input file");
-    let (ascii_maze, maze, entrance, max_row_index, max_col_index) = parse_input(&input);
-
-    let (path_length_part1, path) = path_to_exit(
-        &maze,
-        &HyperPosition::new(entrance, 0),
-        false,
-        max_row_index,
-        max_col_index,
-    )
-    .expect("Failed to solve maze :(");
-
-    visualize_solution(&path, &ascii_maze, max_row_index, max_col_index);
+    let (ascii_maze, maze, entrance) = parse_input(&input);
+
+    let (path_length_part1, path) = path_to_exit(&maze, &HyperPosition::new(entrance, 0), false)
+        .expect("Failed to solve maze :(");
+
+    visualize_solution(&path, &ascii_maze);
+    image::visualise("day20", || {
+        let mut image = Image::from_grid(&ascii_maze, |c| match c {
+            '#' => Colour::GREY,
+            '.' => Colour::WHITE,
+            ' ' => Colour::BLACK,
+            _ => Colour::BLUE, //Portal labels
+        });
+        for step in &path {
+            image.set(step.position, Colour::RED);
+        }
+        image.scaled(6)
+    });
 
     #[allow(unused_variables)]
-    let (path_length_part2, path) = path_to_exit(
-        &maze,
-        &HyperPosition::new(entrance, 0),
-        true,
-        max_row_index,
-        max_col_index,
-    )
-    .expect("Failed to solve maze :(");
+    let (path_length_part2, path) = path_to_exit(&maze, &HyperPosition::new(entrance, 0), true)
+        .expect("Failed to solve maze :(");
 
     //Uncomment to visualize the hyperdimensional solution!
-    // visualize_solution(&path, &ascii_maze, max_row_index, max_col_index);
+    // visualize_solution(&path, &ascii_maze);
 
     (path_length_part1, path_length_part2)
     // (path_length_part1, 0)
 }
 
-fn visualize_solution(
-    path: &[HyperPosition],
-    ascii_maze: &HashMap<Position, char>,
-    max_row_index: isize,
-    max_col_index: isize,
-) {
+fn visualize_solution(path: &[HyperPosition], ascii_maze: &Grid<char>) {
     //First convert the path to a map of position to character
     let mut max_level = 0;
     let mut path_viz: HashMap<HyperPosition, char> = path
         .iter()
         .tuple_windows()
         .map(|(this, next)| {
-            let c = match (
-                next.position.row - this.position.row,
-                next.position.col - this.position.col,
-            ) {
-                (0, 1) => '>',
-                (0, -1) => '<',
-                (1, 0) => 'v',
-                (-1, 0) => '^',
-                _ => '*', //Teleportation!
-            };
-            //Cheeky side-effect - track the max level we've seen
+            let step = next.position - this.position;
+            let c = Direction::ALL
+                .into_iter()
+                .find(|direction| direction.offset() == step)
+                .map_or('*', char::from); //Teleportation!
+                                          //Cheeky side-effect - track the max level we've seen
             max_level = max(max_level, this.level);
             (*this, c)
         })
@@ -132,214 +113,112 @@ fn visualize_solution(
             "\n------------------------ Level {} ------------------------\n",
             level
         );
-        for row in 0..(max_row_index + 1) {
-            for col in 0..(max_col_index + 1) {
-                print!(
-                    "{}",
-                    match path_viz.get(&HyperPosition::new(Position::new(row, col), level)) {
-                        Some(c) => *c,
-                        None => *ascii_maze.get(&Position::new(row, col)).unwrap(),
-                    }
-                );
-            }
-            println!();
-        }
+        let level_map = ascii_maze
+            .map(|point, c| *path_viz.get(&HyperPosition::new(point, level)).unwrap_or(c));
+        print!("{}", level_map);
     }
 }
 
 // Returns the length of the path to the exit and the path itself for visualization.
 fn path_to_exit(
-    maze: &HashMap<Position, Node>,
+    maze: &Grid<Node>,
     entrance: &HyperPosition,
     recursive: bool, //Entrance and Exit are only available in level 0. Inner portals go down a level, outer portals back up.
-    max_row_index: isize,
-    max_col_index: isize,
 ) -> Result<(usize, Vec<HyperPosition>), &'static str> {
-    //Simple BFS algorithm. Note that the maze is a map of position rather than hyperposition to avoid unnecessary duplication/copying.
-
-    //Practicing avoiding interior mutability of nodes so use auxiliary structure
-    //to store parent relationships and path lengths.
-    //Keyed by node position, value is the position of the node's parent and the
-    //length of the path to this node
-
-    let mut search_state: HashMap<HyperPosition, (HyperPosition, usize)> = HashMap::new();
-    //Don't add the entrance to the search state as we use the absence of an entry
-    //to terminate the evaluation of the path taken through the maze
-
-    let mut frontier: VecDeque<HyperPosition> = VecDeque::new();
-    frontier.push_back(*entrance);
-
-    let mut explored: HashSet<HyperPosition> = HashSet::new();
-    let directions = [(0, 1), (1, 0), (0, -1), (-1, 0)];
-    let mut exit_position: Option<HyperPosition> = None;
-
-    'outer: while !frontier.is_empty() {
-        let current_position = frontier.pop_front().unwrap();
-        let current_node = maze.get(&current_position.position).unwrap();
-        let current_path_length = match search_state.get(&current_position) {
-            Some((_, path_length)) => *path_length,
-            None => 0,
-        };
-        explored.insert(current_position);
-
-        for (d_row, d_col) in directions.iter() {
-            let mut new_position = HyperPosition::new(
-                Position::new(
-                    current_position.position.row + d_row,
-                    current_position.position.col + d_col,
-                ),
-                current_position.level,
-            );
-            let mut new_node = maze.get(&new_position.position).unwrap();
-
-            //Check if we're stepping into the void from a portal and update the new node to the
-            //teleported-to position. In recursive mode this results in a level change
-            if let NodeType::Void = new_node.node_type {
-                //Void - stepping into the void from a portal jumps to its partner
-                if let Some(partner_pos) = current_node.portal_partner {
-                    //Overwrite the position and figure out the level
-                    let new_level = if !recursive {
-                        0
-                    } else if is_outer_portal(
-                        current_position.position,
-                        max_row_index,
-                        max_col_index,
-                    ) {
-                        current_position.level - 1
-                    } else {
-                        current_position.level + 1
-                    };
-                    new_position = HyperPosition::new(partner_pos, new_level);
-                    new_node = maze.get(&new_position.position).unwrap();
-                }
-            }
+    //Simple BFS. Note that the maze is a map of position rather than hyperposition to avoid unnecessary duplication/copying.
+    let search = search::bfs(
+        [*entrance],
+        |current_position| neighbours(maze, current_position, recursive),
+        |position| maze[position.position].node_type == NodeType::Exit,
+    );
+
+    let path = search
+        .goal_path()
+        .ok_or("Could not find a path to the exit")?;
+    Ok((path.len() - 1, path))
+}
 
-            if explored.contains(&new_position) {
-                continue;
-            } else {
-                //Take appropriate action depending on the new node type
-                match new_node.node_type {
-                    NodeType::Void | NodeType::Wall => continue, //Wall or void - ignore
-                    NodeType::Exit => {
-                        if !recursive || new_position.level == 0 {
-                            //Exit -> success! Break out of the loop
-                            //Success!
-                            exit_position = Some(new_position);
-                            search_state
-                                .insert(new_position, (current_position, current_path_length + 1));
-                            break 'outer;
-                        } else {
-                            continue; //Exit is a wall in recursive mode with level > 0
-                        }
-                    }
-                    NodeType::Empty => {
-                        //Add the new node to the frontier and update search state map
-                        frontier.push_back(new_position);
-                        search_state
-                            .insert(new_position, (current_position, current_path_length + 1));
-                    }
-                    NodeType::Portal(_) => {
-                        if !recursive
-                            || !is_outer_portal(new_position.position, max_row_index, max_col_index)
-                            || new_position.level != 0
-                        {
-                            //Add the new node to the frontier and update search state map
-                            frontier.push_back(new_position);
-                            search_state
-                                .insert(new_position, (current_position, current_path_length + 1));
-                        } else {
-                            //Outer portal at level 0. Treat as wall
-                            continue;
-                        }
-                    }
-                    NodeType::Entrance => {
-                        if !recursive {
-                            unreachable!("Found Entrance, but we should never revisit!")
-                        } else {
-                            //Treat as wall
-                            continue;
-                        }
-                    }
-                }
+// Where we can step to from current_position, going through a portal if it's on one.
+fn neighbours(
+    maze: &Grid<Node>,
+    current_position: &HyperPosition,
+    recursive: bool,
+) -> Vec<HyperPosition> {
+    let current_node = &maze[current_position.position];
+    let mut neighbours = vec![];
+
+    for step in current_position.position.neighbours() {
+        let mut new_position = HyperPosition::new(step, current_position.level);
+        let mut new_node = &maze[new_position.position];
+
+        //Check if we're stepping into the void from a portal and update the new node to the
+        //teleported-to position. In recursive mode this results in a level change
+        if let NodeType::Void = new_node.node_type {
+            //Void - stepping into the void from a portal jumps to its partner
+            if let Some(partner_pos) = current_node.portal_partner {
+                //Overwrite the position and figure out the level
+                let new_level = if !recursive {
+                    0
+                } else if is_outer_portal(maze, current_position.position) {
+                    current_position.level - 1
+                } else {
+                    current_position.level + 1
+                };
+                new_position = HyperPosition::new(partner_pos, new_level);
+                new_node = &maze[new_position.position];
             }
         }
-    }
 
-    match exit_position {
-        Some(position) => {
-            let mut path_to_exit = vec![position];
-            let (parent_position, path_length) = search_state.get(&position).unwrap_or_else(|| {
-                panic!(
-                    "Search state unexpectedly has no entry for position {:?}",
-                    position
-                )
-            });
-
-            path_to_exit.push(*parent_position);
-
-            let mut state = search_state.get(parent_position);
-            while state.is_some() {
-                let parent_position = state.unwrap().0;
-                path_to_exit.push(parent_position);
-                state = search_state.get(&parent_position);
+        //Whether we can go there depends on the new node type
+        let open = match new_node.node_type {
+            NodeType::Void | NodeType::Wall => false,
+            NodeType::Empty => true,
+            //Exit is a wall in recursive mode with level > 0
+            NodeType::Exit => !recursive || new_position.level == 0,
+            //Outer portals at level 0 are walls in recursive mode
+            NodeType::Portal(_) => {
+                !recursive
+                    || !is_outer_portal(maze, new_position.position)
+                    || new_position.level != 0
             }
-
-            path_to_exit.reverse();
-
-            Ok((*path_length, path_to_exit))
+            //We start here, so there's never any point going back
+            NodeType::Entrance => false,
+        };
+        if open {
+            neighbours.push(new_position);
         }
-        None => Err("Could not find a path to the exit"),
     }
+
+    neighbours
 }
 
-fn is_outer_portal(position: Position, max_row_index: isize, max_col_index: isize) -> bool {
-    if position.row == 2
-        || position.row == max_row_index - 2
-        || position.col == 2
-        || position.col == max_col_index - 2
-    {
-        return true;
-    }
-    false
+fn is_outer_portal(maze: &Grid<Node>, position: Point) -> bool {
+    position.y == 2
+        || position.y == maze.height() as isize - 3
+        || position.x == 2
+        || position.x == maze.width() as isize - 3
 }
 
 //Returns
 // - An ASCII representation of the maze for output
-// - the hash map of position to node for the maze
+// - the maze itself
 // - the position of the entrance node
-// - the max row index
-// - the max column index
-fn parse_input(
-    input: &str,
-) -> (
-    HashMap<Position, char>,
-    HashMap<Position, Node>,
-    Position,
-    isize,
-    isize,
-) {
-    let mut labels: HashMap<Position, char> = HashMap::new();
-    let mut portal_map: HashMap<String, Vec<Position>> = HashMap::new();
-    let mut reverse_portal_map: HashMap<Position, String> = HashMap::new();
-    let mut maze: HashMap<Position, Node> = HashMap::new();
-    let mut max_row_index: isize = 0;
-    let mut max_col_index: isize = 0;
-
-    //First pass - create the map of the maze. This will initialize all the nodes. Labels are extracted to a
-    //map to be parsed in the next step. Nodes containing labels are void, portal nodes are initialized to
-    //empty, to be fixed up in the final step.
-    let mut ascii_maze: HashMap<Position, char> = HashMap::new();
-    for (row, line) in input.lines().enumerate() {
-        for (col, c) in line.chars().enumerate() {
-            let pos = Position::new(row as isize, col as isize);
-            if c.is_ascii_uppercase() {
-                labels.insert(pos, c);
-            }
-            ascii_maze.insert(pos, c);
-            max_row_index = max(max_row_index, row as isize);
-            max_col_index = max(max_col_index, col as isize);
-        }
-    }
+fn parse_input(input: &str) -> (Grid<char>, Grid<Node>, Point) {
+    let mut portal_map: HashMap<String, Vec<Point>> = HashMap::new();
+    let mut reverse_portal_map: HashMap<Point, String> = HashMap::new();
+
+    //First pass - read the maze in. Editors tend to strip trailing spaces, so short lines are padded out with void.
+    //Nodes containing labels are void, portal nodes are empty, to be fixed up in the final step.
+    let lines: Vec<Vec<char>> = input.lines().map(|line| line.chars().collect()).collect();
+    let width = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+    let ascii_maze = Grid::from_fn(width, lines.len(), |point| {
+        lines[point.y as usize]
+            .get(point.x as usize)
+            .copied()
+            .unwrap_or(' ')
+    });
+    let max_row_index = ascii_maze.height() as isize - 1;
+    let max_col_index = ascii_maze.width() as isize - 1;
 
     //Step 2 - extract the portal and Entrance/Exit labels and update the node types correspondingly. We'll create
     //a map from label to portal node position so we can match up portal nodes in the final step.
@@ -347,107 +226,112 @@ fn parse_input(
     // The strategy is to process labels that have one other label and one empty node as opposite
     // neighbours. Labels with only a label neigbour (the rest void) are not directly processed, but picked up
     // when processing the partner label.
-    for (pos, label) in &labels {
+    for (pos, label) in ascii_maze.iter().filter(|(_, c)| c.is_ascii_uppercase()) {
         //Need to parse out the portal labels.
 
         // Ignore anything on the boundary - they don't need direct processing and this allows us to avoid the
         // boundary conditions in subsequent logic
-        if pos.row == 0 || pos.row == max_row_index || pos.col == 0 || pos.col == max_col_index {
+        if pos.y == 0 || pos.y == max_row_index || pos.x == 0 || pos.x == max_col_index {
             continue;
         }
 
-        let d_pos: Vec<(isize, isize)> = vec![(0, 1), (1, 0), (0, -1), (-1, 0)];
-        let mut empty_neighbour: Option<char> = None;
-        let mut empty_neighbour_direction = (0, 0);
+        let mut empty_neighbour: Option<Direction> = None;
         let mut label_neighbour: Option<char> = None;
-        for (d_row, d_col) in d_pos {
-            let c = ascii_maze
-                .get(&Position::new(pos.row + d_row, pos.col + d_col))
-                .unwrap();
-            if *c == '.' {
-                empty_neighbour = Some(*c);
-                empty_neighbour_direction = (d_row, d_col);
+        for direction in Direction::ALL {
+            let c = ascii_maze[pos.step(direction)];
+            if c == '.' {
+                empty_neighbour = Some(direction);
             }
-
-            if let Some(c) = labels.get(&Position::new(pos.row + d_row, pos.col + d_col)) {
-                label_neighbour = Some(*c);
+            if c.is_ascii_uppercase() {
+                label_neighbour = Some(c);
             }
         }
 
-        if empty_neighbour.is_some() {
-            assert!(label_neighbour.is_some());
-            let full_label: String = match empty_neighbour_direction {
+        if let Some(direction) = empty_neighbour {
+            let label_neighbour = label_neighbour.expect("Portal label with only one letter");
+            let full_label: String = match direction {
                 //empty neighbour to right or below so label is neighbour label + label
-                (0, 1) | (1, 0) => [label_neighbour.unwrap(), *label]
-                    .iter()
-                    .collect::<String>(),
-                //empty neighbour to left or above so label is neighbour label + label
-                (0, -1) | (-1, 0) => [*label, label_neighbour.unwrap()]
-                    .iter()
-                    .collect::<String>(),
-                _ => unreachable!(),
+                Direction::Right | Direction::Down => [label_neighbour, *label].iter().collect(),
+                //empty neighbour to left or above so label is label + neighbour label
+                Direction::Left | Direction::Up => [*label, label_neighbour].iter().collect(),
             };
 
+            let portal = pos.step(direction);
             portal_map
                 .entry(full_label.clone())
-                .and_modify(|v| {
-                    v.push(Position::new(
-                        pos.row + empty_neighbour_direction.0,
-                        pos.col + empty_neighbour_direction.1,
-                    ))
-                })
-                .or_insert_with(|| {
-                    vec![Position::new(
-                        pos.row + empty_neighbour_direction.0,
-                        pos.col + empty_neighbour_direction.1,
-                    )]
-                });
-
-            reverse_portal_map.insert(
-                Position::new(
-                    pos.row + empty_neighbour_direction.0,
-                    pos.col + empty_neighbour_direction.1,
-                ),
-                full_label,
-            );
+                .or_default()
+                .push(portal);
+            reverse_portal_map.insert(portal, full_label);
         };
     }
 
-    let mut entrance_position = Position::new(0, 0);
+    let mut entrance_position = Point::ORIGIN;
     //Finally, construct the maze
-    for (position, c) in &ascii_maze {
-        match reverse_portal_map.get(position) {
-            Some(label) => {
-                let node_type = match label.as_str() {
-                    "AA" => {
-                        entrance_position = *position;
-                        NodeType::Entrance
-                    }
-                    "ZZ" => NodeType::Exit,
-                    _ => NodeType::Portal(label.clone()),
-                };
-                let mut portal_partner = None; //None is relevant for entry/exit.
-                for pos in portal_map
-                    .get(label)
-                    .expect("Key unexpectedly missing from portal map")
-                {
-                    if *pos != *position {
-                        portal_partner = Some(*pos);
-                    }
+    let maze = ascii_maze.map(|position, c| match reverse_portal_map.get(&position) {
+        Some(label) => {
+            let node_type = match label.as_str() {
+                "AA" => {
+                    entrance_position = position;
+                    NodeType::Entrance
                 }
-                maze.insert(*position, Node::new(node_type, portal_partner));
-            }
-            None => {
-                maze.insert(*position, Node::new(NodeType::from(*c), None));
-            }
+                "ZZ" => NodeType::Exit,
+                _ => NodeType::Portal(label.clone()),
+            };
+            //None is relevant for entry/exit.
+            let portal_partner = portal_map
+                .get(label)
+                .expect("Key unexpectedly missing from portal map")
+                .iter()
+                .find(|pos| **pos != position)
+                .copied();
+            Node::new(node_type, portal_partner)
         }
-    }
+        None => Node::new(NodeType::from(*c), None),
+    });
+
+    (ascii_maze, maze, entrance_position)
+}
 
-    (
-        ascii_maze,
-        maze,
-        entrance_position,
-        max_row_index,
-        max_col_index,
-    )
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_to_exit() {
+        let input = [
+            "         A           ",
+            "         A           ",
+            "  #######.#########  ",
+            "  #######.........#  ",
+            "  #######.#######.#  ",
+            "  #######.#######.#  ",
+            "  #######.#######.#  ",
+            "  #####  B    ###.#  ",
+            "BC...##  C    ###.#  ",
+            "  ##.##       ###.#  ",
+            "  ##...DE  F  ###.#  ",
+            "  #####    G  ###.#  ",
+            "  #########.#####.#  ",
+            "DE..#######...###.#  ",
+            "  #.#########.###.#  ",
+            "FG..#########.....#  ",
+            "  ###########.#####  ",
+            "             Z       ",
+            "             Z       ",
+        ]
+        .join("\n");
+        let (_, maze, entrance) = parse_input(&input);
+        let entrance = HyperPosition::new(entrance, 0);
+
+        let (length, path) = path_to_exit(&maze, &entrance, false).unwrap();
+        assert_eq!(23, length);
+        assert_eq!(24, path.len());
+        assert_eq!(entrance, path[0]);
+        assert_eq!(NodeType::Exit, maze[path[23].position].node_type);
+
+        //Recursively, going through a portal doesn't help - it's the long way round.
+        let (length, path) = path_to_exit(&maze, &entrance, true).unwrap();
+        assert_eq!(26, length);
+        assert!(path.iter().all(|position| position.level == 0));
+    }
 }
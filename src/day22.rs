@@ -1,3 +1,4 @@
+use crate::math;
 use std::fs;
 
 //Constants for part 2
@@ -148,7 +149,8 @@ pub fn day22() -> (usize, usize) {
     //
     // There's probably a library for this but I'm trying to learn more about modular arithmetic so will implement euclid's extended
     // algorithm to calculate the modular inverse. See here: https://en.wikipedia.org/wiki/Modular_multiplicative_inverse
-    let inv_increments = modular_inverse(increments, N);
+    let inv_increments =
+        math::mod_inverse(increments, &N).expect("Increment must be invertible mod N");
 
     //C^-1 I^-1 acting M times on index 2020 will give us the original index it came from. We can read that off
     //to get the answer (x is inv_increments, y is cuts). After some scribbling
@@ -161,8 +163,8 @@ pub fn day22() -> (usize, usize) {
     // To calculate x^M mod N, use exponentiation in powers of 2 to calculate (see https://codeforces.com/blog/entry/72527)
     // Let's use the notation above for, ahem, clarity
     let x = inv_increments;
-    let x_min_1_inv = modular_inverse(x - 1, N);
-    let x_to_m_mod_n = pow_mod(x, M, N); //Yes, M and N are swapped.
+    let x_min_1_inv = math::mod_inverse(x - 1, &N).expect("x - 1 must be invertible mod N");
+    let x_to_m_mod_n = math::pow_mod(x, M, &N); //Yes, M and N are swapped.
     let i: i128 = 2020;
     let y = cuts;
     //Fingers crossed
@@ -188,46 +190,6 @@ pub fn day22() -> (usize, usize) {
     (part1, part2 as usize)
 }
 
-//Use iterative version of the algorithm to calculate x^n mod m
-fn pow_mod(x: i128, n: i128, m: i128) -> i128 {
-    let mut y: i128 = 1;
-    let mut x = x;
-    let mut n = n;
-    while n > 0 {
-        if n % 2 != 0 {
-            //Odd
-            y = (y * x) % m;
-        }
-        n /= 2;
-        x = (x * x) % m;
-    }
-
-    y
-}
-
-// Calculate the modular multiplicative inverse of a mod N. Use Extended Euclid algorithm to get Bezout t coefficient.
-// See here for theory - https://en.wikipedia.org/wiki/Extended_Euclidean_algorithm
-fn modular_inverse(a: i128, n: i128) -> i128 {
-    let mut old_t: i128 = 0;
-    let mut t: i128 = 1;
-    let mut old_r = n;
-    let mut r = a;
-
-    //Extended Euclidean algorithm, but without bothering with s.
-    while r > 0 {
-        let q = old_r / r;
-        (old_r, r) = (r, old_r - q * r); //r_i+1 = r_i-1 - q_i r_i. q_i is euclidean division of old_r / r.
-        (old_t, t) = (t, old_t - q * t); //t_i+1 = t_i-1 - q_i t_i
-    }
-
-    // The Bezout coefficient we want is old_t. It might be negative in which case we'll return N-old_t
-    if old_t > 0 {
-        old_t
-    } else {
-        n + old_t
-    }
-}
-
 fn parse_into_techniques(input: &str) -> Vec<Technique> {
     input
         .lines()
@@ -1,14 +1,19 @@
 use crate::intcode;
 use crate::utils;
-use std::collections::VecDeque;
-use std::panic;
+use std::any::Any;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::fs;
+use std::str::FromStr;
 use std::thread::JoinHandle;
 use std::time;
 use std::{sync::mpsc, thread};
 
-const NETWORK_SIZE: usize = 50;
 const INVALID_NETWORK_ADDRESS: isize = -1;
-const NAT_ADDRESS: isize = 255;
+
+//Optional network configuration. If this file exists it overrides the default (puzzle) network - see NetworkConfig::from_str
+//for the format.
+const NETWORK_CONFIG_FILE: &str = "input/day23_network.txt";
 
 //Due to the multi-threaded nature of the solution, we need to tune when the computers consider themselves idle and how
 //fast their polling loop is for new messages.
@@ -20,6 +25,247 @@ const MAX_IDLE_COUNTER: usize = 3; //Number of worker loops with empty input/out
 const STATUS_KICK_TIMER: u128 = 10; //milliseconds between status heartbeats
 const POLL_INTERVAL: u64 = 1; //Poll for new messages ever millisecond.
 
+//Some configs never get a packet to the nat, e.g. if every packet to it is dropped. The nat waits this many idle spells
+//for one to arrive before giving up on the network.
+const MAX_IDLE_WITHOUT_PACKET: usize = 100;
+
+// Describes the shape of the network and how the nat and the links between computers behave. The default is the
+// network from the puzzle: 50 computers, nat on 255, nat resending the last packet to computer 0 when the network
+// goes idle, and perfect links.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetworkConfig {
+    pub size: usize,             //Number of computers - addresses 0..size
+    pub nat_address: isize,      //Packets sent here go to the nat
+    pub idle_policy: IdlePolicy, //What the nat does when all computers are idle
+    pub unknown_address_policy: UnknownAddressPolicy, //What happens to packets addressed to nobody
+    pub link_rules: Vec<LinkRule>, //First matching rule applies to each packet
+}
+
+impl Default for NetworkConfig {
+    fn default() -> NetworkConfig {
+        NetworkConfig {
+            size: 50,
+            nat_address: 255,
+            idle_policy: IdlePolicy::ResendLast { destination: 0 },
+            unknown_address_policy: UnknownAddressPolicy::Panic,
+            link_rules: vec![],
+        }
+    }
+}
+
+impl NetworkConfig {
+    // Checks the settings make sense together, as they can come in any order.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.is_computer(self.nat_address) {
+            return Err(format!(
+                "Nat address {} clashes with a computer address (network size {})",
+                self.nat_address, self.size
+            ));
+        }
+        if let IdlePolicy::ResendLast { destination } = self.idle_policy {
+            if !self.is_computer(destination) {
+                return Err(format!(
+                    "Idle resend destination {} isn't a computer (network size {})",
+                    destination, self.size
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn is_computer(&self, address: isize) -> bool {
+        address >= 0 && (address as usize) < self.size
+    }
+
+    // Decides where a packet sent by source should go, taking into account the nat, unknown addresses and any link rules.
+    // packet_count is the number of packets previously sent by source that matched the same link rule, and is used for
+    // the drop_every rule.
+    fn route(&self, source: isize, packet: &Packet, packet_count: usize) -> Route {
+        if let Some(rule) = self.link_rule(source, packet.address) {
+            if rule.drop_every > 0 && (packet_count + 1).is_multiple_of(rule.drop_every) {
+                return Route::Drop;
+            }
+        }
+
+        if packet.address == self.nat_address {
+            Route::Nat
+        } else if self.is_computer(packet.address) {
+            Route::Computer(packet.address as usize)
+        } else {
+            match self.unknown_address_policy {
+                UnknownAddressPolicy::Panic => {
+                    panic!("Packet sent to unknown address: {:?}", packet)
+                }
+                UnknownAddressPolicy::Drop => Route::Drop,
+                UnknownAddressPolicy::ForwardToNat => Route::Nat,
+            }
+        }
+    }
+
+    // Returns the index of the first link rule matching source -> destination.
+    fn link_rule_index(&self, source: isize, destination: isize) -> Option<usize> {
+        self.link_rules
+            .iter()
+            .position(|rule| rule.matches(source, destination))
+    }
+
+    fn link_rule(&self, source: isize, destination: isize) -> Option<&LinkRule> {
+        self.link_rule_index(source, destination)
+            .map(|index| &self.link_rules[index])
+    }
+}
+
+// One config setting per line, as key=value. Blank lines and lines starting with # are ignored. Anything not mentioned
+// keeps its default value, e.g.:
+//
+// size=10
+// nat_address=255
+// idle=resend 3        (or idle=halt)
+// unknown=drop         (or unknown=panic, unknown=nat)
+// link=* 0 drop_every=2 delay_ms=5
+//
+// Link rules take a source and a destination address, either of which may be * for any address, followed by optional
+// drop_every and delay_ms settings.
+impl FromStr for NetworkConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = NetworkConfig::default();
+        for (line_number, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| {
+                format!(
+                    "Line {}: expected key=value, got '{}'",
+                    line_number + 1,
+                    line
+                )
+            })?;
+            let value = value.trim();
+            let error = |err: String| format!("Line {}: {}", line_number + 1, err);
+            match key.trim() {
+                "size" => config.size = parse_setting(value).map_err(error)?,
+                "nat_address" => config.nat_address = parse_setting(value).map_err(error)?,
+                "idle" => config.idle_policy = value.parse().map_err(error)?,
+                "unknown" => config.unknown_address_policy = value.parse().map_err(error)?,
+                "link" => config.link_rules.push(value.parse().map_err(error)?),
+                other => return Err(error(format!("Unrecognized setting '{}'", other))),
+            }
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+fn parse_setting<T: FromStr>(value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Bad value '{}'", value))
+}
+
+// What the nat does once every computer in the network has gone idle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdlePolicy {
+    //Resend the last packet received to destination. The network is finished once the same y is sent twice in a row.
+    ResendLast { destination: isize },
+    //Finish as soon as the network goes idle, reporting the last y received.
+    Halt,
+}
+
+impl FromStr for IdlePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some("resend"), None, None) => Ok(IdlePolicy::ResendLast { destination: 0 }),
+            (Some("resend"), Some(destination), None) => Ok(IdlePolicy::ResendLast {
+                destination: parse_setting(destination)?,
+            }),
+            (Some("halt"), None, None) => Ok(IdlePolicy::Halt),
+            _ => Err(format!("Unrecognized idle policy '{}'", s)),
+        }
+    }
+}
+
+// What happens to a packet sent to an address that is neither a computer nor the nat.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnknownAddressPolicy {
+    Panic,
+    Drop,
+    ForwardToNat,
+}
+
+impl FromStr for UnknownAddressPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "panic" => Ok(UnknownAddressPolicy::Panic),
+            "drop" => Ok(UnknownAddressPolicy::Drop),
+            "nat" => Ok(UnknownAddressPolicy::ForwardToNat),
+            _ => Err(format!("Unrecognized unknown address policy '{}'", s)),
+        }
+    }
+}
+
+// Failure rule for packets travelling from source to destination (None matches any address).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LinkRule {
+    pub source: Option<isize>,
+    pub destination: Option<isize>,
+    pub drop_every: usize, //Drop every nth packet on this link. 0 means never drop.
+    pub delay_ms: u64,     //Hold packets back this long before delivering them.
+}
+
+impl LinkRule {
+    fn matches(&self, source: isize, destination: isize) -> bool {
+        self.source.is_none_or(|s| s == source) && self.destination.is_none_or(|d| d == destination)
+    }
+}
+
+impl FromStr for LinkRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let mut address = |name: &str| -> Result<Option<isize>, String> {
+            match words.next() {
+                Some("*") => Ok(None),
+                Some(address) => parse_setting(address).map(Some),
+                None => Err(format!("Link rule missing {} address", name)),
+            }
+        };
+        let source = address("source")?;
+        let destination = address("destination")?;
+        let mut rule = LinkRule {
+            source,
+            destination,
+            drop_every: 0,
+            delay_ms: 0,
+        };
+
+        for setting in words {
+            match setting.split_once('=') {
+                Some(("drop_every", value)) => rule.drop_every = parse_setting(value)?,
+                Some(("delay_ms", value)) => rule.delay_ms = parse_setting(value)?,
+                _ => return Err(format!("Unrecognized link setting '{}'", setting)),
+            }
+        }
+
+        Ok(rule)
+    }
+}
+
+// Where a packet ends up after the config has been applied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Route {
+    Nat,
+    Computer(usize),
+    Drop,
+}
+
 // Represents a computer in the network.
 #[derive(Debug)]
 struct Computer {
@@ -31,6 +277,10 @@ struct Computer {
     packet_senders: Vec<mpsc::Sender<Packet>>, //Vec of send channels indexed by network address (array would be better but it's a ballache)
     status_sender: mpsc::Sender<StatusMessage>, //Send status - is this computer idle or not
     status: Status,
+    config: NetworkConfig,
+    link_packet_counts: Vec<usize>, //Packets sent so far on each link rule, indexed like config.link_rules
+    delayed_packets: BinaryHeap<Reverse<(time::Instant, usize, Packet)>>, //Packets held back by a link rule, soonest due first (ties in send order)
+    packets_delayed: usize, //Number of packets ever delayed - breaks ties between packets due at the same time
 }
 
 impl Computer {
@@ -41,6 +291,7 @@ impl Computer {
         packet_receiver: mpsc::Receiver<Packet>,
         packet_senders: &[mpsc::Sender<Packet>], //Only pass a reference - the constructor takes care of cloning it.
         status_sender: mpsc::Sender<StatusMessage>,
+        config: &NetworkConfig,
     ) -> Computer {
         Computer {
             network_address: INVALID_NETWORK_ADDRESS,
@@ -51,6 +302,10 @@ impl Computer {
             packet_senders: packet_senders.to_owned(), // This clones both the array and the underlying Senders.
             status_sender,
             status: Status::Active,
+            config: config.clone(),
+            link_packet_counts: vec![0; config.link_rules.len()],
+            delayed_packets: BinaryHeap::new(),
+            packets_delayed: 0,
         }
     }
 
@@ -136,24 +391,24 @@ impl Computer {
                             outputs.pop_front().expect("Y output unexpectedly absent"),
                         );
 
-                        if packet.address == NAT_ADDRESS {
-                            //Packet 255!
-                            self.packet_255_sender.send(packet).unwrap_or_else(|err| {
-                                eprintln!("Failed to send packet to nat. Packet: {:?}, Channel: {:?}, Error: {}", packet, self.packet_255_sender, err)
-                            });
+                        let delay_ms = self.packet_delay(&packet);
+                        if delay_ms > 0 {
+                            self.delay_packet(
+                                packet,
+                                time::Instant::now() + time::Duration::from_millis(delay_ms),
+                            );
                         } else {
-                            self.packet_senders[packet.address as usize]
-                                .send(packet)
-                                .unwrap_or_else(|err| {
-                                    eprintln!("Failed to send packet to computer. Packet: {:?}, Channel: {:?}, Error: {}", packet, self.packet_255_sender, err)
-                            });
+                            self.deliver_packet(packet);
                         }
                     }
                 }
                 intcode::ProgramResult::Halted => panic!("Program unexpectedly halted"), //I don't think this should ever happen - always expecting the program to be awaiting input.
             }
 
-            if empty_input_queue && no_outputs {
+            self.deliver_due_packets(time::Instant::now());
+
+            //Computers with packets still in flight aren't idle - the network isn't quiet yet.
+            if empty_input_queue && no_outputs && self.delayed_packets.is_empty() {
                 //Send idle status if this condition has persisted for a while
                 idle_counter += 1;
 
@@ -190,6 +445,69 @@ impl Computer {
             thread::sleep(time::Duration::from_millis(POLL_INTERVAL));
         }
     }
+
+    //Holds a packet back until it's due.
+    fn delay_packet(&mut self, packet: Packet, due: time::Instant) {
+        self.delayed_packets
+            .push(Reverse((due, self.packets_delayed, packet)));
+        self.packets_delayed += 1;
+    }
+
+    //Delivers anything held back by a link rule whose time has come, soonest first.
+    fn deliver_due_packets(&mut self, now: time::Instant) {
+        while let Some(&Reverse((due, _, packet))) = self.delayed_packets.peek() {
+            if due > now {
+                break;
+            }
+            self.delayed_packets.pop();
+            self.deliver_packet(packet);
+        }
+    }
+
+    //Returns the delay of the first link rule the packet matches (0 if none).
+    fn packet_delay(&self, packet: &Packet) -> u64 {
+        match self
+            .config
+            .link_rule_index(self.network_address, packet.address)
+        {
+            Some(index) => self.config.link_rules[index].delay_ms,
+            None => 0,
+        }
+    }
+
+    //Sends the packet wherever the network config says it should go.
+    fn deliver_packet(&mut self, packet: Packet) {
+        let rule_index = self
+            .config
+            .link_rule_index(self.network_address, packet.address);
+        let packet_count = rule_index.map_or(0, |index| self.link_packet_counts[index]);
+        let route = self
+            .config
+            .route(self.network_address, &packet, packet_count);
+        if let Some(index) = rule_index {
+            self.link_packet_counts[index] += 1;
+        }
+
+        match route {
+            Route::Nat => {
+                //Packet 255!
+                self.packet_255_sender.send(packet).unwrap_or_else(|err| {
+                    eprintln!(
+                        "Failed to send packet to nat. Packet: {:?}, Channel: {:?}, Error: {}",
+                        packet, self.packet_255_sender, err
+                    )
+                });
+            }
+            Route::Computer(address) => {
+                self.packet_senders[address]
+                    .send(packet)
+                    .unwrap_or_else(|err| {
+                        eprintln!("Failed to send packet to computer. Packet: {:?}, Channel: {:?}, Error: {}", packet, self.packet_255_sender, err)
+                });
+            }
+            Route::Drop => (),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -197,10 +515,12 @@ struct Nat {
     instruction_senders: Vec<mpsc::Sender<Instruction>>,
     packet_255_receiver: mpsc::Receiver<Packet>,
     status_receiver: mpsc::Receiver<StatusMessage>,
-    idle_packet_sender: mpsc::Sender<Packet>,
-    network_status: [Status; NETWORK_SIZE],
+    idle_packet_sender: Option<mpsc::Sender<Packet>>, //Where to resend the last packet when idle, if anywhere.
+    idle_policy: IdlePolicy,
+    network_status: Vec<Status>,
     last_packet: Option<Packet>,
     last_y_sent: Option<isize>,
+    idle_without_packet: usize, //Times the network has gone idle before any packet reached the nat
 }
 
 impl Nat {
@@ -208,16 +528,20 @@ impl Nat {
         instruction_senders: Vec<mpsc::Sender<Instruction>>,
         packet_255_receiver: mpsc::Receiver<Packet>,
         status_receiver: mpsc::Receiver<StatusMessage>,
-        idle_packet_sender: mpsc::Sender<Packet>,
+        idle_packet_sender: Option<mpsc::Sender<Packet>>,
+        idle_policy: IdlePolicy,
     ) -> Nat {
+        let network_size = instruction_senders.len();
         Nat {
             instruction_senders,
             packet_255_receiver,
             status_receiver,
             idle_packet_sender,
-            network_status: [Status::Active; 50], //Assume active at first
+            idle_policy,
+            network_status: vec![Status::Active; network_size], //Assume active at first
             last_packet: None,
             last_y_sent: None,
+            idle_without_packet: 0,
         }
     }
 
@@ -229,11 +553,10 @@ impl Nat {
         }
     }
 
+    //A computer that's already stopped has dropped its receiver, but it doesn't need telling anyway.
     fn shutdown_network(&self) {
         for sender in &self.instruction_senders {
-            sender
-                .send(Instruction::Shutdown)
-                .unwrap_or_else(|err| panic!("Failed to send instruction: {err}"));
+            let _ = sender.send(Instruction::Shutdown);
         }
     }
 
@@ -248,35 +571,46 @@ impl Nat {
     }
 
     //Receives status updates from computers (Active if the last status message
-    //from any computer is Active; Idle if all computers are idle). Sends to address 0 if idle
-    fn receive_status_updates(&mut self) -> Option<isize> {
+    //from any computer is Active; Idle if all computers are idle). What happens when the network
+    //is idle depends on the idle policy - returns the final y once the nat decides the network is done. If the network
+    //is idle before any packet has reached the nat there's nothing to do yet, so it waits (but not forever).
+    fn receive_status_updates(&mut self) -> Result<Option<isize>, String> {
         for status_message in self.status_receiver.try_iter() {
             self.network_status[status_message.address as usize] = status_message.status;
         }
 
-        if !self
-            .network_status
-            .iter()
-            .any(|status| *status == Status::Active)
-        {
+        if !self.network_status.contains(&Status::Active) {
             //No computers are active. Overall network status is Idle
-            let last_packet = self
-                .last_packet
-                .expect("Should always have last packet to send when network idle");
-            if let Some(last_y) = self.last_y_sent {
-                if last_y == last_packet.y {
-                    //Sending same y again to computer 0
-                    return Some(last_y);
+            match (self.idle_policy, self.last_packet) {
+                (_, None) => {
+                    self.idle_without_packet += 1;
+                    if self.idle_without_packet >= MAX_IDLE_WITHOUT_PACKET {
+                        return Err(format!(
+                            "Network went idle {} times without a packet reaching the nat",
+                            self.idle_without_packet
+                        ));
+                    }
+                }
+                (IdlePolicy::ResendLast { .. }, Some(last_packet)) => {
+                    if let Some(last_y) = self.last_y_sent {
+                        if last_y == last_packet.y {
+                            //Sending same y again to the idle destination
+                            return Ok(Some(last_y));
+                        }
+                    }
+                    self.last_y_sent = Some(last_packet.y);
+                    if let Some(sender) = &self.idle_packet_sender {
+                        sender
+                            .send(last_packet)
+                            .unwrap_or_else(|err| panic!("Failed to send idle packet: {}", err));
+                    }
                 }
+                (IdlePolicy::Halt, Some(last_packet)) => return Ok(Some(last_packet.y)),
             }
-            self.last_y_sent = Some(last_packet.y);
-            self.idle_packet_sender
-                .send(last_packet)
-                .unwrap_or_else(|err| panic!("Failed to send idle packet: {}", err));
             //Reset idle status
-            self.network_status = [Status::Active; 50];
+            self.network_status.fill(Status::Active);
         }
-        None
+        Ok(None)
     }
 }
 
@@ -286,7 +620,7 @@ enum Instruction {
     Shutdown,    //Requests that the computer shut down.
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 struct Packet {
     address: isize,
     x: isize,
@@ -317,48 +651,90 @@ impl StatusMessage {
     }
 }
 
-pub fn day23() -> (usize, usize) {
+//Returns an error rather than answers if the network config is bad, or the network never gets a packet to the nat.
+pub fn day23() -> Result<(usize, usize), String> {
     let initial_state: Vec<isize> = utils::parse_input_by_sep("input/day23.txt", ',');
     let program = intcode::Program::new(&initial_state);
-    let (computers, mut nat) = construct_network(&program);
+    let config = match fs::read_to_string(NETWORK_CONFIG_FILE) {
+        Ok(contents) => contents
+            .parse()
+            .map_err(|err| format!("Bad network config in {NETWORK_CONFIG_FILE}: {err}"))?,
+        Err(_) => NetworkConfig::default(),
+    };
+    config.validate()?;
+    run_network(&program, &config)
+}
+
+//Runs the network until the nat is done. Every computer should keep running until it's told to shut down, so one
+//stopping early means its thread has panicked - that's an error rather than something to wait on forever.
+fn run_network(
+    program: &intcode::Program,
+    config: &NetworkConfig,
+) -> Result<(usize, usize), String> {
+    let (computers, mut nat) = construct_network(program, config);
     let computer_handles = boot_network(computers);
     nat.start_network();
     let mut part1_option = None;
-    let mut part2_option: Option<isize>;
 
-    loop {
+    let part2_result = loop {
         if let Some(y) = nat.receive_packet_255() {
             if part1_option.is_none() {
                 part1_option = Some(y);
             }
         }
 
-        part2_option = nat.receive_status_updates();
-        if part2_option.is_some() {
-            break;
+        match nat.receive_status_updates() {
+            Ok(None) => (),
+            finished => break finished,
+        }
+
+        if computer_handles.iter().any(|handle| handle.is_finished()) {
+            break Err("A computer stopped before the network was shut down".to_string());
         }
 
         thread::sleep(time::Duration::from_millis(1));
-    }
+    };
 
     nat.shutdown_network();
 
-    //Threads should all shut down.
-    for handle in computer_handles {
-        handle
-            .join()
-            .unwrap_or_else(|err| panic::resume_unwind(err));
+    //Threads should all shut down. Any that panicked are reported in place of the answer.
+    let failures: Vec<String> = computer_handles
+        .into_iter()
+        .enumerate()
+        .filter_map(|(address, handle)| {
+            handle
+                .join()
+                .err()
+                .map(|err| format!("Computer {address} panicked: {}", panic_message(&err)))
+        })
+        .collect();
+    if !failures.is_empty() {
+        return Err(failures.join("; "));
     }
 
-    (
-        part1_option.unwrap() as usize,
-        part2_option.unwrap() as usize,
-    )
+    //The nat only finishes once it's had a packet, so there's always a part 1 answer if there's a part 2 one.
+    let part2 = part2_result?.ok_or("Nat stopped without an answer")?;
+    let part1 = part1_option.ok_or("No packet reached the nat")?;
+    Ok((part1 as usize, part2 as usize))
+}
+
+//Panics are usually raised with a &str or a String - anything else just gets a placeholder.
+fn panic_message(payload: &Box<dyn Any + Send>) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
 }
 
 //Constructs Computer objects required by network giving each the resources it needs to talk to the others. Also constructs a "nat"
 //to be used for sending and receiving messages to/from each of the computers, monitoring queues, etc.
-fn construct_network(nic_program: &intcode::Program) -> (Vec<Computer>, Nat) {
+fn construct_network(
+    nic_program: &intcode::Program,
+    config: &NetworkConfig,
+) -> (Vec<Computer>, Nat) {
     //First construct the nat. We need 2 mpsc channels for sending Instructions and receiving the 255 packet.
     let mut instruction_senders: Vec<mpsc::Sender<Instruction>> = vec![];
     let mut instruction_receivers: VecDeque<mpsc::Receiver<Instruction>> = VecDeque::new();
@@ -366,7 +742,7 @@ fn construct_network(nic_program: &intcode::Program) -> (Vec<Computer>, Nat) {
     let mut packet_receivers: VecDeque<mpsc::Receiver<Packet>> = VecDeque::new();
 
     //Set up resources
-    for _ in 0..NETWORK_SIZE {
+    for _ in 0..config.size {
         let (instruction_sender, instruction_receiver) = mpsc::channel::<Instruction>();
         let (packet_sender, packet_receiver) = mpsc::channel::<Packet>();
         instruction_senders.push(instruction_sender);
@@ -377,18 +753,25 @@ fn construct_network(nic_program: &intcode::Program) -> (Vec<Computer>, Nat) {
     let (packet_255_sender, packet_255_receiver) = mpsc::channel::<Packet>();
     let (status_sender, status_receiver) = mpsc::channel::<StatusMessage>();
 
-    //Create the nat
+    //Create the nat. The config has been validated, so the idle destination is always a computer.
+    let idle_packet_sender = match config.idle_policy {
+        IdlePolicy::ResendLast { destination } => {
+            Some(packet_senders[destination as usize].clone())
+        }
+        IdlePolicy::Halt => None,
+    };
     let nat = Nat::new(
         instruction_senders,
         packet_255_receiver,
         status_receiver,
-        packet_senders[0].clone(),
+        idle_packet_sender,
+        config.idle_policy,
     );
 
     //Create the computers
     let mut computers: Vec<Computer> = vec![];
 
-    for _ in 0..NETWORK_SIZE {
+    for _ in 0..config.size {
         computers.push(Computer::new(
             nic_program.clone(),
             instruction_receivers
@@ -400,6 +783,7 @@ fn construct_network(nic_program: &intcode::Program) -> (Vec<Computer>, Nat) {
                 .unwrap_or_else(|| panic!("Failed to get packet receiver from queue")),
             &packet_senders,
             status_sender.clone(),
+            config,
         ));
     }
 
@@ -417,3 +801,176 @@ fn boot_network(computers: Vec<Computer>) -> Vec<JoinHandle<()>> {
 
     computer_join_handles
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config_matches_puzzle() {
+        let config: NetworkConfig = "".parse().unwrap();
+        assert_eq!(NetworkConfig::default(), config);
+        assert_eq!(Route::Nat, config.route(3, &Packet::new(255, 1, 2), 0));
+        assert_eq!(
+            Route::Computer(49),
+            config.route(3, &Packet::new(49, 1, 2), 0)
+        );
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let config: NetworkConfig = "# A small, lossy network
+            size=4
+            nat_address=100
+            idle=resend 2
+            unknown=drop
+            link=1 * drop_every=2
+            link=* 3 delay_ms=5"
+            .parse()
+            .unwrap();
+        assert_eq!(4, config.size);
+        assert_eq!(100, config.nat_address);
+        assert_eq!(
+            IdlePolicy::ResendLast { destination: 2 },
+            config.idle_policy
+        );
+        assert_eq!(UnknownAddressPolicy::Drop, config.unknown_address_policy);
+        assert_eq!(
+            vec![
+                LinkRule {
+                    source: Some(1),
+                    destination: None,
+                    drop_every: 2,
+                    delay_ms: 0
+                },
+                LinkRule {
+                    source: None,
+                    destination: Some(3),
+                    drop_every: 0,
+                    delay_ms: 5
+                }
+            ],
+            config.link_rules
+        );
+
+        assert!("size=4\nnat_address=2".parse::<NetworkConfig>().is_err());
+        assert!("idle=sometimes".parse::<NetworkConfig>().is_err());
+        assert!("link=1".parse::<NetworkConfig>().is_err());
+        assert!("colour=blue".parse::<NetworkConfig>().is_err());
+
+        //The nat can only resend to a computer, wherever the size is set.
+        assert!("idle=resend 4\nsize=4".parse::<NetworkConfig>().is_err());
+        assert!("idle=resend -1".parse::<NetworkConfig>().is_err());
+        assert!("idle=resend 255".parse::<NetworkConfig>().is_err());
+        assert!("idle=resend 3\nsize=4".parse::<NetworkConfig>().is_ok());
+    }
+
+    //A nat for a single computer, with the channels to send it packets and statuses.
+    fn single_computer_nat(
+        idle_policy: IdlePolicy,
+    ) -> (Nat, mpsc::Sender<Packet>, mpsc::Sender<StatusMessage>) {
+        let (instruction_sender, _) = mpsc::channel();
+        let (packet_255_sender, packet_255_receiver) = mpsc::channel();
+        let (status_sender, status_receiver) = mpsc::channel();
+        let nat = Nat::new(
+            vec![instruction_sender],
+            packet_255_receiver,
+            status_receiver,
+            None,
+            idle_policy,
+        );
+        (nat, packet_255_sender, status_sender)
+    }
+
+    #[test]
+    fn test_nat_waits_for_a_packet() {
+        let (mut nat, packet_255_sender, status_sender) = single_computer_nat(IdlePolicy::Halt);
+        let idle = || {
+            status_sender
+                .send(StatusMessage::new(0, Status::Idle))
+                .unwrap()
+        };
+
+        //Idle with nothing at the nat yet - keep waiting.
+        idle();
+        assert_eq!(Ok(None), nat.receive_status_updates());
+
+        //Once a packet's arrived, the next idle spell finishes.
+        packet_255_sender.send(Packet::new(255, 1, 2)).unwrap();
+        assert_eq!(Some(2), nat.receive_packet_255());
+        idle();
+        assert_eq!(Ok(Some(2)), nat.receive_status_updates());
+
+        //But it gives up if nothing ever arrives.
+        let (mut nat, _packet_255_sender, status_sender) =
+            single_computer_nat(IdlePolicy::ResendLast { destination: 0 });
+        for _ in 1..MAX_IDLE_WITHOUT_PACKET {
+            status_sender
+                .send(StatusMessage::new(0, Status::Idle))
+                .unwrap();
+            assert_eq!(Ok(None), nat.receive_status_updates());
+        }
+        status_sender
+            .send(StatusMessage::new(0, Status::Idle))
+            .unwrap();
+        assert!(nat.receive_status_updates().is_err());
+    }
+
+    #[test]
+    fn test_delayed_packets_released_in_due_order() {
+        let config: NetworkConfig = "size=1".parse().unwrap();
+        let (mut computers, mut nat) = construct_network(&intcode::Program::new(&[99]), &config);
+        let computer = &mut computers[0];
+        let now = time::Instant::now();
+        let later = now + time::Duration::from_millis(20);
+
+        //A long delay followed by a short one - the short one shouldn't wait behind it.
+        computer.delay_packet(Packet::new(255, 0, 1), later);
+        computer.delay_packet(Packet::new(255, 0, 2), now);
+        computer.delay_packet(Packet::new(255, 0, 3), now);
+        computer.deliver_due_packets(now);
+        assert_eq!(Some(3), nat.receive_packet_255());
+        assert_eq!(Some(Packet::new(255, 0, 3)), nat.last_packet);
+
+        computer.deliver_due_packets(later);
+        assert_eq!(Some(1), nat.receive_packet_255());
+        assert!(computer.delayed_packets.is_empty());
+    }
+
+    #[test]
+    fn test_computer_panic_is_an_error() {
+        //Reads its address, sends a packet to nobody (which panics under the default policy) then polls forever.
+        let nic = intcode::Program::new(&[3, 100, 104, 7, 104, 1, 104, 2, 3, 101, 1105, 1, 8]);
+        let config: NetworkConfig = "size=1".parse().unwrap();
+        let err = run_network(&nic, &config).unwrap_err();
+        assert!(err.contains("Computer 0 panicked"), "{err}");
+    }
+
+    #[test]
+    fn test_routing() {
+        let config: NetworkConfig = "size=4
+            unknown=nat
+            link=1 * drop_every=2"
+            .parse()
+            .unwrap();
+
+        //Unknown addresses go to the nat
+        assert_eq!(Route::Nat, config.route(0, &Packet::new(17, 1, 2), 0));
+        assert_eq!(Route::Nat, config.route(0, &Packet::new(-3, 1, 2), 0));
+
+        //Every second packet from computer 1 is dropped
+        assert_eq!(
+            Route::Computer(2),
+            config.route(1, &Packet::new(2, 1, 2), 0)
+        );
+        assert_eq!(Route::Drop, config.route(1, &Packet::new(2, 1, 2), 1));
+        assert_eq!(Route::Nat, config.route(1, &Packet::new(255, 1, 2), 2));
+        assert_eq!(Route::Drop, config.route(1, &Packet::new(0, 1, 2), 3));
+
+        //Other links are unaffected
+        assert_eq!(
+            Route::Computer(2),
+            config.route(0, &Packet::new(2, 1, 2), 1)
+        );
+    }
+}
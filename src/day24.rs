@@ -1,10 +1,12 @@
-use std::{
-    collections::{HashMap, HashSet},
-    fs,
-};
+use crate::animation;
+use crate::cycle;
+use crate::grid::{Grid, Point};
+use crate::image::{self, Colour, Image};
+use std::{collections::HashMap, fs, time::Duration};
 
 const NUM_ROWS: usize = 5;
 const NUM_COLS: usize = 5;
+const CENTRE: Point = Point { x: 2, y: 2 }; //Where the next level down is
 const INNER_INDICES: [usize; 4] = [7, 11, 13, 17]; //when eris is flattened, the 4 inner indices affected by bugs on the level above
 const OUTER_INDICES: [usize; 16] = [0, 1, 2, 3, 4, 5, 9, 10, 14, 15, 19, 20, 21, 22, 23, 24]; // the 16 outer indices affected by bugs on the level below.
 
@@ -36,19 +38,15 @@ impl From<Tile> for char {
 
 pub fn day24() -> (usize, usize) {
     let input = fs::read_to_string("input/day24.txt").expect("Could not open file");
-    let mut eris = parse_input(&input);
-    let mut hyper_eris: HashMap<isize, Vec<Vec<Tile>>> = HashMap::new(); //Map of depth to eris
+    let eris = parse_input(&input);
+    let mut hyper_eris: HashMap<isize, Grid<Tile>> = HashMap::new(); //Map of depth to eris
 
-    let mut seen: HashSet<Vec<Vec<Tile>>> = HashSet::new();
+    //First layout to appear twice is the one at the start of the cycle
+    let first_repeat = first_repeated_layout(&eris);
+    let part1 = biodiversity_rating(&first_repeat);
+    image::visualise("day24", || eris_image(&first_repeat).scaled(20));
+    record_evolution(&eris, &first_repeat);
 
-    while !seen.contains(&eris) {
-        seen.insert(eris.clone());
-        eris = evolve_one_minute(&eris);
-    }
-
-    let part1 = biodiversity_rating(&eris);
-
-    eris = parse_input(&input);
     hyper_eris.insert(0, eris);
 
     for _ in 0..200 {
@@ -60,47 +58,72 @@ pub fn day24() -> (usize, usize) {
     (part1, part2)
 }
 
-fn count_bugs(hyper_eris: &HashMap<isize, Vec<Vec<Tile>>>) -> usize {
+fn first_repeated_layout(eris: &Grid<Tile>) -> Grid<Tile> {
+    let cycle = cycle::find_cycle(eris.clone(), evolve_one_minute);
+    cycle::state_after(eris.clone(), evolve_one_minute, cycle.start, &cycle)
+}
+
+fn eris_image(eris: &Grid<Tile>) -> Image {
+    Image::from_grid(eris, |tile| match tile {
+        Tile::Empty => Colour::BLACK,
+        Tile::Bug => Colour::GREEN,
+    })
+}
+
+//A frame a minute from the start until the first repeated layout comes round for the second time.
+fn record_evolution(eris: &Grid<Tile>, first_repeat: &Grid<Tile>) {
+    let mut recorder = animation::recorder("day24", Duration::from_millis(200), 20);
+    if !recorder.is_recording() {
+        return;
+    }
+
+    let mut layout = eris.clone();
+    let mut repeats = 0;
+    while repeats < 2 {
+        recorder.frame(|| eris_image(&layout));
+        if layout == *first_repeat {
+            repeats += 1;
+        }
+        layout = evolve_one_minute(&layout);
+    }
+    recorder.finish();
+}
+
+fn count_bugs(hyper_eris: &HashMap<isize, Grid<Tile>>) -> usize {
     hyper_eris
-        .iter()
-        .map(|(_, eris)| {
-            eris.iter()
-                .flatten()
-                .filter(|tile| **tile == Tile::Bug)
-                .count()
-        })
+        .values()
+        .map(|eris| eris.values().filter(|tile| **tile == Tile::Bug).count())
         .sum()
 }
 
-fn biodiversity_rating(eris: &[Vec<Tile>]) -> usize {
-    eris.iter()
-        .flatten()
+fn biodiversity_rating(eris: &Grid<Tile>) -> usize {
+    eris.values()
         .enumerate()
         .filter(|(_, tile)| **tile == Tile::Bug)
         .map(|(index, _)| 2_usize.pow(index as u32))
         .sum()
 }
 
-fn parse_input(input: &str) -> Vec<Vec<Tile>> {
+fn parse_input(input: &str) -> Grid<Tile> {
     input
-        .lines()
-        .map(|line| {
-            line.chars()
-                .map(|c| {
-                    Tile::try_from(c).unwrap_or_else(|err| {
-                        panic!("Could not extract Tile from character: {}", err)
-                    })
-                })
-                .collect::<Vec<Tile>>()
-        })
-        .collect::<Vec<Vec<Tile>>>()
+        .parse()
+        .unwrap_or_else(|err| panic!("Could not extract Tiles from input: {}", err))
+}
+
+// What a tile becomes, given how many bugs are next to it.
+fn next_tile(tile: Tile, num_adjacent_bugs: usize) -> Tile {
+    match tile {
+        Tile::Empty if num_adjacent_bugs == 1 || num_adjacent_bugs == 2 => Tile::Bug,
+        Tile::Bug if num_adjacent_bugs == 1 => Tile::Bug,
+        _ => Tile::Empty,
+    }
 }
 
 //Pedestrian because I'm tired - this code totally sucks
 fn evolve_one_minute_recursive(
-    hyper_eris: &HashMap<isize, Vec<Vec<Tile>>>,
-) -> HashMap<isize, Vec<Vec<Tile>>> {
-    let mut new_hyper_eris: HashMap<isize, Vec<Vec<Tile>>> = HashMap::new();
+    hyper_eris: &HashMap<isize, Grid<Tile>>,
+) -> HashMap<isize, Grid<Tile>> {
+    let mut new_hyper_eris: HashMap<isize, Grid<Tile>> = HashMap::new();
 
     let mut new_levels: Vec<isize> = vec![];
     for (depth, eris) in hyper_eris {
@@ -111,7 +134,6 @@ fn evolve_one_minute_recursive(
         // - There is no existing level above and there is more than one bug on the inner rim of this level (middle 3x3 square excluding the
         //   center at 2,2).
         // We'll just note this just now, and create the new levels later - the existing tiles don't depend on these
-        let mut new_eris: Vec<Vec<Tile>> = vec![];
         if *depth == *hyper_eris.keys().min().unwrap() && bug_on_rim(eris, &OUTER_INDICES) {
             new_levels.push(*depth - 1);
         }
@@ -120,31 +142,14 @@ fn evolve_one_minute_recursive(
             new_levels.push(*depth + 1);
         }
 
-        for (row, tiles) in eris.iter().enumerate() {
-            let mut row_vector = vec![];
-            for (col, _) in tiles.iter().enumerate() {
-                let num_adjacent_bugs = number_adjacent_bugs(hyper_eris, eris, row, col, *depth);
-                match eris[row][col] {
-                    Tile::Empty => {
-                        if (num_adjacent_bugs == 1 || num_adjacent_bugs == 2)
-                            && (row != 2 || col != 2)
-                        {
-                            row_vector.push(Tile::Bug);
-                        } else {
-                            row_vector.push(Tile::Empty);
-                        }
-                    }
-                    Tile::Bug => {
-                        if num_adjacent_bugs == 1 {
-                            row_vector.push(Tile::Bug);
-                        } else {
-                            row_vector.push(Tile::Empty);
-                        }
-                    }
-                }
+        let new_eris = eris.map(|point, tile| {
+            if point == CENTRE {
+                //Never any bugs here - it's the next level down
+                Tile::Empty
+            } else {
+                next_tile(*tile, number_adjacent_bugs(hyper_eris, eris, point, *depth))
             }
-            new_eris.push(row_vector);
-        }
+        });
 
         new_hyper_eris.insert(*depth, new_eris);
     }
@@ -164,163 +169,94 @@ fn evolve_one_minute_recursive(
 }
 
 fn add_new_level(
-    hyper_eris: &HashMap<isize, Vec<Vec<Tile>>>,
-    new_hyper_eris: &mut HashMap<isize, Vec<Vec<Tile>>>,
+    hyper_eris: &HashMap<isize, Grid<Tile>>,
+    new_hyper_eris: &mut HashMap<isize, Grid<Tile>>,
     level: isize,
     indices: &[usize],
 ) {
-    let mut new_eris: Vec<Vec<Tile>> = vec![];
     //Initialize to empty
-    for _ in 0..NUM_ROWS {
-        let mut row_vector = vec![];
-        for _ in 0..NUM_COLS {
-            row_vector.push(Tile::Empty);
-        }
-        new_eris.push(row_vector);
-    }
-
+    let mut new_eris = Grid::new(NUM_COLS, NUM_ROWS, Tile::Empty);
     let reference_eris = new_eris.clone();
 
     for index in indices {
-        let row = *index / NUM_ROWS; //Floor division gives us the row
-        let col = *index % NUM_COLS; //Modulo gives the column
-        let num_adjacent_bugs = number_adjacent_bugs(hyper_eris, &reference_eris, row, col, level);
-        if num_adjacent_bugs == 1 || num_adjacent_bugs == 2 {
-            new_eris[row][col] = Tile::Bug;
-        } else {
-            new_eris[row][col] = Tile::Empty;
-        }
+        //Floor division gives us the row, modulo the column
+        let point = Point::new((*index % NUM_COLS) as isize, (*index / NUM_ROWS) as isize);
+        let num_adjacent_bugs = number_adjacent_bugs(hyper_eris, &reference_eris, point, level);
+        new_eris[point] = next_tile(Tile::Empty, num_adjacent_bugs);
     }
     new_hyper_eris.insert(level, new_eris);
 }
 
 fn number_adjacent_bugs(
-    hyper_eris: &HashMap<isize, Vec<Vec<Tile>>>,
-    reference_eris: &[Vec<Tile>],
-    row: usize,
-    col: usize,
+    hyper_eris: &HashMap<isize, Grid<Tile>>,
+    reference_eris: &Grid<Tile>,
+    point: Point,
     depth: isize,
 ) -> usize {
     let mut adjacents = vec![];
-    let d = [(1, 0), (0, 1), (-1, 0), (0, -1)];
-    for (dr, dc) in d {
-        if (row as isize) + dr < 0
-            || (col as isize) + dc < 0
-            || (row as isize) + dr >= NUM_ROWS as isize
-            || (col as isize) + dc >= NUM_COLS as isize
-        {
+    for neighbour in point.neighbours() {
+        if !reference_eris.contains(neighbour) {
             //Look to the outer level if it exists
             if let Some(eris) = hyper_eris.get(&(depth - 1)) {
-                if (row as isize) + dr < 0 {
-                    // Looking to the tile above the top row, which is the 1, 2 element of the outer layer
-                    adjacents.push(eris[1][2]);
-                } else if (row as isize) + dr >= NUM_ROWS as isize {
+                let outer_neighbour = if neighbour.y < 0 {
+                    // Looking to the tile above the top row, which is the tile above the centre of the outer layer
+                    Point::new(2, 1)
+                } else if neighbour.y >= NUM_ROWS as isize {
                     //Looking below
-                    adjacents.push(eris[3][2]);
-                } else if (col as isize) + dc < 0 {
+                    Point::new(2, 3)
+                } else if neighbour.x < 0 {
                     //Looking left
-                    adjacents.push(eris[2][1]);
+                    Point::new(1, 2)
                 } else {
                     //Looking right
-                    assert!((col as isize) + dc >= NUM_COLS as isize);
-                    adjacents.push(eris[2][3]);
-                }
+                    assert!(neighbour.x >= NUM_COLS as isize);
+                    Point::new(3, 2)
+                };
+                adjacents.push(eris[outer_neighbour]);
             }
-        } else if (row as isize) + dr == 2 && (col as isize) + dc == 2 {
+        } else if neighbour == CENTRE {
             //Move to the inner level if it exists
             if let Some(eris) = hyper_eris.get(&(depth + 1)) {
-                if row == 2 && col == 1 {
+                let edge: Vec<Point> = if point == Point::new(1, 2) {
                     //Right side of this tile, so left side of inner eris
-                    for r in eris.iter().take(NUM_ROWS) {
-                        adjacents.push(r[0]);
-                    }
-                } else if row == 1 && col == 2 {
+                    (0..NUM_ROWS as isize).map(|y| Point::new(0, y)).collect()
+                } else if point == Point::new(2, 1) {
                     //Bottom of this tile, top side of inner eris
-                    for j in 0..NUM_COLS {
-                        adjacents.push(eris[0][j]);
-                    }
-                } else if row == 2 && col == 3 {
+                    (0..NUM_COLS as isize).map(|x| Point::new(x, 0)).collect()
+                } else if point == Point::new(3, 2) {
                     //Left side of this tile, right side of inner eris
-                    for r in eris.iter().take(NUM_ROWS) {
-                        adjacents.push(r[4]);
-                    }
+                    (0..NUM_ROWS as isize).map(|y| Point::new(4, y)).collect()
                 } else {
-                    assert_eq!(3, row);
-                    assert_eq!(2, col);
+                    assert_eq!(Point::new(2, 3), point);
                     //Top side of this tile, bottom side of inner eris
-                    for j in 0..NUM_COLS {
-                        adjacents.push(eris[4][j]);
-                    }
-                }
+                    (0..NUM_COLS as isize).map(|x| Point::new(x, 4)).collect()
+                };
+                adjacents.extend(edge.into_iter().map(|point| eris[point]));
             }
         } else {
             //Tile at the same depth
-            adjacents.push(
-                reference_eris[((row as isize) + dr) as usize][((col as isize) + dc) as usize],
-            );
+            adjacents.push(reference_eris[neighbour]);
         }
     }
 
     adjacents.iter().filter(|tile| **tile == Tile::Bug).count()
 }
 
-fn bug_on_rim(eris: &[Vec<Tile>], indices: &[usize]) -> bool {
-    eris.iter()
-        .flatten()
+fn bug_on_rim(eris: &Grid<Tile>, indices: &[usize]) -> bool {
+    eris.values()
         .enumerate()
         .any(|(index, tile)| indices.contains(&index) && *tile == Tile::Bug)
 }
 
-fn evolve_one_minute(eris: &[Vec<Tile>]) -> Vec<Vec<Tile>> {
-    eris.iter()
-        .enumerate()
-        .map(|(row, tiles)| {
-            tiles
-                .iter()
-                .enumerate()
-                .map(|(col, tile)| {
-                    let d: [(isize, isize); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
-                    let adjacents = d
-                        .iter()
-                        .map(|(dr, dc)| {
-                            match eris.get(if row as isize + *dr >= 0 {
-                                (row as isize + *dr) as usize
-                            } else {
-                                NUM_ROWS //Exceeds bounds - guaranteed to return None
-                            }) {
-                                Some(tiles) => match tiles.get(if col as isize + *dc >= 0 {
-                                    (col as isize + *dc) as usize
-                                } else {
-                                    NUM_COLS //Exceeds bounds - guaranteed to return None
-                                }) {
-                                    Some(tile) => *tile,
-                                    None => Tile::Empty,
-                                },
-                                None => Tile::Empty,
-                            }
-                        })
-                        .collect::<Vec<Tile>>();
-                    let num_bugs = adjacents.iter().filter(|adj| **adj == Tile::Bug).count();
-                    match *tile {
-                        Tile::Empty => {
-                            if num_bugs == 1 || num_bugs == 2 {
-                                Tile::Bug
-                            } else {
-                                Tile::Empty
-                            }
-                        }
-                        Tile::Bug => {
-                            if num_bugs == 1 {
-                                Tile::Bug
-                            } else {
-                                Tile::Empty
-                            }
-                        }
-                    }
-                })
-                .collect::<Vec<Tile>>()
-        })
-        .collect::<Vec<Vec<Tile>>>()
+fn evolve_one_minute(eris: &Grid<Tile>) -> Grid<Tile> {
+    //Anything outside the grid is empty, so only the neighbours inside it count.
+    eris.map(|point, tile| {
+        let num_bugs = eris
+            .neighbours(point)
+            .filter(|neighbour| eris[*neighbour] == Tile::Bug)
+            .count();
+        next_tile(*tile, num_bugs)
+    })
 }
 
 #[cfg(test)]
@@ -377,6 +313,25 @@ mod test {
         assert_eq!(expected, eris);
     }
 
+    #[test]
+    fn test_first_repeated_layout() {
+        let eris = parse_input(
+            "....#
+#..#.
+#..##
+..#..
+#....",
+        );
+        let expected = parse_input(
+            ".....
+.....
+.....
+#....
+.#...",
+        );
+        assert_eq!(expected, first_repeated_layout(&eris));
+    }
+
     #[test]
     fn test_biodiversity_rating() {
         let eris = parse_input(
@@ -398,7 +353,7 @@ mod test {
 ..#..
 #....",
         );
-        let mut hyper_eris: HashMap<isize, Vec<Vec<Tile>>> = HashMap::new();
+        let mut hyper_eris: HashMap<isize, Grid<Tile>> = HashMap::new();
         hyper_eris.insert(0, eris);
 
         for _ in 0..10 {
@@ -1,3 +1,5 @@
+use crate::grid::Grid;
+use crate::image::{self, Colour, Image};
 use std::fs;
 
 ///Day 8 solution
@@ -14,13 +16,23 @@ pub fn day8() -> (usize, usize) {
 
     let part2: i32 = 0;
 
-    decode_image(&layers, layer_size)
-        .chunks(WIDTH)
-        .for_each(|row| {
-            row.iter()
-                .for_each(|&c| print!("{}", if c == '0' { ' ' } else { '*' }));
-            println!();
+    let decoded = decode_image(&layers, layer_size);
+    image::visualise("day8", || {
+        let message = Grid::from_fn(WIDTH, HEIGHT, |point| {
+            decoded[point.y as usize * WIDTH + point.x as usize]
         });
+        Image::from_grid(&message, |c| match c {
+            '0' => Colour::BLACK,
+            _ => Colour::WHITE,
+        })
+        .scaled(10)
+    });
+
+    decoded.chunks(WIDTH).for_each(|row| {
+        row.iter()
+            .for_each(|&c| print!("{}", if c == '0' { ' ' } else { '*' }));
+        println!();
+    });
 
     (part1, part2 as usize)
 }
@@ -0,0 +1,628 @@
+// Line segments on the grid that run horizontally or vertically, and polylines made of them - day 3's wires, or
+// anything else traced out by U/D/L/R moves. As each segment is its own bounding box (just a very thin one), two of
+// them meet wherever their bounding boxes do, which is nothing, a single point, or - if they lie along the same line -
+// a stretch of overlap. That covers crossing, touching at the ends, zero length segments and running alongside each
+// other without any special cases, and it's all integer arithmetic, so there's no rounding to worry about.
+//
+// crossings finds everywhere some polylines meet each other with a sweep line, rather than testing every segment
+// against every other, and closest_crossing and quickest_crossing pick out the best of those places.
+//
+// Bearing is a direction at any angle, kept as a ratio of integers rather than an angle in floating point, so points
+// along the same line always have exactly the same bearing and bearings compare exactly (which day 10 needs).
+#![allow(dead_code)]
+
+use crate::grid::{Bounds, Direction, Point};
+use num::Integer;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeSet, HashMap};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Segment {
+    pub start: Point,
+    pub end: Point,
+}
+
+impl Segment {
+    pub fn new(start: Point, end: Point) -> Segment {
+        assert!(
+            start.x == end.x || start.y == end.y,
+            "{} to {} is neither horizontal nor vertical",
+            start,
+            end
+        );
+        Segment { start, end }
+    }
+
+    // A single point counts as both horizontal and vertical.
+    pub fn is_horizontal(&self) -> bool {
+        self.start.y == self.end.y
+    }
+
+    pub fn is_vertical(&self) -> bool {
+        self.start.x == self.end.x
+    }
+
+    pub fn length(&self) -> usize {
+        self.start.manhattan_distance(self.end) as usize
+    }
+
+    pub fn bounds(&self) -> Bounds {
+        Bounds {
+            min: self.start.min(self.end),
+            max: self.start.max(self.end),
+        }
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        self.bounds().contains(point)
+    }
+
+    // One step from start towards end (or nowhere, for a single point).
+    pub fn step(&self) -> Point {
+        Point::new(
+            (self.end.x - self.start.x).signum(),
+            (self.end.y - self.start.y).signum(),
+        )
+    }
+
+    // From start to end.
+    pub fn points(&self) -> impl Iterator<Item = Point> {
+        let (start, step) = (self.start, self.step());
+        (0..=self.length() as isize).map(move |steps| start + step * steps)
+    }
+
+    // Steps from start to a point on the segment.
+    pub fn distance_along(&self, point: Point) -> usize {
+        debug_assert!(self.contains(point), "{} isn't on {:?}", point, self);
+        self.start.manhattan_distance(point) as usize
+    }
+
+    // The point on the segment closest to point - by Manhattan distance, or as the crow flies, as it's the same one.
+    pub fn nearest_to(&self, point: Point) -> Point {
+        let Bounds { min, max } = self.bounds();
+        Point::new(point.x.clamp(min.x, max.x), point.y.clamp(min.y, max.y))
+    }
+
+    // Where this meets other. An overlap goes the same way as this segment.
+    pub fn intersection(&self, other: &Segment) -> Option<Intersection> {
+        let Bounds { min, max } = self.bounds().intersection(&other.bounds())?;
+        Some(if min == max {
+            Intersection::Point(min)
+        } else if self.start <= self.end {
+            Intersection::Overlap(Segment::new(min, max))
+        } else {
+            Intersection::Overlap(Segment::new(max, min))
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Intersection {
+    Point(Point),
+    Overlap(Segment),
+}
+
+impl Intersection {
+    pub fn points(&self) -> Vec<Point> {
+        match self {
+            Intersection::Point(point) => vec![*point],
+            Intersection::Overlap(overlap) => overlap.points().collect(),
+        }
+    }
+
+    // The points that could be best by any measure that either changes steadily along the intersection (like steps
+    // along a polyline) or has a single lowest point (like the distance from excluded) - the ends and the point nearest
+    // excluded, plus the points either side of those in case one of them is excluded.
+    fn candidates(&self, excluded: Point) -> Vec<Point> {
+        match self {
+            Intersection::Point(point) if *point == excluded => vec![],
+            Intersection::Point(point) => vec![*point],
+            Intersection::Overlap(overlap) => {
+                let step = overlap.step();
+                let mut candidates: Vec<Point> =
+                    [overlap.start, overlap.end, overlap.nearest_to(excluded)]
+                        .into_iter()
+                        .flat_map(|point| [point - step, point, point + step])
+                        .filter(|point| overlap.contains(*point) && *point != excluded)
+                        .collect();
+                candidates.sort();
+                candidates.dedup();
+                candidates
+            }
+        }
+    }
+}
+
+// Joined up segments, each starting where the last one ended.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Polyline {
+    segments: Vec<Segment>,
+    steps: Vec<usize>, //Along the polyline to the start of each segment
+}
+
+impl Polyline {
+    pub fn from_moves(
+        start: Point,
+        moves: impl IntoIterator<Item = (Direction, usize)>,
+    ) -> Polyline {
+        let mut polyline = Polyline::default();
+        let mut position = start;
+        for (direction, distance) in moves {
+            let end = position + direction.offset() * distance as isize;
+            polyline.push(Segment::new(position, end));
+            position = end;
+        }
+        polyline
+    }
+
+    pub fn push(&mut self, segment: Segment) {
+        if let Some(last) = self.segments.last() {
+            assert_eq!(
+                last.end, segment.start,
+                "Segments of a polyline have to join up"
+            );
+        }
+        self.steps.push(self.length());
+        self.segments.push(segment);
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    // Where it starts, then the end of each segment in turn.
+    pub fn vertices(&self) -> Vec<Point> {
+        self.segments
+            .first()
+            .map(|first| first.start)
+            .into_iter()
+            .chain(self.segments.iter().map(|segment| segment.end))
+            .collect()
+    }
+
+    pub fn length(&self) -> usize {
+        match (self.segments.last(), self.steps.last()) {
+            (Some(last), Some(steps)) => steps + last.length(),
+            _ => 0,
+        }
+    }
+
+    // Steps along the polyline to a point on one of its segments, going that way.
+    pub fn steps_to(&self, segment: usize, point: Point) -> usize {
+        self.steps[segment] + self.segments[segment].distance_along(point)
+    }
+}
+
+// Where two different polylines meet, and which segment of each meets the other. The polylines are in order, and the
+// intersection goes the same way as the first one's segment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Crossing {
+    pub polylines: (usize, usize),
+    pub segments: (usize, usize),
+    pub intersection: Intersection,
+}
+
+// Everywhere the polylines meet each other (but not where one meets itself), by sweeping across from left to right.
+// Horizontal segments go into a set ordered by y as the sweep reaches them and come out again as it passes them, so
+// at each vertical segment, the horizontal ones that cross it are the range of the set between its ends. Segments
+// along the same line are sorted by where they start, and each overlaps all the earlier ones that haven't finished
+// yet. Either way the work is O(n log n) for n segments, plus however many pairs of segments meet.
+pub fn crossings(polylines: &[Polyline]) -> Vec<Crossing> {
+    //(polyline, segment), with single points counting as horizontal.
+    let ids: Vec<(usize, usize)> = polylines
+        .iter()
+        .enumerate()
+        .flat_map(|(polyline, p)| (0..p.segments.len()).map(move |segment| (polyline, segment)))
+        .collect();
+    let segment = |id: usize| polylines[ids[id].0].segments[ids[id].1];
+    let bounds = |id: usize| segment(id).bounds();
+    let horizontal = |id: usize| segment(id).is_horizontal();
+
+    let mut crossings = vec![];
+    let mut add = |a: usize, b: usize| {
+        let ((a, b), (first, second)) = match ids[a].0.cmp(&ids[b].0) {
+            std::cmp::Ordering::Equal => return,
+            std::cmp::Ordering::Less => ((a, b), (ids[a], ids[b])),
+            std::cmp::Ordering::Greater => ((b, a), (ids[b], ids[a])),
+        };
+        crossings.push(Crossing {
+            polylines: (first.0, second.0),
+            segments: (first.1, second.1),
+            intersection: segment(a)
+                .intersection(&segment(b))
+                .expect("Sweep found segments that don't meet"),
+        });
+    };
+
+    //Horizontal against vertical. At the same x, horizontal segments go in before the vertical ones are checked, and
+    //come out after, so touching ends count.
+    const ADD: u8 = 0;
+    const CHECK: u8 = 1;
+    const REMOVE: u8 = 2;
+    let mut events: Vec<(isize, u8, usize)> = vec![];
+    for id in 0..ids.len() {
+        let Bounds { min, max } = bounds(id);
+        if horizontal(id) {
+            events.push((min.x, ADD, id));
+            events.push((max.x, REMOVE, id));
+        } else {
+            events.push((min.x, CHECK, id));
+        }
+    }
+    events.sort_unstable();
+    let mut active: BTreeSet<(isize, usize)> = BTreeSet::new();
+    for (_, event, id) in events {
+        let Bounds { min, max } = bounds(id);
+        match event {
+            ADD => {
+                active.insert((min.y, id));
+            }
+            REMOVE => {
+                active.remove(&(min.y, id));
+            }
+            _ => {
+                for (_, crossing) in active.range((min.y, 0)..=(max.y, usize::MAX)) {
+                    add(id, *crossing);
+                }
+            }
+        }
+    }
+
+    //Along the same line.
+    let mut lines: HashMap<(bool, isize), Vec<usize>> = HashMap::new();
+    for id in 0..ids.len() {
+        let Bounds { min, .. } = bounds(id);
+        let line = if horizontal(id) {
+            (true, min.y)
+        } else {
+            (false, min.x)
+        };
+        lines.entry(line).or_default().push(id);
+    }
+    for ((is_horizontal, _), mut on_line) in lines {
+        let along = |point: Point| if is_horizontal { point.x } else { point.y };
+        on_line.sort_by_key(|id| along(bounds(*id).min));
+        let mut unfinished: Vec<usize> = vec![];
+        for id in on_line {
+            let start = along(bounds(id).min);
+            unfinished.retain(|earlier| along(bounds(*earlier).max) >= start);
+            for earlier in &unfinished {
+                add(*earlier, id);
+            }
+            unfinished.push(id);
+        }
+    }
+
+    crossings
+}
+
+// The point where polylines cross that's the shortest Manhattan distance from from, and that distance. from itself
+// doesn't count, as it's usually where they all start.
+pub fn closest_crossing(crossings: &[Crossing], from: Point) -> Option<(Point, usize)> {
+    crossings
+        .iter()
+        .flat_map(|crossing| crossing.intersection.candidates(from))
+        .map(|point| (point, point.manhattan_distance(from) as usize))
+        .min_by_key(|(_, distance)| *distance)
+}
+
+// The point where polylines cross that takes the fewest steps along both of them between them to get to, and that
+// number of steps. excluded doesn't count, as that's usually where they all start.
+pub fn quickest_crossing(
+    polylines: &[Polyline],
+    crossings: &[Crossing],
+    excluded: Point,
+) -> Option<(Point, usize)> {
+    crossings
+        .iter()
+        .flat_map(|crossing| {
+            let (first, second) = (
+                &polylines[crossing.polylines.0],
+                &polylines[crossing.polylines.1],
+            );
+            crossing
+                .intersection
+                .candidates(excluded)
+                .into_iter()
+                .map(move |point| {
+                    let steps = first.steps_to(crossing.segments.0, point)
+                        + second.steps_to(crossing.segments.1, point);
+                    (point, steps)
+                })
+        })
+        .min_by_key(|(_, steps)| *steps)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Rotation {
+    Clockwise,
+    Anticlockwise,
+}
+
+// The direction of an offset, as the offset in lowest terms - (4, -6) and (2, -3) have the same bearing. Bearings are
+// ordered clockwise, starting from straight up (and remembering that y increases downwards): first by which half of
+// the circle they're in, then by the sign of the cross product, which says which of two bearings within half a turn
+// of each other comes first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Bearing {
+    x: isize,
+    y: isize,
+}
+
+impl Bearing {
+    pub const UP: Bearing = Bearing { x: 0, y: -1 };
+    pub const RIGHT: Bearing = Bearing { x: 1, y: 0 };
+    pub const DOWN: Bearing = Bearing { x: 0, y: 1 };
+    pub const LEFT: Bearing = Bearing { x: -1, y: 0 };
+
+    pub fn new(offset: Point) -> Bearing {
+        assert_ne!(Point::ORIGIN, offset, "Can't take the bearing of no offset");
+        let gcd = offset.x.gcd(&offset.y);
+        Bearing {
+            x: offset.x / gcd,
+            y: offset.y / gcd,
+        }
+    }
+
+    pub fn between(from: Point, to: Point) -> Bearing {
+        Bearing::new(to - from)
+    }
+
+    // The smallest step in this direction that lands on a grid point.
+    pub fn offset(&self) -> Point {
+        Point::new(self.x, self.y)
+    }
+
+    //0 from straight up to just before straight down, 1 from there round to just before straight up again.
+    fn half(&self) -> u8 {
+        u8::from(!(self.x > 0 || (self.x == 0 && self.y < 0)))
+    }
+
+    //Positive if other is less than half a turn clockwise from this.
+    fn cross(&self, other: &Bearing) -> isize {
+        self.x * other.y - self.y * other.x
+    }
+
+    // Order going round from start in the direction given, with start itself first.
+    pub fn cmp_from(&self, other: &Bearing, start: Bearing, rotation: Rotation) -> Ordering {
+        match rotation {
+            Rotation::Clockwise => (*self < start, *self).cmp(&(*other < start, *other)),
+            Rotation::Anticlockwise => {
+                (*self > start, Reverse(*self)).cmp(&(*other > start, Reverse(*other)))
+            }
+        }
+    }
+}
+
+impl From<Direction> for Bearing {
+    fn from(direction: Direction) -> Bearing {
+        Bearing::new(direction.offset())
+    }
+}
+
+impl Ord for Bearing {
+    fn cmp(&self, other: &Bearing) -> Ordering {
+        self.half()
+            .cmp(&other.half())
+            .then_with(|| 0.cmp(&self.cross(other)))
+    }
+}
+
+impl PartialOrd for Bearing {
+    fn partial_cmp(&self, other: &Bearing) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn segment(start: (isize, isize), end: (isize, isize)) -> Segment {
+        Segment::new(Point::new(start.0, start.1), Point::new(end.0, end.1))
+    }
+
+    #[test]
+    fn test_intersection() {
+        let horizontal = segment((0, 2), (6, 2));
+        //Crossing, touching at an end, and missing.
+        assert_eq!(
+            Some(Intersection::Point(Point::new(3, 2))),
+            horizontal.intersection(&segment((3, 0), (3, 5)))
+        );
+        assert_eq!(
+            Some(Intersection::Point(Point::new(6, 2))),
+            horizontal.intersection(&segment((6, 2), (6, -4)))
+        );
+        assert_eq!(None, horizontal.intersection(&segment((7, 0), (7, 5))));
+        assert_eq!(None, horizontal.intersection(&segment((0, 3), (6, 3))));
+        //Overlapping, going the way of the first segment.
+        assert_eq!(
+            Some(Intersection::Overlap(segment((4, 2), (6, 2)))),
+            horizontal.intersection(&segment((9, 2), (4, 2)))
+        );
+        assert_eq!(
+            Some(Intersection::Overlap(segment((6, 2), (4, 2)))),
+            segment((9, 2), (4, 2)).intersection(&horizontal)
+        );
+        assert_eq!(
+            Some(Intersection::Point(Point::new(6, 2))),
+            horizontal.intersection(&segment((6, 2), (9, 2)))
+        );
+        //A single point.
+        assert_eq!(
+            Some(Intersection::Point(Point::new(1, 2))),
+            horizontal.intersection(&segment((1, 2), (1, 2)))
+        );
+
+        let overlap = segment((0, 5), (0, 1));
+        assert_eq!(5, overlap.points().count());
+        assert_eq!(Point::new(0, 1), overlap.nearest_to(Point::new(3, -2)));
+        assert_eq!(
+            vec![
+                Point::new(0, 1),
+                Point::new(0, 2),
+                Point::new(0, 4),
+                Point::new(0, 5)
+            ],
+            Intersection::Overlap(overlap).candidates(Point::new(0, 3))
+        );
+    }
+
+    //Every point on more than one polyline, the slow way.
+    fn crossing_points(polylines: &[Polyline]) -> HashSet<Point> {
+        let mut seen_on: HashMap<Point, usize> = HashMap::new();
+        let mut crossing_points = HashSet::new();
+        for (index, polyline) in polylines.iter().enumerate() {
+            for point in polyline
+                .segments()
+                .iter()
+                .flat_map(|segment| segment.points())
+            {
+                if *seen_on.entry(point).or_insert(index) != index {
+                    crossing_points.insert(point);
+                }
+            }
+        }
+        crossing_points
+    }
+
+    #[test]
+    fn test_crossings() {
+        let moves = |description: &str| {
+            description
+                .split(',')
+                .map(|step| {
+                    (
+                        Direction::try_from(step.chars().next().unwrap()).unwrap(),
+                        step[1..].parse().unwrap(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        //Crossing, running alongside each other and touching, with a zero length move thrown in.
+        let polylines: Vec<Polyline> = [
+            "R8,U5,L5,D3",
+            "U7,R6,D4,L4",
+            "R3,U0,R2,U8,L10",
+            "D1,R12,U4,L1,U3",
+        ]
+        .iter()
+        .map(|description| Polyline::from_moves(Point::ORIGIN, moves(description)))
+        .collect();
+        assert_eq!(21, polylines[3].length());
+
+        let crossings = crossings(&polylines);
+        let found: HashSet<Point> = crossings
+            .iter()
+            .flat_map(|crossing| crossing.intersection.points())
+            .collect();
+        assert_eq!(crossing_points(&polylines), found);
+        for crossing in &crossings {
+            assert!(crossing.polylines.0 < crossing.polylines.1);
+            for point in crossing.intersection.points() {
+                assert!(
+                    polylines[crossing.polylines.0].segments()[crossing.segments.0].contains(point)
+                );
+                assert!(
+                    polylines[crossing.polylines.1].segments()[crossing.segments.1].contains(point)
+                );
+            }
+        }
+
+        //The slow way again.
+        let steps_to = |polyline: &Polyline, target: Point| {
+            let mut steps = 0;
+            for segment in polyline.segments() {
+                for point in segment.points().skip(1) {
+                    steps += 1;
+                    if point == target {
+                        return Some(steps);
+                    }
+                }
+            }
+            None
+        };
+        let closest = found
+            .iter()
+            .filter(|point| **point != Point::ORIGIN)
+            .map(|point| point.manhattan_distance(Point::ORIGIN) as usize)
+            .min();
+        assert_eq!(
+            closest,
+            closest_crossing(&crossings, Point::ORIGIN).map(|(_, distance)| distance)
+        );
+        let quickest = crossings
+            .iter()
+            .flat_map(|crossing| {
+                crossing
+                    .intersection
+                    .points()
+                    .into_iter()
+                    .filter_map(|point| {
+                        Some(
+                            steps_to(&polylines[crossing.polylines.0], point)?
+                                + steps_to(&polylines[crossing.polylines.1], point)?,
+                        )
+                    })
+            })
+            .min();
+        assert_eq!(
+            quickest,
+            quickest_crossing(&polylines, &crossings, Point::ORIGIN).map(|(_, steps)| steps)
+        );
+        assert_eq!(None, closest_crossing(&[], Point::ORIGIN));
+    }
+
+    #[test]
+    fn test_bearings() {
+        assert_eq!(
+            Bearing::new(Point::new(2, -3)),
+            Bearing::new(Point::new(4, -6))
+        );
+        assert_eq!(Bearing::UP, Bearing::new(Point::new(0, -7)));
+        assert_eq!(Bearing::LEFT, Bearing::from(Direction::Left));
+        assert_eq!(
+            Point::new(-1, 3),
+            Bearing::between(Point::new(5, 5), Point::new(3, 11)).offset()
+        );
+
+        //Going round clockwise from straight up, with some awkward ones either side of the axes.
+        let clockwise: Vec<Bearing> = [
+            (0, -1),
+            (1, -100),
+            (1, -1),
+            (100, -1),
+            (1, 0),
+            (100, 1),
+            (1, 1),
+            (1, 100),
+            (0, 1),
+            (-1, 100),
+            (-1, 1),
+            (-100, 1),
+            (-1, 0),
+            (-100, -1),
+            (-1, -1),
+            (-1, -100),
+        ]
+        .iter()
+        .map(|(x, y)| Bearing::new(Point::new(*x, *y)))
+        .collect();
+        let mut sorted = clockwise.clone();
+        sorted.reverse();
+        sorted.sort();
+        assert_eq!(clockwise, sorted);
+
+        //Starting somewhere else, and going the other way.
+        sorted.sort_by(|a, b| a.cmp_from(b, Bearing::DOWN, Rotation::Clockwise));
+        assert_eq!(clockwise[8..], sorted[..8]);
+        assert_eq!(clockwise[..8], sorted[8..]);
+        sorted.sort_by(|a, b| a.cmp_from(b, Bearing::DOWN, Rotation::Anticlockwise));
+        let mut anticlockwise = clockwise[..=8].to_vec();
+        anticlockwise.reverse();
+        anticlockwise.extend(clockwise[9..].iter().rev());
+        assert_eq!(anticlockwise, sorted);
+    }
+}
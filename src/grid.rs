@@ -0,0 +1,710 @@
+// 2D grids for the map-based days. Points have x increasing to the right and y increasing downwards, as maps are
+// read and printed. Grid is dense (every point in a width x height rectangle from the origin has a value, e.g. a map
+// read from the input); SparseGrid holds values only where they've been put (e.g. what a droid has explored so far),
+// keeping track of the bounds as it goes. Both render back to text, and Grid parses from it for any tile type that
+// implements TryFrom<char>.
+#![allow(dead_code)]
+
+use std::collections::hash_map::{self, HashMap};
+use std::fmt;
+use std::ops::{Add, AddAssign, Index, IndexMut, Mul, Sub};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Point {
+    pub x: isize,
+    pub y: isize,
+}
+
+impl Point {
+    pub const ORIGIN: Point = Point { x: 0, y: 0 };
+
+    pub fn new(x: isize, y: isize) -> Point {
+        Point { x, y }
+    }
+
+    pub fn step(self, direction: Direction) -> Point {
+        self + direction.offset()
+    }
+
+    // The four points next to this one, in the order of Direction::ALL.
+    pub fn neighbours(self) -> impl Iterator<Item = Point> {
+        Direction::ALL
+            .into_iter()
+            .map(move |direction| self.step(direction))
+    }
+
+    // The eight points around this one, clockwise from the one above.
+    pub fn neighbours_with_diagonals(self) -> impl Iterator<Item = Point> {
+        [
+            (0, -1),
+            (1, -1),
+            (1, 0),
+            (1, 1),
+            (0, 1),
+            (-1, 1),
+            (-1, 0),
+            (-1, -1),
+        ]
+        .into_iter()
+        .map(move |(dx, dy)| self + Point::new(dx, dy))
+    }
+
+    pub fn manhattan_distance(self, other: Point) -> isize {
+        (self.x - other.x).abs() + (self.y - other.y).abs()
+    }
+}
+
+impl Add for Point {
+    type Output = Point;
+
+    fn add(self, other: Point) -> Point {
+        Point::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl AddAssign for Point {
+    fn add_assign(&mut self, other: Point) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Point {
+    type Output = Point;
+
+    fn sub(self, other: Point) -> Point {
+        Point::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl Mul<isize> for Point {
+    type Output = Point;
+
+    fn mul(self, factor: isize) -> Point {
+        Point::new(self.x * factor, self.y * factor)
+    }
+}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Direction {
+    Up, //Towards negative y
+    Right,
+    Down,
+    Left,
+}
+
+impl Direction {
+    //Clockwise from up
+    pub const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Right,
+        Direction::Down,
+        Direction::Left,
+    ];
+
+    pub fn offset(self) -> Point {
+        match self {
+            Direction::Up => Point::new(0, -1),
+            Direction::Right => Point::new(1, 0),
+            Direction::Down => Point::new(0, 1),
+            Direction::Left => Point::new(-1, 0),
+        }
+    }
+
+    pub fn turn_right(self) -> Direction {
+        Direction::ALL[(self as usize + 1) % 4]
+    }
+
+    pub fn turn_left(self) -> Direction {
+        Direction::ALL[(self as usize + 3) % 4]
+    }
+
+    pub fn reverse(self) -> Direction {
+        Direction::ALL[(self as usize + 2) % 4]
+    }
+}
+
+// Either an arrow (^>v<) or the initial letter (URDL).
+impl TryFrom<char> for Direction {
+    type Error = String;
+
+    fn try_from(c: char) -> Result<Self, Self::Error> {
+        match c {
+            '^' | 'U' => Ok(Direction::Up),
+            '>' | 'R' => Ok(Direction::Right),
+            'v' | 'D' => Ok(Direction::Down),
+            '<' | 'L' => Ok(Direction::Left),
+            other => Err(format!("Not a direction: {}", other)),
+        }
+    }
+}
+
+impl From<Direction> for char {
+    fn from(direction: Direction) -> char {
+        match direction {
+            Direction::Up => '^',
+            Direction::Right => '>',
+            Direction::Down => 'v',
+            Direction::Left => '<',
+        }
+    }
+}
+
+// The smallest rectangle containing some points. Both corners are inside it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Bounds {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Bounds {
+    pub fn new(point: Point) -> Bounds {
+        Bounds {
+            min: point,
+            max: point,
+        }
+    }
+
+    // None if there aren't any points.
+    pub fn of(points: impl IntoIterator<Item = Point>) -> Option<Bounds> {
+        let mut points = points.into_iter();
+        let mut bounds = Bounds::new(points.next()?);
+        points.for_each(|point| bounds.include(point));
+        Some(bounds)
+    }
+
+    pub fn include(&mut self, point: Point) {
+        self.min = Point::new(self.min.x.min(point.x), self.min.y.min(point.y));
+        self.max = Point::new(self.max.x.max(point.x), self.max.y.max(point.y));
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        (self.min.x..=self.max.x).contains(&point.x) && (self.min.y..=self.max.y).contains(&point.y)
+    }
+
+    // The points inside both, if there are any.
+    pub fn intersection(&self, other: &Bounds) -> Option<Bounds> {
+        let min = Point::new(self.min.x.max(other.min.x), self.min.y.max(other.min.y));
+        let max = Point::new(self.max.x.min(other.max.x), self.max.y.min(other.max.y));
+        (min.x <= max.x && min.y <= max.y).then_some(Bounds { min, max })
+    }
+
+    pub fn width(&self) -> usize {
+        (self.max.x - self.min.x + 1) as usize
+    }
+
+    pub fn height(&self) -> usize {
+        (self.max.y - self.min.y + 1) as usize
+    }
+
+    // Every point inside, a row at a time from the top.
+    pub fn points(&self) -> impl Iterator<Item = Point> {
+        let Bounds { min, max } = *self;
+        (min.y..=max.y).flat_map(move |y| (min.x..=max.x).map(move |x| Point::new(x, y)))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Grid<T> {
+    width: usize,
+    height: usize,
+    cells: Vec<T>, //A row at a time
+}
+
+impl<T> Grid<T> {
+    pub fn new(width: usize, height: usize, fill: T) -> Grid<T>
+    where
+        T: Clone,
+    {
+        Grid {
+            width,
+            height,
+            cells: vec![fill; width * height],
+        }
+    }
+
+    // Builds a grid from a function of each point.
+    pub fn from_fn(width: usize, height: usize, f: impl FnMut(Point) -> T) -> Grid<T> {
+        let cells = (0..height as isize)
+            .flat_map(|y| (0..width as isize).map(move |x| Point::new(x, y)))
+            .map(f)
+            .collect();
+        Grid {
+            width,
+            height,
+            cells,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        (0..self.width as isize).contains(&point.x) && (0..self.height as isize).contains(&point.y)
+    }
+
+    fn index_of(&self, point: Point) -> Option<usize> {
+        if self.contains(point) {
+            Some(point.y as usize * self.width + point.x as usize)
+        } else {
+            None
+        }
+    }
+
+    // None outside the grid.
+    pub fn get(&self, point: Point) -> Option<&T> {
+        self.index_of(point).map(|index| &self.cells[index])
+    }
+
+    pub fn get_mut(&mut self, point: Point) -> Option<&mut T> {
+        self.index_of(point).map(|index| &mut self.cells[index])
+    }
+
+    pub fn points(&self) -> impl Iterator<Item = Point> {
+        let width = self.width as isize;
+        (0..self.cells.len() as isize).map(move |index| Point::new(index % width, index / width))
+    }
+
+    // Every point with its value, a row at a time from the top.
+    pub fn iter(&self) -> impl Iterator<Item = (Point, &T)> {
+        self.points().zip(self.cells.iter())
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.cells.iter()
+    }
+
+    // The neighbours of point that are in the grid.
+    pub fn neighbours(&self, point: Point) -> impl Iterator<Item = Point> + '_ {
+        point
+            .neighbours()
+            .filter(move |neighbour| self.contains(*neighbour))
+    }
+
+    // The first point, reading from the top, whose value matches.
+    pub fn find(&self, mut predicate: impl FnMut(&T) -> bool) -> Option<Point> {
+        self.iter()
+            .find(|(_, value)| predicate(value))
+            .map(|(point, _)| point)
+    }
+
+    pub fn map<U>(&self, mut f: impl FnMut(Point, &T) -> U) -> Grid<U> {
+        Grid {
+            width: self.width,
+            height: self.height,
+            cells: self.iter().map(|(point, value)| f(point, value)).collect(),
+        }
+    }
+
+    // One line per row, each ending in a newline.
+    pub fn render(&self, mut to_char: impl FnMut(&T) -> char) -> String {
+        let mut text = String::with_capacity((self.width + 1) * self.height);
+        for row in self.cells.chunks(self.width.max(1)) {
+            text.extend(row.iter().map(&mut to_char));
+            text.push('\n');
+        }
+        text
+    }
+}
+
+impl<T> Index<Point> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, point: Point) -> &T {
+        self.get(point)
+            .unwrap_or_else(|| panic!("{} is outside the grid", point))
+    }
+}
+
+impl<T> IndexMut<Point> for Grid<T> {
+    fn index_mut(&mut self, point: Point) -> &mut T {
+        self.get_mut(point)
+            .unwrap_or_else(|| panic!("{} is outside the grid", point))
+    }
+}
+
+impl<T: Copy + Into<char>> fmt::Display for Grid<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(|value| (*value).into()))
+    }
+}
+
+// Lines and columns count from 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GridParseError {
+    BadCharacter {
+        line: usize,
+        column: usize,
+        character: char,
+        reason: String,
+    },
+    Ragged {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for GridParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GridParseError::BadCharacter {
+                line,
+                column,
+                character,
+                reason,
+            } => write!(
+                f,
+                "{}:{}: couldn't parse '{}' ({})",
+                line, column, character, reason
+            ),
+            GridParseError::Ragged {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Line {} is {} characters long but the first line is {}",
+                line, found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GridParseError {}
+
+// A line of text per row. Blank lines at the end and carriage returns are ignored, but all the other lines must be
+// the same length.
+impl<T: TryFrom<char>> FromStr for Grid<T>
+where
+    T::Error: fmt::Display,
+{
+    type Err = GridParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines: Vec<&str> = s.lines().map(|line| line.trim_end_matches('\r')).collect();
+        while lines.last().is_some_and(|line| line.trim().is_empty()) {
+            lines.pop();
+        }
+        let width = lines.first().map_or(0, |line| line.chars().count());
+        let mut cells = Vec::with_capacity(width * lines.len());
+        for (y, line) in lines.iter().enumerate() {
+            let found = line.chars().count();
+            if found != width {
+                return Err(GridParseError::Ragged {
+                    line: y + 1,
+                    expected: width,
+                    found,
+                });
+            }
+            for (x, c) in line.chars().enumerate() {
+                cells.push(T::try_from(c).map_err(|err| GridParseError::BadCharacter {
+                    line: y + 1,
+                    column: x + 1,
+                    character: c,
+                    reason: err.to_string(),
+                })?);
+            }
+        }
+        Ok(Grid {
+            width,
+            height: lines.len(),
+            cells,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SparseGrid<T> {
+    cells: HashMap<Point, T>,
+    bounds: Option<Bounds>, //Of every point ever inserted, even if it's since been removed
+}
+
+impl<T> Default for SparseGrid<T> {
+    fn default() -> Self {
+        SparseGrid {
+            cells: HashMap::new(),
+            bounds: None,
+        }
+    }
+}
+
+impl<T> SparseGrid<T> {
+    pub fn new() -> SparseGrid<T> {
+        SparseGrid::default()
+    }
+
+    fn include(&mut self, point: Point) {
+        match &mut self.bounds {
+            Some(bounds) => bounds.include(point),
+            None => self.bounds = Some(Bounds::new(point)),
+        }
+    }
+
+    // Returns the old value, if any.
+    pub fn insert(&mut self, point: Point, value: T) -> Option<T> {
+        self.include(point);
+        self.cells.insert(point, value)
+    }
+
+    pub fn entry(&mut self, point: Point) -> hash_map::Entry<'_, Point, T> {
+        self.include(point);
+        self.cells.entry(point)
+    }
+
+    pub fn remove(&mut self, point: Point) -> Option<T> {
+        self.cells.remove(&point)
+    }
+
+    pub fn get(&self, point: Point) -> Option<&T> {
+        self.cells.get(&point)
+    }
+
+    pub fn get_mut(&mut self, point: Point) -> Option<&mut T> {
+        self.cells.get_mut(&point)
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        self.cells.contains_key(&point)
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    // In no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (Point, &T)> {
+        self.cells.iter().map(|(point, value)| (*point, value))
+    }
+
+    pub fn points(&self) -> impl Iterator<Item = Point> + '_ {
+        self.cells.keys().copied()
+    }
+
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.bounds
+    }
+
+    // Any point whose value matches.
+    pub fn find(&self, mut predicate: impl FnMut(&T) -> bool) -> Option<Point> {
+        self.iter()
+            .find(|(_, value)| predicate(value))
+            .map(|(point, _)| point)
+    }
+
+    // The whole of the bounds, a line per row, with background wherever there's no value.
+    pub fn render(&self, background: char, mut to_char: impl FnMut(&T) -> char) -> String {
+        let bounds = match self.bounds {
+            Some(bounds) => bounds,
+            None => return String::new(),
+        };
+        let mut text = String::with_capacity((bounds.width() + 1) * bounds.height());
+        for point in bounds.points() {
+            text.push(self.get(point).map_or(background, &mut to_char));
+            if point.x == bounds.max.x {
+                text.push('\n');
+            }
+        }
+        text
+    }
+}
+
+impl<T> Index<Point> for SparseGrid<T> {
+    type Output = T;
+
+    fn index(&self, point: Point) -> &T {
+        self.get(point)
+            .unwrap_or_else(|| panic!("Nothing at {}", point))
+    }
+}
+
+impl<T> FromIterator<(Point, T)> for SparseGrid<T> {
+    fn from_iter<I: IntoIterator<Item = (Point, T)>>(iter: I) -> Self {
+        let mut grid = SparseGrid::new();
+        for (point, value) in iter {
+            grid.insert(point, value);
+        }
+        grid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum Tile {
+        Open,
+        Wall,
+    }
+
+    impl TryFrom<char> for Tile {
+        type Error = String;
+
+        fn try_from(c: char) -> Result<Self, Self::Error> {
+            match c {
+                '.' => Ok(Tile::Open),
+                '#' => Ok(Tile::Wall),
+                other => Err(format!("Not a tile: {}", other)),
+            }
+        }
+    }
+
+    impl From<Tile> for char {
+        fn from(tile: Tile) -> char {
+            match tile {
+                Tile::Open => '.',
+                Tile::Wall => '#',
+            }
+        }
+    }
+
+    #[test]
+    fn test_points_and_directions() {
+        let point = Point::new(2, 3);
+        assert_eq!(Point::new(2, 2), point.step(Direction::Up));
+        assert_eq!(
+            vec![
+                Point::new(2, 2),
+                Point::new(3, 3),
+                Point::new(2, 4),
+                Point::new(1, 3)
+            ],
+            point.neighbours().collect::<Vec<Point>>()
+        );
+        assert_eq!(8, point.neighbours_with_diagonals().count());
+        assert_eq!(5, point.manhattan_distance(Point::ORIGIN));
+        assert_eq!(Point::new(6, 9), point * 3);
+        assert_eq!(Point::new(1, 1), point - Point::new(1, 2));
+        assert_eq!("(2, 3)", point.to_string());
+
+        assert_eq!(Direction::Right, Direction::Up.turn_right());
+        assert_eq!(Direction::Left, Direction::Up.turn_left());
+        assert_eq!(Direction::Down, Direction::Up.reverse());
+        for direction in Direction::ALL {
+            assert_eq!(direction, direction.turn_left().turn_right());
+            assert_eq!(direction, direction.reverse().reverse());
+            assert_eq!(Ok(direction), Direction::try_from(char::from(direction)));
+        }
+        assert_eq!(Ok(Direction::Left), Direction::try_from('L'));
+        assert!(Direction::try_from('x').is_err());
+    }
+
+    #[test]
+    fn test_bounds() {
+        assert_eq!(None, Bounds::of(vec![]));
+        let bounds =
+            Bounds::of(vec![Point::new(1, -1), Point::new(-2, 3), Point::new(0, 0)]).unwrap();
+        assert_eq!(
+            Bounds {
+                min: Point::new(-2, -1),
+                max: Point::new(1, 3)
+            },
+            bounds
+        );
+        assert_eq!((4, 5), (bounds.width(), bounds.height()));
+        assert!(bounds.contains(Point::new(-2, 3)));
+        assert!(!bounds.contains(Point::new(2, 0)));
+        assert_eq!(20, bounds.points().count());
+        assert_eq!(Some(Point::new(-1, -1)), bounds.points().nth(1));
+
+        let other = Bounds::of(vec![Point::new(1, 3), Point::new(5, 7)]).unwrap();
+        assert_eq!(
+            Some(Bounds::new(Point::new(1, 3))),
+            bounds.intersection(&other)
+        );
+        assert_eq!(None, bounds.intersection(&Bounds::new(Point::new(2, 0))));
+    }
+
+    #[test]
+    fn test_grid() {
+        let text = "#.##\n#..#\n####\n";
+        let mut grid: Grid<Tile> = format!("{}\n\n", text).parse().unwrap();
+        assert_eq!((4, 3), (grid.width(), grid.height()));
+        assert_eq!(Tile::Wall, grid[Point::new(0, 1)]);
+        assert_eq!(Some(&Tile::Open), grid.get(Point::new(1, 0)));
+        assert_eq!(None, grid.get(Point::new(4, 0)));
+        assert_eq!(None, grid.get(Point::new(-1, 0)));
+        assert_eq!(text, grid.to_string());
+        assert_eq!(
+            Some(Point::new(1, 0)),
+            grid.find(|tile| *tile == Tile::Open)
+        );
+        assert_eq!(
+            vec![Point::new(1, 0), Point::new(0, 1)],
+            grid.neighbours(Point::ORIGIN).collect::<Vec<Point>>()
+        );
+
+        grid[Point::new(1, 0)] = Tile::Wall;
+        let middle_row = grid
+            .map(|point, tile| (point.y, *tile))
+            .render(|(y, tile)| if *y == 1 { char::from(*tile) } else { ' ' });
+        assert_eq!("    \n#..#\n    \n", middle_row);
+        let numbered = Grid::from_fn(3, 2, |point| point.x + 10 * point.y);
+        assert_eq!(
+            vec![0, 1, 2, 10, 11, 12],
+            numbered.values().copied().collect::<Vec<isize>>()
+        );
+    }
+
+    #[test]
+    fn test_grid_parse_errors() {
+        assert_eq!(
+            Err(GridParseError::BadCharacter {
+                line: 2,
+                column: 3,
+                character: 'x',
+                reason: "Not a tile: x".to_string()
+            }),
+            "...\n..x\n".parse::<Grid<Tile>>()
+        );
+        assert_eq!(
+            "Line 2 is 2 characters long but the first line is 3",
+            "...\n..\n".parse::<Grid<Tile>>().unwrap_err().to_string()
+        );
+        assert_eq!(0, "".parse::<Grid<Tile>>().unwrap().height());
+    }
+
+    #[test]
+    fn test_sparse_grid() {
+        let mut grid: SparseGrid<Tile> = SparseGrid::new();
+        assert_eq!("", grid.render(' ', |tile| char::from(*tile)));
+        grid.insert(Point::new(1, 1), Tile::Wall);
+        grid.insert(Point::new(-1, 0), Tile::Open);
+        *grid.entry(Point::new(0, 2)).or_insert(Tile::Open) = Tile::Wall;
+        assert_eq!(3, grid.len());
+        assert_eq!(Tile::Wall, grid[Point::new(0, 2)]);
+        assert_eq!(
+            Some(Bounds {
+                min: Point::new(-1, 0),
+                max: Point::new(1, 2)
+            }),
+            grid.bounds()
+        );
+        assert_eq!(
+            ".  \n  #\n # \n",
+            grid.render(' ', |tile| char::from(*tile))
+        );
+
+        //Removing doesn't shrink the bounds.
+        assert_eq!(Some(Tile::Open), grid.remove(Point::new(-1, 0)));
+        assert_eq!(3, grid.bounds().unwrap().width());
+        let copy: SparseGrid<Tile> = grid.iter().map(|(point, tile)| (point, *tile)).collect();
+        assert_eq!(2, copy.bounds().unwrap().width());
+    }
+}
@@ -0,0 +1,399 @@
+// Pictures of the maps the solutions print, as PPM or PNG files. A palette function picks the colour of each cell of a
+// Grid or SparseGrid. Both formats are written by hand: PPM is just a header and the raw pixels, and PNG is a handful
+// of checksummed chunks with the pixels in a zlib stream. Rather than implement DEFLATE compression, the zlib stream
+// uses "stored" blocks, which hold the data as is - the files are bigger than they need be, but every PNG reader can
+// open them.
+//
+// When the runner is given --images, days pass their visualisations to visualise (or visualise_svg, for the odd one
+// that's drawn as SVG instead), which saves them to the output directory; otherwise visualise does nothing (and
+// doesn't even build the image).
+#![allow(dead_code)]
+
+use crate::grid::{Grid, Point, SparseGrid};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+static SAVING_TO: Mutex<Option<PathBuf>> = Mutex::new(None);
+static SAVED: Mutex<Vec<PathBuf>> = Mutex::new(vec![]);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Colour {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Colour {
+    pub const BLACK: Colour = Colour::new(0, 0, 0);
+    pub const WHITE: Colour = Colour::new(255, 255, 255);
+    pub const GREY: Colour = Colour::new(128, 128, 128);
+    pub const RED: Colour = Colour::new(220, 40, 40);
+    pub const GREEN: Colour = Colour::new(40, 180, 40);
+    pub const BLUE: Colour = Colour::new(40, 80, 220);
+    pub const YELLOW: Colour = Colour::new(240, 200, 40);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Colour {
+        Colour { r, g, b }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Colour>, //Row by row from the top left
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, background: Colour) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![background; width * height],
+        }
+    }
+
+    // One pixel per cell.
+    pub fn from_grid<T>(grid: &Grid<T>, mut palette: impl FnMut(&T) -> Colour) -> Image {
+        Image {
+            width: grid.width(),
+            height: grid.height(),
+            pixels: grid.values().map(&mut palette).collect(),
+        }
+    }
+
+    // One pixel per point within the grid's bounds, with background wherever there's nothing.
+    pub fn from_sparse_grid<T>(
+        grid: &SparseGrid<T>,
+        background: Colour,
+        mut palette: impl FnMut(&T) -> Colour,
+    ) -> Image {
+        let bounds = match grid.bounds() {
+            Some(bounds) => bounds,
+            None => return Image::new(0, 0, background),
+        };
+        let mut image = Image::new(bounds.width(), bounds.height(), background);
+        for (point, value) in grid.iter() {
+            image.set(point - bounds.min, palette(value));
+        }
+        image
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // Row by row from the top left.
+    pub fn pixels(&self) -> &[Colour] {
+        &self.pixels
+    }
+
+    pub fn get(&self, point: Point) -> Colour {
+        self.pixels[self.index(point)]
+    }
+
+    pub fn set(&mut self, point: Point, colour: Colour) {
+        let index = self.index(point);
+        self.pixels[index] = colour;
+    }
+
+    fn index(&self, point: Point) -> usize {
+        assert!(
+            point.x >= 0
+                && point.y >= 0
+                && (point.x as usize) < self.width
+                && (point.y as usize) < self.height,
+            "{} is outside the {}x{} image",
+            point,
+            self.width,
+            self.height
+        );
+        point.y as usize * self.width + point.x as usize
+    }
+
+    // Each pixel becomes a factor x factor square - a 5x5 map makes for a very small picture otherwise.
+    pub fn scaled(&self, factor: usize) -> Image {
+        let mut pixels = Vec::with_capacity(self.pixels.len() * factor * factor);
+        for row in self.pixels.chunks(self.width.max(1)) {
+            for _ in 0..factor {
+                for pixel in row {
+                    pixels.extend(std::iter::repeat_n(*pixel, factor));
+                }
+            }
+        }
+        Image {
+            width: self.width * factor,
+            height: self.height * factor,
+            pixels,
+        }
+    }
+
+    // Binary PPM (P6).
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut ppm = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        for pixel in &self.pixels {
+            ppm.extend([pixel.r, pixel.g, pixel.b]);
+        }
+        ppm
+    }
+
+    // 8 bit RGB PNG. Each row of pixels is preceded by its filter type, which is always 0 (none).
+    // See https://www.w3.org/TR/png/
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut header = vec![];
+        header.extend((self.width as u32).to_be_bytes());
+        header.extend((self.height as u32).to_be_bytes());
+        header.extend([8, 2, 0, 0, 0]); //Bit depth, colour type (RGB), compression, filter and interlace methods
+
+        let mut scanlines = Vec::with_capacity(self.height * (1 + 3 * self.width));
+        for row in self.pixels.chunks(self.width.max(1)) {
+            scanlines.push(0);
+            for pixel in row {
+                scanlines.extend([pixel.r, pixel.g, pixel.b]);
+            }
+        }
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        write_chunk(&mut png, b"IHDR", &header);
+        write_chunk(&mut png, b"IDAT", &zlib_stored(&scanlines));
+        write_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    // Saves as PPM if path ends .ppm, otherwise PNG.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let bytes = match path.extension() {
+            Some(extension) if extension == "ppm" => self.to_ppm(),
+            _ => self.to_png(),
+        };
+        fs::write(path, bytes)
+    }
+}
+
+const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+// Length, type, data, then the CRC of the type and data.
+fn write_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(chunk_type);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+// zlib stream (RFC 1950) of DEFLATE stored blocks (RFC 1951 section 3.2.4), which hold up to 65535 bytes each: a byte
+// with the final block flag (and block type 0), then the length and its one's complement, little-endian.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01]; //32K window, no dictionary, fastest "compression"
+    let blocks: Vec<&[u8]> = if data.is_empty() {
+        vec![data]
+    } else {
+        data.chunks(65535).collect()
+    };
+    for (i, block) in blocks.iter().enumerate() {
+        stream.push(u8::from(i == blocks.len() - 1));
+        let length = block.len() as u16;
+        stream.extend(length.to_le_bytes());
+        stream.extend((!length).to_le_bytes());
+        stream.extend(*block);
+    }
+    stream.extend(adler32(data).to_be_bytes());
+    stream
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+    let (mut a, mut b) = (1, 0);
+    for byte in data {
+        a = (a + *byte as u32) % MODULUS;
+        b = (b + a) % MODULUS;
+    }
+    (b << 16) | a
+}
+
+// The CRC-32 used by PNG (and zip, and ethernet), a byte at a time from a table of the remainder of each byte.
+fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (byte, entry) in table.iter_mut().enumerate() {
+        let mut remainder = byte as u32;
+        for _ in 0..8 {
+            remainder = if remainder & 1 == 1 {
+                (remainder >> 1) ^ 0xEDB88320
+            } else {
+                remainder >> 1
+            };
+        }
+        *entry = remainder;
+    }
+
+    !data.iter().fold(!0, |crc, byte| {
+        table[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+pub fn start_saving(directory: impl Into<PathBuf>) {
+    *SAVING_TO
+        .lock()
+        .unwrap_or_else(|err| panic!("Image directory poisoned: {err}")) = Some(directory.into());
+}
+
+pub fn saving() -> bool {
+    SAVING_TO
+        .lock()
+        .unwrap_or_else(|err| panic!("Image directory poisoned: {err}"))
+        .is_some()
+}
+
+// Saves the image make makes as name.png in the output directory, if we're saving images.
+pub fn visualise(name: &str, make: impl FnOnce() -> Image) {
+    save_visualisation(&format!("{name}.png"), || make().to_png());
+}
+
+// The same for pictures that are easier to draw as SVG than pixel by pixel, saved as name.svg.
+pub fn visualise_svg(name: &str, make: impl FnOnce() -> String) {
+    save_visualisation(&format!("{name}.svg"), || make().into_bytes());
+}
+
+fn save_visualisation(file_name: &str, make: impl FnOnce() -> Vec<u8>) {
+    let directory = SAVING_TO
+        .lock()
+        .unwrap_or_else(|err| panic!("Image directory poisoned: {err}"))
+        .clone();
+    if let Some(directory) = directory {
+        let path = directory.join(file_name);
+        fs::write(&path, make())
+            .unwrap_or_else(|err| panic!("Failed to write {}: {err}", path.display()));
+        SAVED
+            .lock()
+            .unwrap_or_else(|err| panic!("Saved images poisoned: {err}"))
+            .push(path);
+    }
+}
+
+// Paths of the images saved since last asked.
+pub fn take_saved() -> Vec<PathBuf> {
+    std::mem::take(
+        &mut *SAVED
+            .lock()
+            .unwrap_or_else(|err| panic!("Saved images poisoned: {err}")),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Reads back a zlib stream of stored blocks, checking everything on the way.
+    fn unzlib_stored(stream: &[u8]) -> Vec<u8> {
+        assert_eq!([0x78, 0x01], stream[..2]);
+        assert_eq!(0, u16::from_be_bytes([stream[0], stream[1]]) % 31);
+        let mut data = vec![];
+        let mut position = 2;
+        loop {
+            let header = stream[position];
+            assert_eq!(0, header & 0b110); //Stored
+            let length = u16::from_le_bytes([stream[position + 1], stream[position + 2]]);
+            let complement = u16::from_le_bytes([stream[position + 3], stream[position + 4]]);
+            assert_eq!(!length, complement);
+            position += 5;
+            data.extend(&stream[position..position + length as usize]);
+            position += length as usize;
+            if header & 1 == 1 {
+                break;
+            }
+        }
+        assert_eq!(adler32(&data).to_be_bytes(), stream[position..]);
+        data
+    }
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(0xCBF43926, crc32(b"123456789"));
+        assert_eq!(0xAE426082, crc32(b"IEND")); //Every PNG ends with this
+        assert_eq!(0, crc32(b""));
+        assert_eq!(0x11E60398, adler32(b"Wikipedia"));
+        assert_eq!(1, adler32(b""));
+    }
+
+    #[test]
+    fn test_zlib_stored() {
+        for length in [0_usize, 1, 65535, 65536, 200_000] {
+            let data: Vec<u8> = (0..length).map(|i| (i * 7 % 251) as u8).collect();
+            let stream = zlib_stored(&data);
+            assert_eq!(data, unzlib_stored(&stream));
+            let blocks = length.max(1).div_ceil(65535);
+            assert_eq!(2 + 5 * blocks + length + 4, stream.len());
+        }
+    }
+
+    #[test]
+    fn test_ppm_and_png() {
+        let mut image = Image::new(2, 1, Colour::BLACK);
+        image.set(Point::new(1, 0), Colour::new(1, 2, 3));
+        assert_eq!(
+            b"P6\n2 1\n255\n\x00\x00\x00\x01\x02\x03".to_vec(),
+            image.to_ppm()
+        );
+
+        let png = image.to_png();
+        assert_eq!(PNG_SIGNATURE, png[..8]);
+        //IHDR: 13 bytes of header, CRC'd along with the chunk type.
+        assert_eq!(
+            [0, 0, 0, 13, b'I', b'H', b'D', b'R', 0, 0, 0, 2, 0, 0, 0, 1, 8, 2, 0, 0, 0],
+            png[8..29]
+        );
+        assert_eq!(crc32(&png[12..29]).to_be_bytes(), png[29..33]);
+        //IDAT: one row, with its filter byte.
+        let idat_length = u32::from_be_bytes(png[33..37].try_into().unwrap()) as usize;
+        assert_eq!(b"IDAT", &png[37..41]);
+        assert_eq!(
+            vec![0, 0, 0, 0, 1, 2, 3],
+            unzlib_stored(&png[41..41 + idat_length])
+        );
+        assert_eq!(
+            [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82],
+            png[png.len() - 12..]
+        );
+    }
+
+    #[test]
+    fn test_from_grids() {
+        let grid: Grid<char> = "#.\n.#".parse().unwrap();
+        let palette = |c: &char| {
+            if *c == '#' {
+                Colour::WHITE
+            } else {
+                Colour::BLACK
+            }
+        };
+        let image = Image::from_grid(&grid, palette);
+        assert_eq!((2, 2), (image.width(), image.height()));
+        assert_eq!(Colour::WHITE, image.get(Point::new(1, 1)));
+        assert_eq!(Colour::BLACK, image.get(Point::new(1, 0)));
+
+        let scaled = image.scaled(3);
+        assert_eq!((6, 6), (scaled.width(), scaled.height()));
+        assert_eq!(Colour::WHITE, scaled.get(Point::new(2, 2)));
+        assert_eq!(Colour::BLACK, scaled.get(Point::new(3, 2)));
+        assert_eq!(Colour::WHITE, scaled.get(Point::new(5, 5)));
+
+        let sparse: SparseGrid<char> = [(Point::new(-1, 3), '#'), (Point::new(1, 4), '.')]
+            .into_iter()
+            .collect();
+        let image = Image::from_sparse_grid(&sparse, Colour::RED, palette);
+        assert_eq!((3, 2), (image.width(), image.height()));
+        assert_eq!(Colour::WHITE, image.get(Point::new(0, 0)));
+        assert_eq!(Colour::BLACK, image.get(Point::new(2, 1)));
+        assert_eq!(Colour::RED, image.get(Point::new(1, 0)));
+        let empty = Image::from_sparse_grid(&SparseGrid::new(), Colour::RED, palette);
+        assert_eq!((0, 0), (empty.width(), empty.height()));
+    }
+}
@@ -1,76 +1,457 @@
+use crate::intcode_dialect::{InstructionSet, Operation};
+use crate::intcode_optimiser::{self, Optimised};
+use crate::intcode_profile::{self, Profile};
+use crate::intcode_replay::{Event, Transcript};
+use num::bigint::BigInt;
+use num::traits::{CheckedAdd, CheckedMul, FromPrimitive, One, ToPrimitive, Zero};
 use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+
+// The type of value held in each Intcode memory cell. The puzzles only need isize, but programs can produce values
+// that don't fit - anything integer-like from num will do, including BigInt. Addresses, op codes and modes are always
+// small, so those are converted to isize as they're needed.
+pub trait Word:
+    'static
+    + Clone
+    + fmt::Debug
+    + fmt::Display
+    + Ord
+    + Zero
+    + One
+    + CheckedAdd
+    + CheckedMul
+    + ToPrimitive
+    + FromPrimitive
+{
+}
+
+impl<T> Word for T where
+    T: 'static
+        + Clone
+        + fmt::Debug
+        + fmt::Display
+        + Ord
+        + Zero
+        + One
+        + CheckedAdd
+        + CheckedMul
+        + ToPrimitive
+        + FromPrimitive
+{
+}
+
+#[allow(dead_code)]
+pub type Program64 = Program<i64>;
+#[allow(dead_code)]
+pub type Program128 = Program<i128>;
+#[allow(dead_code)]
+pub type BigProgram = Program<BigInt>;
+
+#[derive(Clone, Debug)]
+pub struct Program<W = isize> {
+    program: Vec<W>,
+    memory: HashMap<isize, W>,
+    instruction_pointer: usize,
+    inputs: VecDeque<W>,
+    outputs: Vec<W>,
+    relative_base: isize,
+    profile: Option<Profile>,
+    history: Option<Vec<UndoStep<W>>>, //One entry per instruction executed, if recording
+    pending_writes: Vec<(isize, Option<W>)>, //Old values overwritten by the instruction being executed
+    consumed_input: Option<W>,               //Input taken by the instruction being executed
+    instruction_set: Arc<InstructionSet<W>>,
+    transcript: Option<Transcript<W>>,
+    optimised: Option<Arc<Optimised>>, //Set while running an optimised image - see intcode_optimiser
+}
 
+// Everything needed to put the program back the way it was before an instruction executed.
 #[derive(Clone, Debug)]
-pub struct Program {
-    program: Vec<isize>,
-    memory: HashMap<isize, isize>,
+struct UndoStep<W> {
     instruction_pointer: usize,
-    inputs: VecDeque<isize>,
-    outputs: Vec<isize>,
     relative_base: isize,
+    writes: Vec<(isize, Option<W>)>, //Address and old value, None if it was never set
+    consumed_input: Option<W>,
+    output_index: Option<usize>, //Where the output went, if there was one
 }
 
 impl Program {
     pub fn new(program: &[isize]) -> Program {
-        Program {
-            program: program.to_owned(),
+        Program::from_words(program)
+    }
+}
+
+impl<W: Word> Program<W> {
+    pub fn from_words(program: &[W]) -> Program<W> {
+        Program::loaded(program, intcode_optimiser::optimising())
+    }
+
+    // Runs an optimised version of program, whether or not the runner's optimising everything.
+    #[allow(dead_code)]
+    pub fn optimised(program: &[W]) -> Program<W> {
+        Program::loaded(program, true)
+    }
+
+    fn loaded(image: &[W], optimise: bool) -> Program<W> {
+        let mut program = Program {
+            program: vec![],
             memory: HashMap::new(),
             instruction_pointer: 0,
             inputs: VecDeque::new(),
             outputs: vec![],
             relative_base: 0,
+            profile: None,
+            history: None,
+            pending_writes: vec![],
+            consumed_input: None,
+            instruction_set: InstructionSet::shared_standard(),
+            transcript: None,
+            optimised: None,
+        };
+        program.load(image, optimise);
+        if intcode_profile::collecting() {
+            program.enable_profiling();
         }
+        program
+    }
+
+    // Build a program with a wider word type from a puzzle input (which always fits in an isize).
+    #[allow(dead_code)]
+    pub fn from_image(image: &[isize]) -> Program<W> {
+        Program::from_words(&image.iter().map(|&x| word_from(x)).collect::<Vec<W>>())
     }
 
+    // Runs until the program halts or needs input. Arithmetic isn't checked, so overflow behaves as it does for W
+    // (panic in debug, wrap in release for the primitive types). Panics on anything else that goes wrong.
     pub fn run(&mut self) -> ProgramResult {
-        let mut result = ProgramResult::Halted;
+        self.execute(false)
+            .unwrap_or_else(|err| panic!("Intcode program failed: {err}"))
+    }
+
+    // As for run, but arithmetic overflow is reported as an error instead of panicking or wrapping, as is any other
+    // failure (bad op codes, unrepresentable addresses, etc.).
+    #[allow(dead_code)]
+    pub fn run_checked(&mut self) -> Result<ProgramResult, IntcodeError> {
+        self.execute(true)
+    }
+
+    // Starts counting instruction executions. The counts survive initialize, so they accumulate over repeated runs of
+    // the same program.
+    #[allow(dead_code)]
+    pub fn enable_profiling(&mut self) {
+        let image = self
+            .program
+            .iter()
+            .map(|word| word.to_isize().unwrap_or(0))
+            .collect();
+        self.profile = Some(Profile::new(image, &self.instruction_set));
+    }
+
+    #[allow(dead_code)]
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    // Returns the counts so far and starts counting again from zero.
+    #[allow(dead_code)]
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profile.as_mut().map(|profile| profile.take_counts())
+    }
+
+    // Replaces the instructions the program understands - the full day 9 set unless this is called. Survives
+    // initialize.
+    #[allow(dead_code)]
+    pub fn set_instruction_set(&mut self, instruction_set: InstructionSet<W>) {
+        //The optimiser only knows what the standard instructions do.
+        if !instruction_set.is_standard() {
+            self.put_original_back(|| "the instruction set was changed".to_string());
+        }
+        if let Some(profile) = &mut self.profile {
+            profile.set_instruction_set(&instruction_set);
+        }
+        self.instruction_set = Arc::new(instruction_set);
+    }
+
+    #[allow(dead_code)]
+    pub fn instruction_set(&self) -> &InstructionSet<W> {
+        &self.instruction_set
+    }
+
+    fn execute(&mut self, checked: bool) -> Result<ProgramResult, IntcodeError> {
+        let result = self.execute_instructions(checked);
+        if intcode_profile::collecting() {
+            if let Some(profile) = self.take_profile() {
+                intcode_profile::collect(profile);
+            }
+        }
+        result
+    }
+
+    fn execute_instructions(&mut self, checked: bool) -> Result<ProgramResult, IntcodeError> {
         loop {
-            let mut instruction =
-                Instruction::new(&self.program[self.instruction_pointer..], &mut self.inputs);
-            match instruction.execute(self) {
-                InstructionResult::OkIncrement(increment) => {
-                    self.instruction_pointer += increment;
-                }
-                InstructionResult::OutputIncrement(output, increment) => {
-                    self.outputs.push(output);
-                    self.instruction_pointer += increment;
-                }
+            if let Some(result) = self.execute_instruction(checked)? {
+                return Ok(result);
+            }
+        }
+    }
+
+    // Executes a single instruction, returning the result if the program can't go any further.
+    fn execute_instruction(
+        &mut self,
+        checked: bool,
+    ) -> Result<Option<ProgramResult>, IntcodeError> {
+        let instruction_pointer = self.instruction_pointer;
+        let relative_base = self.relative_base;
+        self.pending_writes.clear();
+        self.consumed_input = None;
+        if self.breaks_optimisation(instruction_pointer) {
+            self.put_original_back(|| {
+                format!("the instruction at {instruction_pointer} overlaps rewritten code")
+            });
+        }
+        let program_fragment =
+            self.program
+                .get(self.instruction_pointer..)
+                .ok_or(IntcodeError::Truncated {
+                    instruction_pointer: self.instruction_pointer,
+                })?;
+        let instruction = Instruction::new(program_fragment, &self.instruction_set)
+            .map_err(|err| err.at(self.instruction_pointer))?;
+        let instruction_result = instruction
+            .execute(self, checked)
+            .map_err(|err| err.at(self.instruction_pointer))?;
+        if let Some(profile) = &mut self.profile {
+            //Custom instructions only count towards the address hits.
+            let op_code = match instruction.operation {
+                Operation::Builtin(op_code) => Some(op_code),
+                Operation::Custom(_) => None,
+            };
+            match instruction_result {
+                InstructionResult::AwaitInput => (),
                 InstructionResult::OkSet(address) => {
-                    self.instruction_pointer = address;
+                    profile.record(self.instruction_pointer, op_code);
+                    profile.record_jump(self.instruction_pointer, address);
                 }
-                InstructionResult::OkRelativeBaseIncrement(base_increment, pointer_increment) => {
-                    self.relative_base += base_increment;
-                    self.instruction_pointer += pointer_increment;
+                _ => profile.record(self.instruction_pointer, op_code),
+            }
+        }
+
+        let mut result = None;
+        let mut produced_output = None;
+        let output_index = self.outputs.len();
+        match instruction_result {
+            InstructionResult::OkIncrement(increment) => {
+                self.instruction_pointer += increment;
+            }
+            InstructionResult::OutputIncrement(output, increment) => {
+                if self.history.is_some() || self.transcript.is_some() {
+                    produced_output = Some(output.clone());
                 }
-                InstructionResult::AwaitInput => {
-                    result = ProgramResult::AwaitingInput;
-                    break;
+                self.outputs.push(output);
+                self.instruction_pointer += increment;
+            }
+            InstructionResult::OkSet(address) => {
+                self.instruction_pointer = address;
+            }
+            InstructionResult::OkRelativeBaseIncrement(base_increment, pointer_increment) => {
+                self.relative_base =
+                    self.relative_base
+                        .checked_add(base_increment)
+                        .ok_or_else(|| IntcodeError::Overflow {
+                            instruction_pointer,
+                            op_code: format!("{:?}", OpCode::RelativeBaseOffset),
+                        })?;
+                self.instruction_pointer += pointer_increment;
+            }
+            InstructionResult::AwaitInput => {
+                //Nothing happened, so nothing to undo - unless a custom instruction took some input before deciding
+                //to wait, in which case it goes back.
+                if let Some(input) = self.consumed_input.take() {
+                    self.inputs.push_front(input);
                 }
-                InstructionResult::Halt => {
-                    self.instruction_pointer += 1;
-                    break;
+                return Ok(Some(ProgramResult::AwaitingInput));
+            }
+            InstructionResult::Halt => {
+                self.instruction_pointer += 1;
+                result = Some(ProgramResult::Halted);
+            }
+        };
+
+        if let Some(transcript) = &mut self.transcript {
+            if let Some(input) = &self.consumed_input {
+                transcript.events.push(Event::Input(input.clone()));
+            }
+            if let Some(output) = &produced_output {
+                transcript.events.push(Event::Output(output.clone()));
+            }
+        }
+
+        if let Some(history) = &mut self.history {
+            history.push(UndoStep {
+                instruction_pointer,
+                relative_base,
+                writes: std::mem::take(&mut self.pending_writes),
+                consumed_input: self.consumed_input.take(),
+                output_index: produced_output.is_some().then_some(output_index),
+            });
+        }
+
+        Ok(result)
+    }
+
+    // Executes the next instruction only. Returns None if the program can carry on, otherwise why it can't.
+    #[allow(dead_code)]
+    pub fn step(&mut self) -> Option<ProgramResult> {
+        self.execute_instruction(false)
+            .unwrap_or_else(|err| panic!("Intcode program failed: {err}"))
+    }
+
+    // Starts recording what each instruction does so it can be undone with step_back and friends. Costs memory for
+    // every instruction executed from here on. Recording stops (and the history is lost) on initialize.
+    #[allow(dead_code)]
+    pub fn enable_history(&mut self) {
+        self.history = Some(vec![]);
+    }
+
+    #[allow(dead_code)]
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    // Starts a transcript of every input taken and output produced from here on (see intcode_replay). Undoing an
+    // instruction takes what it did back out of the transcript. The transcript stops on initialize.
+    #[allow(dead_code)]
+    pub fn start_transcript(&mut self) {
+        self.transcript = Some(Transcript::new());
+    }
+
+    #[allow(dead_code)]
+    pub fn transcript(&self) -> Option<&Transcript<W>> {
+        self.transcript.as_ref()
+    }
+
+    // Stops the transcript, returning it.
+    #[allow(dead_code)]
+    pub fn take_transcript(&mut self) -> Option<Transcript<W>> {
+        self.transcript.take()
+    }
+
+    // Number of instructions that can be undone - i.e. executed since recording started.
+    #[allow(dead_code)]
+    pub fn instruction_count(&self) -> usize {
+        self.history.as_ref().map_or(0, |history| history.len())
+    }
+
+    // Undoes the last instruction executed - memory, instruction pointer, relative base, inputs and outputs all go
+    // back to how they were. Outputs the caller has already removed aren't put back. Returns false if there's nothing
+    // to undo.
+    #[allow(dead_code)]
+    pub fn step_back(&mut self) -> bool {
+        let step = match self.history.as_mut().and_then(|history| history.pop()) {
+            Some(step) => step,
+            None => return false,
+        };
+
+        for (address, old_value) in step.writes.into_iter().rev() {
+            match old_value {
+                Some(value) => self.set_value_at(address, value),
+                None => {
+                    self.memory.remove(&address);
                 }
-            };
+            }
+        }
+        if let Some(transcript) = &mut self.transcript {
+            //Taken out in the reverse order they went in.
+            if step.output_index.is_some()
+                && matches!(transcript.events.last(), Some(Event::Output(_)))
+            {
+                transcript.events.pop();
+            }
+            if step.consumed_input.is_some()
+                && matches!(transcript.events.last(), Some(Event::Input(_)))
+            {
+                transcript.events.pop();
+            }
+        }
+        if let Some(input) = step.consumed_input {
+            self.inputs.push_front(input);
         }
+        if let Some(output_index) = step.output_index {
+            self.outputs.truncate(output_index);
+        }
+        self.instruction_pointer = step.instruction_pointer;
+        self.relative_base = step.relative_base;
+        true
+    }
 
-        result
+    // Goes back to just before the most recent instruction that wrote to address. Returns false, and leaves the
+    // program alone, if nothing recorded wrote there.
+    #[allow(dead_code)]
+    pub fn run_back_to_last_write(&mut self, address: isize) -> bool {
+        let last_write = self.history.as_ref().and_then(|history| {
+            history
+                .iter()
+                .rposition(|step| step.writes.iter().any(|(written, _)| *written == address))
+        });
+        match last_write {
+            Some(instruction_count) => self.rewind_to(instruction_count),
+            None => false,
+        }
+    }
+
+    // Goes back to the state after instruction_count instructions had been executed (counting from when recording
+    // started). Returns false, and leaves the program alone, if that's not in the recorded history.
+    #[allow(dead_code)]
+    pub fn rewind_to(&mut self, instruction_count: usize) -> bool {
+        if instruction_count > self.instruction_count() {
+            return false;
+        }
+        while self.instruction_count() > instruction_count {
+            self.step_back();
+        }
+        true
+    }
+
+    #[allow(dead_code)]
+    pub fn instruction_pointer(&self) -> usize {
+        self.instruction_pointer
+    }
+
+    #[allow(dead_code)]
+    pub fn relative_base(&self) -> isize {
+        self.relative_base
     }
 
-    pub fn set_noun_verb_inputs(&mut self, noun: isize, verb: isize) {
+    #[allow(dead_code)]
+    pub fn set_noun_verb_inputs(&mut self, noun: W, verb: W) {
         // Happy for this to panic - indices 1 and 2 should always be present
+        if self.guarded(1) || self.guarded(2) {
+            self.put_original_back(|| "the noun and verb were set".to_string());
+        }
         self.program[1] = noun;
         self.program[2] = verb;
     }
 
-    pub fn add_input(&mut self, input: isize) {
+    pub fn add_input(&mut self, input: W) {
         self.inputs.push_back(input);
     }
 
-    pub fn output_deprecated(&self) -> isize {
+    // Takes the next input for the instruction being executed, so that it can be put back if the instruction is
+    // undone. For use by instructions (including custom ones) only.
+    pub fn take_input(&mut self) -> Option<W> {
+        let input = self.inputs.pop_front();
+        if input.is_some() {
+            self.consumed_input = input.clone();
+        }
+        input
+    }
+
+    #[allow(dead_code)]
+    pub fn output_deprecated(&self) -> W {
         self.get_value_at(0)
     }
 
-    pub fn outputs(&self) -> &Vec<isize> {
+    pub fn outputs(&self) -> &Vec<W> {
         &self.outputs
     }
 
@@ -78,20 +459,26 @@ impl Program {
         self.outputs = vec![];
     }
 
-    pub fn remove_last_output(&mut self) -> Option<isize> {
+    pub fn remove_last_output(&mut self) -> Option<W> {
         self.outputs.pop()
     }
 
-    pub fn get_value_at(&self, index: isize) -> isize {
-        if (index as usize) < self.program.len() {
-            self.program[index as usize]
+    pub fn get_value_at(&self, index: isize) -> W {
+        if let Some(optimised) = self.optimised.as_ref().filter(|_| self.guarded(index)) {
+            //Reads as if it hadn't been optimised
+            word_from(optimised.original(index as usize))
+        } else if (index as usize) < self.program.len() {
+            self.program[index as usize].clone()
         } else {
             //Get it from memory
-            *self.memory.get(&index).unwrap_or(&0)
+            self.memory.get(&index).cloned().unwrap_or_else(W::zero)
         }
     }
 
-    pub fn set_value_at(&mut self, index: isize, value: isize) {
+    pub fn set_value_at(&mut self, index: isize, value: W) {
+        if self.guarded(index) {
+            self.put_original_back(|| format!("address {index} was set"));
+        }
         if (index as usize) < self.program.len() {
             self.program[index as usize] = value;
         } else {
@@ -100,13 +487,102 @@ impl Program {
         }
     }
 
-    pub fn initialize(&mut self, initial_values: &[isize]) {
-        initial_values.clone_into(&mut self.program);
+    // Every cell that has a value: the whole image, then anything written outside it, in address order.
+    #[allow(dead_code)]
+    pub fn cells(&self) -> impl Iterator<Item = (isize, &W)> {
+        let mut outside: Vec<(isize, &W)> = self
+            .memory
+            .iter()
+            .map(|(address, value)| (*address, value))
+            .collect();
+        outside.sort_by_key(|(address, _)| *address);
+        self.program
+            .iter()
+            .enumerate()
+            .map(|(address, value)| (address as isize, value))
+            .chain(outside)
+    }
+
+    pub fn initialize(&mut self, initial_values: &[W]) {
+        self.load(initial_values, intcode_optimiser::optimising());
         self.memory = HashMap::new();
         self.instruction_pointer = 0;
         self.inputs = VecDeque::new();
         self.outputs = vec![];
         self.relative_base = 0;
+        self.history = None;
+        self.transcript = None;
+    }
+
+    // Puts image in memory. Optimising only works on images that fit in an isize, which puzzle inputs always do.
+    fn load(&mut self, image: &[W], optimise: bool) {
+        self.optimised = None;
+        let cells: Option<Vec<isize>> = if optimise && self.instruction_set.is_standard() {
+            image.iter().map(|cell| cell.to_isize()).collect()
+        } else {
+            None
+        };
+        match cells {
+            Some(cells) => {
+                let optimised = intcode_optimiser::optimised(&cells);
+                self.program = optimised
+                    .image
+                    .iter()
+                    .map(|&cell| word_from(cell))
+                    .collect();
+                if !optimised.rewrites.is_empty() {
+                    self.optimised = Some(optimised);
+                }
+            }
+            None => image.clone_into(&mut self.program),
+        }
+    }
+
+    // The program's about to depend on something the optimiser changed, so put the original image back. Guarded cells
+    // are never written while the optimised image is in place, so the original values are still the right ones.
+    fn put_original_back(&mut self, reason: impl FnOnce() -> String) {
+        if let Some(optimised) = self.optimised.take() {
+            for address in optimised.guarded_cells() {
+                self.program[address] = word_from(optimised.original(address));
+            }
+            intcode_optimiser::record_put_back(reason());
+        }
+    }
+
+    // True if the instruction at address overlaps a rewritten one, other than by being it. Unless address is guarded
+    // its op code is the original one, so the size is right.
+    fn breaks_optimisation(&self, address: usize) -> bool {
+        self.optimised.as_ref().is_some_and(|optimised| {
+            let size = self
+                .program
+                .get(address)
+                .and_then(|op_code| op_code.to_isize())
+                .and_then(|op_code| self.instruction_set.get(op_code % 100))
+                .map_or(1, |definition| definition.num_parameters + 1);
+            optimised.breaks(address, size)
+        })
+    }
+
+    fn guarded(&self, index: isize) -> bool {
+        self.optimised
+            .as_ref()
+            .is_some_and(|optimised| index >= 0 && optimised.guards(index as usize))
+    }
+
+    // Writes made by instructions go through here so they can be undone.
+    fn write_value_at(&mut self, index: isize, value: W) {
+        if self.guarded(index) {
+            self.put_original_back(|| format!("address {index} was written"));
+        }
+        if self.history.is_some() {
+            let old_value = if (index as usize) < self.program.len() {
+                Some(self.program[index as usize].clone())
+            } else {
+                self.memory.get(&index).cloned()
+            };
+            self.pending_writes.push((index, old_value));
+        }
+        self.set_value_at(index, value);
     }
 }
 
@@ -116,74 +592,224 @@ pub enum ProgramResult {
     Halted,
 }
 
-struct Instruction {
-    op_code: OpCode,
-    parameters: Vec<Parameter>,
-    input: Option<isize>, //Only relevant if OpCode is Input
+// Everything that can go wrong running a program in checked mode. The instruction pointer is that of the instruction
+// that failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IntcodeError {
+    Overflow {
+        instruction_pointer: usize,
+        op_code: String,
+    },
+    InvalidOpCode {
+        instruction_pointer: usize,
+        op_code: String,
+    },
+    InvalidMode {
+        instruction_pointer: usize,
+        mode: isize,
+    },
+    InvalidAddress {
+        instruction_pointer: usize,
+        address: String,
+    },
+    //The instruction runs off the end of the program, or starts past it.
+    Truncated {
+        instruction_pointer: usize,
+    },
+    //Raised deliberately by a custom instruction.
+    #[allow(dead_code)]
+    Trap {
+        instruction_pointer: usize,
+        message: String,
+    },
 }
 
-impl Instruction {
-    //Instructions know how to build themselves from the program fragment starting at the beginning
-    //of the instruction (the number of parameters to extract depends on the op code which is
-    //encapsulated in the Instruction struct/impl).
-    pub fn new(program_fragment: &[isize], inputs: &mut VecDeque<isize>) -> Instruction {
-        use OpCode::*;
-        let op_code = OpCode::try_from(program_fragment[0]).unwrap();
-        let parameters: Vec<Parameter> =
-            Instruction::extract_parameters(program_fragment, &op_code);
-
-        let input = match op_code {
-            Input => {
-                if !inputs.is_empty() {
-                    Some(inputs.pop_front().expect("Expected sufficient inputs!"))
-                } else {
-                    None
-                }
-            }
-            _ => None,
-        };
+impl IntcodeError {
+    // Errors are raised by instructions, which don't know where they live. The program fills the instruction pointer
+    // in on the way out.
+    fn at(self, instruction_pointer: usize) -> IntcodeError {
+        use IntcodeError::*;
+        match self {
+            Overflow { op_code, .. } => Overflow {
+                instruction_pointer,
+                op_code,
+            },
+            InvalidOpCode { op_code, .. } => InvalidOpCode {
+                instruction_pointer,
+                op_code,
+            },
+            InvalidMode { mode, .. } => InvalidMode {
+                instruction_pointer,
+                mode,
+            },
+            InvalidAddress { address, .. } => InvalidAddress {
+                instruction_pointer,
+                address,
+            },
+            Truncated { .. } => Truncated {
+                instruction_pointer,
+            },
+            Trap { message, .. } => Trap {
+                instruction_pointer,
+                message,
+            },
+        }
+    }
+}
 
-        Instruction {
-            op_code,
-            parameters,
-            input,
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use IntcodeError::*;
+        match self {
+            Overflow {
+                instruction_pointer,
+                op_code,
+            } => write!(f, "{op_code} overflowed at address {instruction_pointer}"),
+            InvalidOpCode {
+                instruction_pointer,
+                op_code,
+            } => write!(
+                f,
+                "Invalid OpCode '{op_code}' at address {instruction_pointer}"
+            ),
+            InvalidMode {
+                instruction_pointer,
+                mode,
+            } => write!(f, "Invalid Mode '{mode}' at address {instruction_pointer}"),
+            InvalidAddress {
+                instruction_pointer,
+                address,
+            } => write!(
+                f,
+                "Invalid address {address} at address {instruction_pointer}"
+            ),
+            Truncated {
+                instruction_pointer,
+            } => write!(
+                f,
+                "Instruction at address {instruction_pointer} runs past the end of the program"
+            ),
+            Trap {
+                instruction_pointer,
+                message,
+            } => write!(f, "Trap at address {instruction_pointer}: {message}"),
         }
     }
+}
 
-    fn extract_parameters(program_fragment: &[isize], op_code: &OpCode) -> Vec<Parameter> {
-        use OpCode::*;
+impl std::error::Error for IntcodeError {}
 
-        let num_parameters = match op_code {
-            Input | Output | RelativeBaseOffset => 1,
-            JumpIfTrue | JumpIfFalse => 2,
-            Add | Multiply | LessThan | Equals => 3,
-            Halt => 0,
+fn word_from<W: Word>(value: isize) -> W {
+    W::from_isize(value).expect("Word type too small to hold an isize")
+}
+
+// Converts a word that's being used as an address, offset or op code to an isize.
+fn word_to_isize<W: Word>(value: &W) -> Result<isize, IntcodeError> {
+    value
+        .to_isize()
+        .ok_or_else(|| IntcodeError::InvalidAddress {
+            instruction_pointer: 0,
+            address: value.to_string(),
+        })
+}
+
+struct Instruction<W> {
+    operation: Operation<W>,
+    parameters: Vec<Parameter<W>>,
+}
+
+impl<W: Word> Instruction<W> {
+    //Instructions know how to build themselves from the program fragment starting at the beginning
+    //of the instruction (the number of parameters to extract depends on the op code, which is
+    //looked up in the instruction set).
+    pub fn new(
+        program_fragment: &[W],
+        instruction_set: &InstructionSet<W>,
+    ) -> Result<Instruction<W>, IntcodeError> {
+        let raw_op_code = program_fragment.first().ok_or(IntcodeError::Truncated {
+            instruction_pointer: 0,
+        })?;
+        let raw_op_code = raw_op_code
+            .to_isize()
+            .ok_or_else(|| IntcodeError::InvalidOpCode {
+                instruction_pointer: 0,
+                op_code: raw_op_code.to_string(),
+            })?;
+        let invalid_op_code = || IntcodeError::InvalidOpCode {
+            instruction_pointer: 0,
+            op_code: raw_op_code.to_string(),
+        };
+        //Nearly every program runs the standard set, which only has the builtins - no need to look those up.
+        let (operation, num_parameters) = if instruction_set.is_standard() {
+            let op_code = OpCode::try_from(raw_op_code % 100).map_err(|_| invalid_op_code())?;
+            (Operation::Builtin(op_code), op_code.num_parameters())
+        } else {
+            let definition = instruction_set
+                .get(raw_op_code % 100)
+                .ok_or_else(invalid_op_code)?;
+            (definition.operation.clone(), definition.num_parameters)
         };
+        let parameters: Vec<Parameter<W>> = Instruction::extract_parameters(
+            program_fragment,
+            raw_op_code,
+            num_parameters,
+            instruction_set,
+        )?;
+
+        Ok(Instruction {
+            operation,
+            parameters,
+        })
+    }
 
-        if let Halt = op_code {
-            vec![]
+    fn extract_parameters(
+        program_fragment: &[W],
+        raw_op_code: isize,
+        num_parameters: usize,
+        instruction_set: &InstructionSet<W>,
+    ) -> Result<Vec<Parameter<W>>, IntcodeError> {
+        if num_parameters == 0 {
+            Ok(vec![])
+        } else if program_fragment.len() <= num_parameters {
+            Err(IntcodeError::Truncated {
+                instruction_pointer: 0,
+            })
         } else {
             std::iter::repeat_with({
-                let mut mode_digits = program_fragment[0] / 100;
+                let mut mode_digits = raw_op_code / 100;
                 move || {
                     let mode = mode_digits % 10;
                     mode_digits /= 10;
-                    Mode::try_from(mode).unwrap()
+                    Mode::try_from(mode)
+                        .ok()
+                        .filter(|mode| instruction_set.allows_mode(*mode))
+                        .ok_or(IntcodeError::InvalidMode {
+                            instruction_pointer: 0,
+                            mode,
+                        })
                 }
             })
             .take(num_parameters)
             .zip(program_fragment[1..(num_parameters + 1)].iter())
-            .map(Parameter::new)
+            .map(|(mode, value)| mode.map(|mode| Parameter::new((mode, value))))
             .collect()
         }
     }
 
     // Operate performs the relevant operation on operands and returns Ok or Halt
-    pub fn execute(&mut self, program: &mut Program) -> InstructionResult {
+    pub fn execute(
+        &self,
+        program: &mut Program<W>,
+        checked: bool,
+    ) -> Result<InstructionResult<W>, IntcodeError> {
         use OpCode::*;
-        match self.op_code {
-            Add => self.do_op(program, Add),
-            Multiply => self.do_op(program, Multiply),
+        let op_code = match &self.operation {
+            Operation::Builtin(op_code) => *op_code,
+            Operation::Custom(operation) => return operation(program, &self.parameters),
+        };
+        match op_code {
+            Add => self.do_op(program, Add, checked),
+            Multiply => self.do_op(program, Multiply, checked),
             Input => self.do_input(program),
             Output => self.do_output(program),
             JumpIfTrue => self.do_jump(program, true),
@@ -191,99 +817,129 @@ impl Instruction {
             LessThan => self.do_comparison(program, LessThan),
             Equals => self.do_comparison(program, Equals),
             RelativeBaseOffset => self.do_relative_base(program),
-            Halt => InstructionResult::Halt,
+            Halt => Ok(InstructionResult::Halt),
         }
     }
 
-    fn do_op(&mut self, program: &mut Program, op: OpCode) -> InstructionResult {
-        let mut output_location = self.parameters.last().unwrap().value;
-        if let Mode::Relative = self.parameters.last().unwrap().mode {
-            output_location += program.relative_base;
-        }
+    fn do_op(
+        &self,
+        program: &mut Program<W>,
+        op: OpCode,
+        checked: bool,
+    ) -> Result<InstructionResult<W>, IntcodeError> {
+        let output_location = self.parameters.last().unwrap().address(program)?;
         let operands = self.parameters[0..(self.parameters.len() - 1)]
             .iter()
-            .map(|x| x.mode_adjusted_value(program));
-        program.set_value_at(
-            output_location,
-            match op {
-                OpCode::Add => operands.sum(),
-                OpCode::Multiply => operands.product(),
-                _ => panic!("Only currently valid for Add and Multiply"),
-            },
-        );
+            .map(|x| x.mode_adjusted_value(program))
+            .collect::<Result<Vec<W>, IntcodeError>>()?;
+        let overflow = || IntcodeError::Overflow {
+            instruction_pointer: 0,
+            op_code: format!("{:?}", op),
+        };
+        let value = match (op, checked) {
+            (OpCode::Add, false) => operands.into_iter().fold(W::zero(), |acc, x| acc + x),
+            (OpCode::Multiply, false) => operands.into_iter().fold(W::one(), |acc, x| acc * x),
+            (OpCode::Add, true) => operands
+                .iter()
+                .try_fold(W::zero(), |acc, x| acc.checked_add(x))
+                .ok_or_else(overflow)?,
+            (OpCode::Multiply, true) => operands
+                .iter()
+                .try_fold(W::one(), |acc, x| acc.checked_mul(x))
+                .ok_or_else(overflow)?,
+            _ => panic!("Only currently valid for Add and Multiply"),
+        };
+        program.write_value_at(output_location, value);
         // Parameters vec currently only contains the indices of program elements to add together;
         // the instruction also contained the op code and the output index, so need to add 2 to
         // increment the instruction pointer by the correct amount
-        InstructionResult::OkIncrement(self.parameters.len() + 1)
+        Ok(InstructionResult::OkIncrement(self.parameters.len() + 1))
     }
 
-    fn do_input(&mut self, program: &mut Program) -> InstructionResult {
-        let mut output_location = self.parameters[0].value;
-        if let Mode::Relative = self.parameters[0].mode {
-            output_location += program.relative_base;
-        }
-        match self.input {
+    fn do_input(&self, program: &mut Program<W>) -> Result<InstructionResult<W>, IntcodeError> {
+        let output_location = self.parameters[0].address(program)?;
+        match program.take_input() {
             Some(x) => {
-                program.set_value_at(output_location, x);
-                InstructionResult::OkIncrement(self.parameters.len() + 1)
+                program.write_value_at(output_location, x);
+                Ok(InstructionResult::OkIncrement(self.parameters.len() + 1))
             }
-            None => InstructionResult::AwaitInput,
+            None => Ok(InstructionResult::AwaitInput),
         }
     }
 
-    fn do_output(&mut self, program: &mut Program) -> InstructionResult {
-        let value = self.parameters[0].mode_adjusted_value(program);
-        InstructionResult::OutputIncrement(value, self.parameters.len() + 1)
+    fn do_output(&self, program: &mut Program<W>) -> Result<InstructionResult<W>, IntcodeError> {
+        let value = self.parameters[0].mode_adjusted_value(program)?;
+        Ok(InstructionResult::OutputIncrement(
+            value,
+            self.parameters.len() + 1,
+        ))
     }
 
-    fn do_relative_base(&mut self, program: &mut Program) -> InstructionResult {
-        let value = self.parameters[0].mode_adjusted_value(program);
-        InstructionResult::OkRelativeBaseIncrement(value, self.parameters.len() + 1)
+    fn do_relative_base(
+        &self,
+        program: &mut Program<W>,
+    ) -> Result<InstructionResult<W>, IntcodeError> {
+        let value = word_to_isize(&self.parameters[0].mode_adjusted_value(program)?)?;
+        Ok(InstructionResult::OkRelativeBaseIncrement(
+            value,
+            self.parameters.len() + 1,
+        ))
     }
 
-    fn do_jump(&mut self, program: &mut Program, jump_if_true: bool) -> InstructionResult {
-        let do_jump = self.parameters[0].mode_adjusted_value(program);
-
-        if (jump_if_true && do_jump != 0) || (!jump_if_true && do_jump == 0) {
-            let jump_to = self.parameters[1].mode_adjusted_value(program);
-            return InstructionResult::OkSet(jump_to as usize);
+    fn do_jump(
+        &self,
+        program: &mut Program<W>,
+        jump_if_true: bool,
+    ) -> Result<InstructionResult<W>, IntcodeError> {
+        let do_jump = self.parameters[0].mode_adjusted_value(program)?;
+
+        if (jump_if_true && !do_jump.is_zero()) || (!jump_if_true && do_jump.is_zero()) {
+            let jump_to = self.parameters[1].mode_adjusted_value(program)?;
+            let address = jump_to
+                .to_usize()
+                .ok_or_else(|| IntcodeError::InvalidAddress {
+                    instruction_pointer: 0,
+                    address: jump_to.to_string(),
+                })?;
+            return Ok(InstructionResult::OkSet(address));
         }
 
-        InstructionResult::OkIncrement(self.parameters.len() + 1)
+        Ok(InstructionResult::OkIncrement(self.parameters.len() + 1))
     }
 
-    fn do_comparison(&mut self, program: &mut Program, op_code: OpCode) -> InstructionResult {
-        let mut output_location = self.parameters.last().unwrap().value;
-        if let Mode::Relative = self.parameters.last().unwrap().mode {
-            output_location += program.relative_base;
-        }
-        let first = self.parameters[0].mode_adjusted_value(program);
-        let second = self.parameters[1].mode_adjusted_value(program);
+    fn do_comparison(
+        &self,
+        program: &mut Program<W>,
+        op_code: OpCode,
+    ) -> Result<InstructionResult<W>, IntcodeError> {
+        let output_location = self.parameters.last().unwrap().address(program)?;
+        let first = self.parameters[0].mode_adjusted_value(program)?;
+        let second = self.parameters[1].mode_adjusted_value(program)?;
 
         match op_code {
             OpCode::LessThan => {
                 if first < second {
-                    program.set_value_at(output_location, 1);
+                    program.write_value_at(output_location, W::one());
                 } else {
-                    program.set_value_at(output_location, 0);
+                    program.write_value_at(output_location, W::zero());
                 }
             }
             OpCode::Equals => {
                 if first == second {
-                    program.set_value_at(output_location, 1);
+                    program.write_value_at(output_location, W::one());
                 } else {
-                    program.set_value_at(output_location, 0);
+                    program.write_value_at(output_location, W::zero());
                 }
             }
             _ => panic!("Bad op code {:?}", op_code),
         }
 
-        InstructionResult::OkIncrement(self.parameters.len() + 1)
+        Ok(InstructionResult::OkIncrement(self.parameters.len() + 1))
     }
 }
 
-#[derive(Debug)]
-enum OpCode {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OpCode {
     Add,
     Multiply,
     Input,
@@ -320,17 +976,201 @@ impl TryFrom<isize> for OpCode {
     }
 }
 
-pub enum InstructionResult {
+impl OpCode {
+    pub const ALL: [OpCode; 10] = [
+        OpCode::Add,
+        OpCode::Multiply,
+        OpCode::Input,
+        OpCode::Output,
+        OpCode::JumpIfTrue,
+        OpCode::JumpIfFalse,
+        OpCode::LessThan,
+        OpCode::Equals,
+        OpCode::RelativeBaseOffset,
+        OpCode::Halt,
+    ];
+
+    // The number that selects this op code (the last two digits of an instruction).
+    pub fn code(&self) -> isize {
+        use OpCode::*;
+        match self {
+            Add => 1,
+            Multiply => 2,
+            Input => 3,
+            Output => 4,
+            JumpIfTrue => 5,
+            JumpIfFalse => 6,
+            LessThan => 7,
+            Equals => 8,
+            RelativeBaseOffset => 9,
+            Halt => 99,
+        }
+    }
+
+    pub fn num_parameters(&self) -> usize {
+        use OpCode::*;
+        match self {
+            Input | Output | RelativeBaseOffset => 1,
+            JumpIfTrue | JumpIfFalse => 2,
+            Add | Multiply | LessThan | Equals => 3,
+            Halt => 0,
+        }
+    }
+
+    // True if the last parameter is an address the instruction writes to.
+    pub fn writes(&self) -> bool {
+        use OpCode::*;
+        matches!(self, Add | Multiply | Input | LessThan | Equals)
+    }
+
+    pub fn is_jump(&self) -> bool {
+        matches!(self, OpCode::JumpIfTrue | OpCode::JumpIfFalse)
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        use OpCode::*;
+        match self {
+            Add => "add",
+            Multiply => "mul",
+            Input => "in",
+            Output => "out",
+            JumpIfTrue => "jnz",
+            JumpIfFalse => "jz",
+            LessThan => "lt",
+            Equals => "eq",
+            RelativeBaseOffset => "arb",
+            Halt => "halt",
+        }
+    }
+}
+
+// What a decoded instruction does. Tools can't see inside a custom instruction, so all they know is its name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodedOperation {
+    Builtin(OpCode),
+    Custom { code: isize, mnemonic: String },
+}
+
+// An instruction decoded from a program image without running it, for tools that need to look at code rather than
+// execute it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodedInstruction {
+    pub address: usize,
+    pub operation: DecodedOperation,
+    pub parameters: Vec<(Mode, isize)>,
+}
+
+impl DecodedInstruction {
+    // None for a custom instruction.
+    pub fn op_code(&self) -> Option<OpCode> {
+        match self.operation {
+            DecodedOperation::Builtin(op_code) => Some(op_code),
+            DecodedOperation::Custom { .. } => None,
+        }
+    }
+
+    // The number that selects the operation (the last two digits of the instruction).
+    pub fn code(&self) -> isize {
+        match self.operation {
+            DecodedOperation::Builtin(op_code) => op_code.code(),
+            DecodedOperation::Custom { code, .. } => code,
+        }
+    }
+
+    pub fn mnemonic(&self) -> &str {
+        match &self.operation {
+            DecodedOperation::Builtin(op_code) => op_code.mnemonic(),
+            DecodedOperation::Custom { mnemonic, .. } => mnemonic,
+        }
+    }
+
+    // Only ever true for the built in jumps - a custom instruction could jump, but there's no knowing.
+    pub fn is_jump(&self) -> bool {
+        self.op_code().is_some_and(|op_code| op_code.is_jump())
+    }
+
+    // As for is_jump, only the built in instructions are known to write.
+    pub fn writes(&self) -> bool {
+        self.op_code().is_some_and(|op_code| op_code.writes())
+    }
+
+    // Number of memory cells the instruction occupies
+    pub fn size(&self) -> usize {
+        self.parameters.len() + 1
+    }
+
+    // Address of the instruction that follows this one in memory
+    pub fn next_address(&self) -> usize {
+        self.address + self.size()
+    }
+}
+
+impl fmt::Display for DecodedInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>5}: {}", self.address, self.mnemonic())?;
+        for (mode, value) in &self.parameters {
+            match mode {
+                Mode::Position => write!(f, " [{}]", value)?,
+                Mode::Immediate => write!(f, " {}", value)?,
+                Mode::Relative => write!(f, " [rb{:+}]", value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Decodes the instruction at address in image, as a program with instruction_set would run it. Fails if the op code or
+// any of the modes aren't in the instruction set, or the instruction runs off the end of the image.
+pub fn decode<W: Word>(
+    image: &[isize],
+    address: usize,
+    instruction_set: &InstructionSet<W>,
+) -> Result<DecodedInstruction, String> {
+    let raw_op_code = *image
+        .get(address)
+        .ok_or_else(|| format!("Address {} is outside the image", address))?;
+    let code = raw_op_code % 100;
+    let definition = instruction_set
+        .get(code)
+        .ok_or_else(|| format!("Invalid OpCode '{}'", code))?;
+    let values = image
+        .get(address + 1..address + 1 + definition.num_parameters)
+        .ok_or_else(|| format!("Instruction at {} runs off the end of the image", address))?;
+    let mut mode_digits = raw_op_code / 100;
+    let mut parameters = vec![];
+    for value in values {
+        let mode = Mode::try_from(mode_digits % 10)?;
+        if !instruction_set.allows_mode(mode) {
+            return Err(format!("Invalid Mode '{}'", mode_digits % 10));
+        }
+        parameters.push((mode, *value));
+        mode_digits /= 10;
+    }
+
+    Ok(DecodedInstruction {
+        address,
+        operation: match &definition.operation {
+            Operation::Builtin(op_code) => DecodedOperation::Builtin(*op_code),
+            Operation::Custom(_) => DecodedOperation::Custom {
+                code,
+                mnemonic: definition.mnemonic.clone(),
+            },
+        },
+        parameters,
+    })
+}
+
+pub enum InstructionResult<W> {
     OkIncrement(usize), //Contains the increment to the instruction pointer
     OkSet(usize),       //Contains absolute value for instruction pointer
-    OutputIncrement(isize, usize),
+    OutputIncrement(W, usize),
     AwaitInput,
     OkRelativeBaseIncrement(isize, usize),
     Halt,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-enum Mode {
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Mode {
     Position,
     Immediate,
     Relative,
@@ -351,26 +1191,58 @@ impl TryFrom<isize> for Mode {
     }
 }
 
-struct Parameter {
+// A parameter of the instruction being executed, as passed to custom instructions.
+pub struct Parameter<W> {
     mode: Mode,
-    value: isize,
+    value: W,
 }
 
-impl Parameter {
-    fn new(parameter: (Mode, &isize)) -> Parameter {
+impl<W: Word> Parameter<W> {
+    fn new(parameter: (Mode, &W)) -> Parameter<W> {
         Parameter {
             mode: parameter.0,
-            value: *parameter.1,
+            value: parameter.1.clone(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    // The address this parameter refers to, for parameters that are written to. Memory starts at 0, so an address that
+    // works out negative (or off the end of isize) is an error rather than a cell nobody can otherwise reach.
+    pub fn address(&self, program: &Program<W>) -> Result<isize, IntcodeError> {
+        let invalid = |address: String| IntcodeError::InvalidAddress {
+            instruction_pointer: 0,
+            address,
+        };
+        let mut address = word_to_isize(&self.value)?;
+        if let Mode::Relative = self.mode {
+            address = address
+                .checked_add(program.relative_base)
+                .ok_or_else(|| invalid(format!("{} + {}", program.relative_base, address)))?;
+        }
+        if address < 0 {
+            return Err(invalid(address.to_string()));
         }
+        Ok(address)
     }
 
-    fn mode_adjusted_value(&self, program: &Program) -> isize {
+    pub fn mode_adjusted_value(&self, program: &Program<W>) -> Result<W, IntcodeError> {
         match self.mode {
-            Mode::Position => program.get_value_at(self.value),
-            Mode::Immediate => self.value,
-            Mode::Relative => program.get_value_at(self.value + program.relative_base),
+            Mode::Position | Mode::Relative => Ok(program.get_value_at(self.address(program)?)),
+            Mode::Immediate => Ok(self.value.clone()),
         }
     }
+
+    // Writes to the address this parameter refers to, in a way that can be undone.
+    #[allow(dead_code)]
+    pub fn write(&self, program: &mut Program<W>, value: W) -> Result<(), IntcodeError> {
+        let address = self.address(program)?;
+        program.write_value_at(address, value);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -512,4 +1384,211 @@ mod tests {
         program.run();
         assert_eq!(1001, *program.outputs().last().unwrap());
     }
+
+    #[test]
+    fn test_wide_words() {
+        //Squares 2^40 and outputs it - too big for 64 bits.
+        let image = vec![1102, 1099511627776, 1099511627776, 7, 4, 7, 99, 0];
+
+        let mut program = Program128::from_image(&image);
+        program.run();
+        assert_eq!(1 << 80, *program.outputs().last().unwrap());
+
+        let mut program = BigProgram::from_image(&image);
+        program.run();
+        assert_eq!(BigInt::from(2).pow(80), *program.outputs().last().unwrap());
+
+        //Multiply up a big number one input at a time - 3^100.
+        let mut program = BigProgram::from_image(&[
+            3, 100, 1002, 101, 3, 101, 1001, 100, -1, 100, 1005, 100, 2, 4, 101, 99,
+        ]);
+        program.set_value_at(101, BigInt::one());
+        program.add_input(BigInt::from(100));
+        assert_eq!(ProgramResult::Halted, program.run_checked().unwrap());
+        assert_eq!(BigInt::from(3).pow(100), program.outputs()[0]);
+    }
+
+    #[test]
+    fn test_checked_overflow() {
+        let image = vec![1102, 1099511627776, 1099511627776, 7, 4, 7, 99, 0];
+        let mut program = Program64::from_image(&image);
+        assert_eq!(
+            Err(IntcodeError::Overflow {
+                instruction_pointer: 0,
+                op_code: "Multiply".to_string()
+            }),
+            program.run_checked()
+        );
+
+        //Overflow further into the program, in an add
+        let mut program = Program::new(&[1101, 1, 1, 9, 1001, 10, 1, 10, 99, 0, 0]);
+        program.set_value_at(10, isize::MAX);
+        assert_eq!(
+            Err(IntcodeError::Overflow {
+                instruction_pointer: 4,
+                op_code: "Add".to_string()
+            }),
+            program.run_checked()
+        );
+
+        let mut program = Program::new(&[1101, 1, 2, 5, 99, 0]);
+        assert_eq!(Ok(ProgramResult::Halted), program.run_checked());
+        assert_eq!(3, program.get_value_at(5));
+
+        let mut program = Program::new(&[1101, 1, 2, 5, 42]);
+        assert_eq!(
+            Err(IntcodeError::InvalidOpCode {
+                instruction_pointer: 4,
+                op_code: "42".to_string()
+            }),
+            program.run_checked()
+        );
+
+        //An image that stops partway through an instruction, or jumps past its end.
+        let mut program = Program::new(&[1101, 1, 2, 5, 1, 0]);
+        assert_eq!(
+            Err(IntcodeError::Truncated {
+                instruction_pointer: 4
+            }),
+            program.run_checked()
+        );
+        let mut program = Program::new(&[1105, 1, 20]);
+        assert_eq!(
+            Err(IntcodeError::Truncated {
+                instruction_pointer: 20
+            }),
+            program.run_checked()
+        );
+        let mut program = Program::new(&[1101, 1, 2, 0]);
+        assert_eq!(
+            Err(IntcodeError::Truncated {
+                instruction_pointer: 4
+            }),
+            program.run_checked()
+        );
+    }
+
+    #[test]
+    fn test_checked_addresses() {
+        //Position mode reading from a negative address.
+        let mut program = Program::new(&[1, -1, 0, 0, 99]);
+        assert_eq!(
+            Err(IntcodeError::InvalidAddress {
+                instruction_pointer: 0,
+                address: "-1".to_string()
+            }),
+            program.run_checked()
+        );
+
+        //Relative mode writing below zero.
+        let mut program = Program::new(&[109, 2, 21101, 1, 2, -3, 99]);
+        assert_eq!(
+            Err(IntcodeError::InvalidAddress {
+                instruction_pointer: 2,
+                address: "-1".to_string()
+            }),
+            program.run_checked()
+        );
+
+        //Relative mode addresses that don't fit in an isize.
+        let mut program = Program::new(&[109, isize::MAX, 204, 1, 99]);
+        assert_eq!(
+            Err(IntcodeError::InvalidAddress {
+                instruction_pointer: 2,
+                address: format!("{} + 1", isize::MAX)
+            }),
+            program.run_checked()
+        );
+
+        //And a relative base that doesn't either.
+        let mut program = Program::new(&[109, isize::MAX, 109, 1, 99]);
+        assert_eq!(
+            Err(IntcodeError::Overflow {
+                instruction_pointer: 2,
+                op_code: "RelativeBaseOffset".to_string()
+            }),
+            program.run_checked()
+        );
+    }
+
+    #[test]
+    fn test_reverse_execution() {
+        //The day 9 quine uses relative mode and memory beyond the end of the program.
+        let image = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        let mut program = Program::new(&image);
+        program.enable_history();
+        assert_eq!(ProgramResult::Halted, program.run());
+        assert_eq!(image, *program.outputs());
+        let instructions = program.instruction_count();
+        assert!(instructions > 16);
+
+        //Back one step - before the halt.
+        assert!(program.step_back());
+        assert_eq!(15, program.instruction_pointer());
+        assert_eq!(ProgramResult::Halted, program.run());
+        assert_eq!(instructions, program.instruction_count());
+
+        //All the way back to the start.
+        assert!(program.rewind_to(0));
+        assert_eq!(0, program.instruction_pointer());
+        assert_eq!(0, program.relative_base());
+        assert!(program.outputs().is_empty());
+        assert_eq!(0, program.get_value_at(100));
+        assert!(!program.step_back());
+        assert!(!program.rewind_to(1));
+
+        //And it runs the same again.
+        assert_eq!(ProgramResult::Halted, program.run());
+        assert_eq!(image, *program.outputs());
+    }
+
+    #[test]
+    fn test_step_and_rewind_inputs() {
+        //Counts down from the input, outputting each value.
+        let mut program = Program::new(&[3, 12, 4, 12, 1001, 12, -1, 12, 1005, 12, 2, 99, 0]);
+        program.enable_history();
+        assert_eq!(Some(ProgramResult::AwaitingInput), program.step());
+        assert_eq!(0, program.instruction_count());
+        program.add_input(3);
+        assert_eq!(None, program.step());
+        assert_eq!(3, program.get_value_at(12));
+        assert_eq!(ProgramResult::Halted, program.run());
+        assert_eq!(vec![3, 2, 1], *program.outputs());
+
+        //Back to just before the last write to address 12, by the final add.
+        assert!(program.run_back_to_last_write(12));
+        assert_eq!(4, program.instruction_pointer());
+        assert_eq!(1, program.get_value_at(12));
+        assert_eq!(vec![3, 2, 1], *program.outputs());
+        assert_eq!(None, program.step());
+        assert_eq!(0, program.get_value_at(12));
+        assert!(!program.run_back_to_last_write(11));
+
+        //Undoing the input puts it back in the queue.
+        assert!(program.rewind_to(0));
+        assert_eq!(0, program.get_value_at(12));
+        assert_eq!(ProgramResult::Halted, program.run());
+        assert_eq!(vec![3, 2, 1], *program.outputs());
+    }
+
+    #[test]
+    fn test_step_back_over_removed_output() {
+        //Outputs 7 twice - the outputs can't be told apart by value.
+        let mut program = Program::new(&[104, 7, 104, 7, 99]);
+        program.enable_history();
+        assert_eq!(ProgramResult::Halted, program.run());
+        assert_eq!(Some(7), program.remove_last_output());
+
+        //Undoing the second output leaves the first alone, as the caller's already taken the second.
+        assert!(program.step_back());
+        assert!(program.step_back());
+        assert_eq!(2, program.instruction_pointer());
+        assert_eq!(vec![7], *program.outputs());
+
+        //Undoing the first one does take it away.
+        assert!(program.step_back());
+        assert!(program.outputs().is_empty());
+    }
 }
@@ -0,0 +1,491 @@
+// Static analysis of Intcode images - builds a control flow graph without running the program, works out which code
+// is reachable and spots instructions that write into code. Not used by the solutions themselves; it's for poking
+// at the puzzle programs (e.g. dump day 21's springdroid host with to_dot and render it with Graphviz).
+#![allow(dead_code)]
+
+use crate::intcode::{self, DecodedInstruction, Mode, OpCode, Word};
+use crate::intcode_dialect::InstructionSet;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeKind {
+    FallThrough, //On to the next instruction in memory
+    Jump,        //Jump to an immediate mode target
+    Unresolved, //Jump to a position mode target, resolved using the initial image - it may differ at run time
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Edge {
+    pub target: usize,
+    pub kind: EdgeKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: usize,
+    pub end: usize, //One past the last cell of the last instruction
+    pub instructions: Vec<DecodedInstruction>,
+    pub successors: Vec<Edge>,
+    pub only_via_unresolved: bool, //Only reachable if some unresolved jump really goes where the image says it does
+}
+
+// An instruction that writes (in position mode) to a cell that's part of reachable code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SelfModifyingWrite {
+    pub instruction: usize,
+    pub target: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ControlFlowGraph {
+    pub blocks: BTreeMap<usize, BasicBlock>, //Keyed by start address
+    pub unresolved_jumps: Vec<usize>, //Jumps with position or relative mode targets, and custom instructions
+    pub self_modifying_writes: Vec<SelfModifyingWrite>,
+    pub relative_writes: Vec<usize>, //Instructions writing in relative mode - they could be writing into code too
+    pub invalid: Vec<(usize, String)>, //Reachable addresses that don't hold a valid instruction, and why
+    pub unreachable: Vec<Range<usize>>, //Cells no reachable instruction covers - data or dead code
+}
+
+impl ControlFlowGraph {
+    // Builds the graph by following control flow from address 0. Jumps whose targets are in memory (position mode)
+    // are followed using the value in the image, but only after everything reachable without them has been found, so
+    // that code reachable only that way can be flagged. Relative mode targets depend on the relative base and can't
+    // be followed at all. Jump operands that some reachable instruction overwrites can't be trusted either, so those
+    // jumps may go either way, to wherever the image says.
+    pub fn new(image: &[isize]) -> ControlFlowGraph {
+        ControlFlowGraph::with_instruction_set(image, &InstructionSet::<isize>::standard())
+    }
+
+    // As for new, for a program that runs with instruction_set. Custom instructions are assumed to carry on to the
+    // next instruction, but as they could go anywhere they count as unresolved jumps.
+    pub fn with_instruction_set<W: Word>(
+        image: &[isize],
+        instruction_set: &InstructionSet<W>,
+    ) -> ControlFlowGraph {
+        ControlFlowGraph::build(image, instruction_set, &[])
+    }
+
+    // As for new, but also following control from each of entry_points, as if some jump the graph can't follow goes
+    // there - so code only reached from them is only_via_unresolved.
+    pub fn with_entry_points(image: &[isize], entry_points: &[usize]) -> ControlFlowGraph {
+        ControlFlowGraph::build(image, &InstructionSet::<isize>::standard(), entry_points)
+    }
+
+    fn build<W: Word>(
+        image: &[isize],
+        instruction_set: &InstructionSet<W>,
+        entry_points: &[usize],
+    ) -> ControlFlowGraph {
+        //Overwritten cells make more jumps go both ways, which can find more code, which can overwrite more cells - so
+        //go round until nothing new turns up.
+        let mut overwritten: BTreeSet<usize> = BTreeSet::new();
+        let (instructions, via_unresolved, invalid, code, self_modifying_writes) = loop {
+            let (instructions, via_unresolved, invalid) =
+                explore(image, instruction_set, entry_points, &overwritten);
+
+            //Which cells hold reachable code?
+            let mut code = vec![false; image.len()];
+            for instruction in instructions.values() {
+                for cell in &mut code[instruction.address..instruction.next_address()] {
+                    *cell = true;
+                }
+            }
+
+            let self_modifying_writes: Vec<SelfModifyingWrite> = instructions
+                .values()
+                .filter(|instruction| instruction.writes())
+                .filter_map(|instruction| match instruction.parameters.last() {
+                    Some(&(Mode::Position, target))
+                        if target >= 0 && code.get(target as usize) == Some(&true) =>
+                    {
+                        Some(SelfModifyingWrite {
+                            instruction: instruction.address,
+                            target: target as usize,
+                        })
+                    }
+                    _ => None,
+                })
+                .collect();
+
+            let targets: BTreeSet<usize> = self_modifying_writes
+                .iter()
+                .map(|write| write.target)
+                .collect();
+            if targets == overwritten {
+                break (
+                    instructions,
+                    via_unresolved,
+                    invalid,
+                    code,
+                    self_modifying_writes,
+                );
+            }
+            overwritten = targets;
+        };
+
+        let blocks = build_blocks(&instructions, &via_unresolved, image, &overwritten);
+
+        let unresolved_jumps = instructions
+            .values()
+            .filter(|instruction| match instruction.op_code() {
+                Some(op_code) => {
+                    op_code.is_jump()
+                        && (instruction.parameters[1].0 != Mode::Immediate
+                            || overwritten.contains(&(instruction.address + 2)))
+                }
+                None => true,
+            })
+            .map(|instruction| instruction.address)
+            .collect();
+
+        let relative_writes = instructions
+            .values()
+            .filter(|instruction| {
+                instruction.writes()
+                    && matches!(instruction.parameters.last(), Some((Mode::Relative, _)))
+            })
+            .map(|instruction| instruction.address)
+            .collect();
+
+        let mut unreachable: Vec<Range<usize>> = vec![];
+        for (address, _) in code.iter().enumerate().filter(|(_, is_code)| !**is_code) {
+            match unreachable.last_mut() {
+                Some(range) if range.end == address => range.end += 1,
+                _ => unreachable.push(address..address + 1),
+            }
+        }
+
+        ControlFlowGraph {
+            blocks,
+            unresolved_jumps,
+            self_modifying_writes,
+            relative_writes,
+            invalid: invalid.into_iter().collect(),
+            unreachable,
+        }
+    }
+
+    // Renders the graph in Graphviz DOT format. Edges from unresolved jumps are dashed, blocks only reachable through
+    // them are grey and blocks containing cells that get overwritten are red.
+    pub fn to_dot(&self) -> String {
+        let modified: BTreeSet<usize> = self
+            .self_modifying_writes
+            .iter()
+            .map(|write| write.target)
+            .collect();
+
+        let mut dot =
+            String::from("digraph intcode {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            for instruction in &block.instructions {
+                write!(label, "{}\\l", instruction).unwrap();
+            }
+            let mut attributes = vec![format!("label=\"{}\"", label)];
+            if modified.range(block.start..block.end).next().is_some() {
+                attributes.push("color=red".to_string());
+            }
+            if block.only_via_unresolved {
+                attributes.push("style=filled, fillcolor=lightgrey".to_string());
+            }
+            writeln!(dot, "    b{} [{}];", block.start, attributes.join(", ")).unwrap();
+        }
+
+        for (address, err) in &self.invalid {
+            writeln!(
+                dot,
+                "    b{} [label=\"{}: {}\", shape=octagon, color=red];",
+                address, address, err
+            )
+            .unwrap();
+        }
+
+        for block in self.blocks.values() {
+            for edge in &block.successors {
+                let style = match edge.kind {
+                    EdgeKind::FallThrough => "",
+                    EdgeKind::Jump => " [label=\"jump\"]",
+                    EdgeKind::Unresolved => " [label=\"jump\", style=dashed]",
+                };
+                writeln!(dot, "    b{} -> b{}{};", block.start, edge.target, style).unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+// Finds the instructions reachable from address 0 (and from entry_points, as if by unresolved jumps), along with
+// those only reachable through unresolved jumps and any addresses that don't decode. Jump operands in overwritten
+// cells are treated as unknown.
+fn explore<W: Word>(
+    image: &[isize],
+    instruction_set: &InstructionSet<W>,
+    entry_points: &[usize],
+    overwritten: &BTreeSet<usize>,
+) -> (
+    BTreeMap<usize, DecodedInstruction>,
+    BTreeSet<usize>,
+    BTreeMap<usize, String>,
+) {
+    let mut instructions: BTreeMap<usize, DecodedInstruction> = BTreeMap::new();
+    let mut via_unresolved: BTreeSet<usize> = BTreeSet::new();
+    let mut invalid: BTreeMap<usize, String> = BTreeMap::new();
+    let mut resolved_queue = vec![0];
+    let mut unresolved_queue = entry_points.to_vec();
+
+    while let Some((address, unresolved)) = resolved_queue
+        .pop()
+        .map(|address| (address, false))
+        .or_else(|| unresolved_queue.pop().map(|address| (address, true)))
+    {
+        if instructions.contains_key(&address) || invalid.contains_key(&address) {
+            continue;
+        }
+
+        let instruction = match intcode::decode(image, address, instruction_set) {
+            Ok(instruction) => instruction,
+            Err(err) => {
+                invalid.insert(address, err);
+                continue;
+            }
+        };
+
+        if unresolved {
+            via_unresolved.insert(address);
+        }
+
+        for edge in successors(&instruction, image, overwritten) {
+            if unresolved || edge.kind == EdgeKind::Unresolved {
+                unresolved_queue.push(edge.target);
+            } else {
+                resolved_queue.push(edge.target);
+            }
+        }
+        instructions.insert(address, instruction);
+    }
+
+    (instructions, via_unresolved, invalid)
+}
+
+// Where control can go after instruction. Jumps with immediate mode conditions only go one way, unless the condition
+// gets overwritten. An overwritten immediate target is only as good as a position mode one.
+fn successors(
+    instruction: &DecodedInstruction,
+    image: &[isize],
+    overwritten: &BTreeSet<usize>,
+) -> Vec<Edge> {
+    let fall_through = Edge {
+        target: instruction.next_address(),
+        kind: EdgeKind::FallThrough,
+    };
+
+    match instruction.op_code() {
+        Some(OpCode::Halt) => vec![],
+        Some(op_code @ (OpCode::JumpIfTrue | OpCode::JumpIfFalse)) => {
+            let (condition_mode, condition) = instruction.parameters[0];
+            let condition_known = condition_mode == Mode::Immediate
+                && !overwritten.contains(&(instruction.address + 1));
+            let (may_jump, may_fall_through) = if condition_known {
+                let jumps = (condition != 0) == (op_code == OpCode::JumpIfTrue);
+                (jumps, !jumps)
+            } else {
+                (true, true)
+            };
+
+            let mut edges = vec![];
+            if may_fall_through {
+                edges.push(fall_through);
+            }
+            if may_jump {
+                let jump = match instruction.parameters[1] {
+                    (Mode::Immediate, target)
+                        if overwritten.contains(&(instruction.address + 2)) =>
+                    {
+                        Some((target, EdgeKind::Unresolved))
+                    }
+                    (Mode::Immediate, target) => Some((target, EdgeKind::Jump)),
+                    (Mode::Position, address) if address >= 0 => image
+                        .get(address as usize)
+                        .map(|&target| (target, EdgeKind::Unresolved)),
+                    _ => None,
+                };
+                if let Some((target, kind)) = jump.filter(|(target, _)| *target >= 0) {
+                    edges.push(Edge {
+                        target: target as usize,
+                        kind,
+                    });
+                }
+            }
+            edges
+        }
+        _ => vec![fall_through],
+    }
+}
+
+// Groups instructions into basic blocks. A block starts at address 0, at any jump target, after any jump or halt, and
+// wherever decoding isn't contiguous.
+fn build_blocks(
+    instructions: &BTreeMap<usize, DecodedInstruction>,
+    via_unresolved: &BTreeSet<usize>,
+    image: &[isize],
+    overwritten: &BTreeSet<usize>,
+) -> BTreeMap<usize, BasicBlock> {
+    let mut leaders: BTreeSet<usize> = BTreeSet::from([0]);
+    for instruction in instructions.values() {
+        let edges = successors(instruction, image, overwritten);
+        if instruction.is_jump() || instruction.op_code() == Some(OpCode::Halt) {
+            leaders.insert(instruction.next_address());
+        }
+        leaders.extend(
+            edges
+                .iter()
+                .filter(|edge| edge.kind != EdgeKind::FallThrough)
+                .map(|edge| edge.target),
+        );
+    }
+
+    let mut blocks: BTreeMap<usize, BasicBlock> = BTreeMap::new();
+    let mut current: Option<BasicBlock> = None;
+    for instruction in instructions.values() {
+        let continues = match &current {
+            Some(block) => {
+                block.end == instruction.address && !leaders.contains(&instruction.address)
+            }
+            None => false,
+        };
+        if !continues {
+            if let Some(block) = current.take() {
+                blocks.insert(block.start, block);
+            }
+            current = Some(BasicBlock {
+                start: instruction.address,
+                end: instruction.address,
+                instructions: vec![],
+                successors: vec![],
+                only_via_unresolved: via_unresolved.contains(&instruction.address),
+            });
+        }
+        let block = current.as_mut().unwrap();
+        block.end = instruction.next_address();
+        block.successors = successors(instruction, image, overwritten);
+        block.instructions.push(instruction.clone());
+    }
+    if let Some(block) = current {
+        blocks.insert(block.start, block);
+    }
+
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block_ranges(cfg: &ControlFlowGraph) -> Vec<Range<usize>> {
+        cfg.blocks
+            .values()
+            .map(|block| block.start..block.end)
+            .collect()
+    }
+
+    #[test]
+    fn test_blocks_and_unresolved_jumps() {
+        //Outputs 0 if the input is 0, otherwise 1 - the jump target is in memory.
+        let image = [3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9];
+        let cfg = ControlFlowGraph::new(&image);
+        assert_eq!(vec![0..5, 5..9, 9..12], block_ranges(&cfg));
+        assert_eq!(
+            vec![
+                Edge {
+                    target: 5,
+                    kind: EdgeKind::FallThrough
+                },
+                Edge {
+                    target: 9,
+                    kind: EdgeKind::Unresolved
+                }
+            ],
+            cfg.blocks[&0].successors
+        );
+        assert_eq!(vec![2], cfg.unresolved_jumps);
+        assert!(cfg.blocks.values().all(|block| !block.only_via_unresolved));
+        assert!(cfg.self_modifying_writes.is_empty());
+        assert_eq!(vec![12..16], cfg.unreachable);
+    }
+
+    #[test]
+    fn test_only_via_unresolved() {
+        let image = [105, 1, 7, 99, 104, 42, 99, 4];
+        let cfg = ControlFlowGraph::new(&image);
+        assert_eq!(vec![0..3, 4..7], block_ranges(&cfg));
+        assert!(!cfg.blocks[&0].only_via_unresolved);
+        assert!(cfg.blocks[&4].only_via_unresolved);
+        assert_eq!(vec![3..4, 7..8], cfg.unreachable);
+
+        //It really does go there
+        let mut program = intcode::Program::new(&image);
+        program.run();
+        assert_eq!(vec![42], *program.outputs());
+    }
+
+    #[test]
+    fn test_self_modifying_code() {
+        //The input overwrites the jump condition, which is otherwise always true - so the jump can go either way.
+        let image = [3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1];
+        let cfg = ControlFlowGraph::new(&image);
+        assert_eq!(
+            vec![SelfModifyingWrite {
+                instruction: 0,
+                target: 3
+            }],
+            cfg.self_modifying_writes
+        );
+        assert_eq!(vec![0..5, 5..9, 9..12], block_ranges(&cfg));
+        assert_eq!(vec![12..13], cfg.unreachable);
+        assert!(cfg.unresolved_jumps.is_empty());
+        assert!(cfg
+            .to_dot()
+            .contains("b0 [label=\"    0: in [3]\\l    2: jnz -1 9\\l\", color=red];"));
+
+        //It really can fall through.
+        let mut program = intcode::Program::new(&image);
+        program.add_input(0);
+        program.run();
+        assert_eq!(vec![0], *program.outputs());
+
+        //Overwriting the target makes the jump unresolved, and it's only followed once everything else is found.
+        let image = [3, 4, 1105, 1, 7, 99, 99, 104, 42, 99];
+        let cfg = ControlFlowGraph::new(&image);
+        assert_eq!(vec![2], cfg.unresolved_jumps);
+        assert_eq!(vec![0..5, 7..10], block_ranges(&cfg));
+        assert!(cfg.blocks[&7].only_via_unresolved);
+        assert!(cfg.relative_writes.is_empty());
+
+        //Relative mode writes could land anywhere, code included.
+        let cfg = ControlFlowGraph::new(&[109, 2, 21101, 1, 2, 0, 99]);
+        assert!(cfg.self_modifying_writes.is_empty());
+        assert_eq!(vec![2], cfg.relative_writes);
+    }
+
+    #[test]
+    fn test_relative_jumps_and_invalid_code() {
+        let image = [109, 5, 2205, 0, 1, 1106, 0, 10, 99];
+        let cfg = ControlFlowGraph::new(&image);
+        assert_eq!(vec![2], cfg.unresolved_jumps);
+        assert_eq!(vec![0..5, 5..8], block_ranges(&cfg));
+        assert_eq!(
+            vec![(10, "Address 10 is outside the image".to_string())],
+            cfg.invalid
+        );
+        assert_eq!(vec![8..9], cfg.unreachable);
+
+        let dot = cfg.to_dot();
+        assert!(dot.starts_with("digraph intcode {"));
+        assert!(dot.contains("b0 [label=\"    0: arb 5\\l    2: jnz [rb+0] [rb+1]\\l\"];"));
+        assert!(dot.contains("b10 [label=\"10: Address 10 is outside the image\""));
+        assert!(dot.contains("b5 -> b10 [label=\"jump\"];"));
+    }
+}
@@ -0,0 +1,395 @@
+// Async Intcode machines. A program's run loop becomes a future that, whenever the program needs input, waits on a
+// stream rather than returning AwaitingInput to the caller, and sends its outputs down a channel whose receiving end
+// is itself a stream. Machines connected that way can all run on one executor, each only being polled when there's
+// input for it, with no threads and no sleep-and-retry loops.
+//
+// std has futures but not streams, channels or an executor, so minimal versions of those live here too. They're
+// enough for driving Intcode machines and not meant to be much more.
+#![allow(dead_code)]
+
+use crate::intcode::{IntcodeError, Program, ProgramResult, Word};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+// A source of values that arrive over time - the async version of an iterator.
+pub trait Stream {
+    type Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>>;
+}
+
+// Future for the next value from a stream (None once it's finished).
+pub struct Next<'a, S> {
+    stream: &'a mut S,
+}
+
+impl<S: Stream + Unpin> Future for Next<'_, S> {
+    type Output = Option<S::Item>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut *self.stream).poll_next(cx)
+    }
+}
+
+pub fn next<S: Stream + Unpin>(stream: &mut S) -> Next<'_, S> {
+    Next { stream }
+}
+
+// A stream that's always ready with the next value from an iterator.
+pub struct Iter<I> {
+    iter: I,
+}
+
+impl<I: Iterator + Unpin> Stream for Iter<I> {
+    type Item = I::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<I::Item>> {
+        Poll::Ready(self.iter.next())
+    }
+}
+
+pub fn iter<I: IntoIterator>(iter: I) -> Iter<I::IntoIter> {
+    Iter {
+        iter: iter.into_iter(),
+    }
+}
+
+struct Channel<T> {
+    queue: VecDeque<T>,
+    waker: Option<Waker>, //Of the receiver, if it's waiting
+    senders: usize,
+    receiver_alive: bool,
+}
+
+// Unbounded channel. The receiver is a stream that finishes once every sender has gone and the queue is empty.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let channel = Arc::new(Mutex::new(Channel {
+        queue: VecDeque::new(),
+        waker: None,
+        senders: 1,
+        receiver_alive: true,
+    }));
+    (
+        Sender {
+            channel: Arc::clone(&channel),
+        },
+        Receiver { channel },
+    )
+}
+
+pub struct Sender<T> {
+    channel: Arc<Mutex<Channel<T>>>,
+}
+
+impl<T> Sender<T> {
+    // Queues value for the receiver. Gives it back if the receiver has gone.
+    pub fn send(&self, value: T) -> Result<(), T> {
+        let mut channel = self.channel.lock().unwrap();
+        if !channel.receiver_alive {
+            return Err(value);
+        }
+        channel.queue.push_back(value);
+        if let Some(waker) = channel.waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Sender<T> {
+        self.channel.lock().unwrap().senders += 1;
+        Sender {
+            channel: Arc::clone(&self.channel),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut channel = self.channel.lock().unwrap();
+        channel.senders -= 1;
+        if channel.senders == 0 {
+            //The receiver needs to find out it's not getting anything else.
+            if let Some(waker) = channel.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    channel: Arc<Mutex<Channel<T>>>,
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut channel = self.channel.lock().unwrap();
+        match channel.queue.pop_front() {
+            Some(value) => Poll::Ready(Some(value)),
+            None if channel.senders == 0 => Poll::Ready(None),
+            None => {
+                channel.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    // Everything queued right now, without waiting.
+    pub fn drain(&self) -> Vec<T> {
+        self.channel.lock().unwrap().queue.drain(..).collect()
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.channel.lock().unwrap().receiver_alive = false;
+    }
+}
+
+// Runs program until it halts, taking input from inputs whenever it needs some and sending each output to outputs as
+// soon as the program stops to wait (so in order, and before it waits). Outputs are also left in the program. If the
+// inputs finish while the program is waiting for one, gives up and returns AwaitingInput along with the program.
+// Outputs nobody is listening for any more are dropped.
+pub async fn run<W: Word, S: Stream<Item = W> + Unpin>(
+    mut program: Program<W>,
+    mut inputs: S,
+    outputs: Sender<W>,
+) -> Result<(ProgramResult, Program<W>), IntcodeError> {
+    let mut sent = 0;
+    loop {
+        let result = program.run_checked()?;
+        for output in &program.outputs()[sent..] {
+            let _ = outputs.send(output.clone());
+        }
+        sent = program.outputs().len();
+
+        match result {
+            ProgramResult::Halted => return Ok((result, program)),
+            ProgramResult::AwaitingInput => match next(&mut inputs).await {
+                Some(input) => program.add_input(input),
+                None => return Ok((result, program)),
+            },
+        }
+    }
+}
+
+struct Slot<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+}
+
+// The eventual result of a spawned task. Can be awaited by another task, or checked from outside the executor.
+pub struct JoinHandle<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    // The task's result, if it's finished (and this hasn't been called before).
+    pub fn try_take(&self) -> Option<T> {
+        self.slot.lock().unwrap().value.take()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut slot = self.slot.lock().unwrap();
+        match slot.value.take() {
+            Some(value) => Poll::Ready(value),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+// Wakes a task by queueing it to be polled.
+struct TaskWaker {
+    task: usize,
+    ready: Arc<Mutex<VecDeque<usize>>>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.ready.lock().unwrap().push_back(self.task);
+    }
+}
+
+// Single threaded executor. Tasks are only polled when something has woken them.
+#[derive(Default)]
+pub struct LocalExecutor {
+    tasks: Vec<Option<Task>>, //None once finished
+    ready: Arc<Mutex<VecDeque<usize>>>,
+}
+
+impl LocalExecutor {
+    pub fn new() -> LocalExecutor {
+        LocalExecutor::default()
+    }
+
+    pub fn spawn<F: Future + 'static>(&mut self, future: F) -> JoinHandle<F::Output> {
+        let slot = Arc::new(Mutex::new(Slot {
+            value: None,
+            waker: None,
+        }));
+        let task_slot = Arc::clone(&slot);
+        self.tasks.push(Some(Box::pin(async move {
+            let value = future.await;
+            let mut slot = task_slot.lock().unwrap();
+            slot.value = Some(value);
+            if let Some(waker) = slot.waker.take() {
+                waker.wake();
+            }
+        })));
+        self.ready.lock().unwrap().push_back(self.tasks.len() - 1);
+        JoinHandle { slot }
+    }
+
+    // Polls tasks until none of them can make progress. Returns the number that haven't finished - they're waiting
+    // for something, which may come from outside (e.g. a send on a channel) before the next call.
+    pub fn run_until_stalled(&mut self) -> usize {
+        loop {
+            let task = self.ready.lock().unwrap().pop_front();
+            let task = match task {
+                Some(task) => task,
+                None => break,
+            };
+            let waker = Waker::from(Arc::new(TaskWaker {
+                task,
+                ready: Arc::clone(&self.ready),
+            }));
+            if let Some(future) = &mut self.tasks[task] {
+                if future
+                    .as_mut()
+                    .poll(&mut Context::from_waker(&waker))
+                    .is_ready()
+                {
+                    self.tasks[task] = None;
+                }
+            }
+        }
+        self.tasks.iter().filter(|task| task.is_some()).count()
+    }
+
+    // Runs future, and the spawned tasks alongside it, to completion. Panics if everything stalls first.
+    pub fn block_on<F: Future + 'static>(&mut self, future: F) -> F::Output {
+        let handle = self.spawn(future);
+        self.run_until_stalled();
+        handle
+            .try_take()
+            .expect("Executor stalled before the future completed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    //Counts down from its input, outputting each value.
+    const COUNTDOWN: [isize; 13] = [3, 12, 4, 12, 1001, 12, -1, 12, 1005, 12, 2, 99, 0];
+    //Outputs the sum of each pair of inputs, forever.
+    const ADDER: [isize; 15] = [3, 13, 3, 14, 1, 13, 14, 13, 4, 13, 1105, 1, 0, 0, 0];
+
+    #[test]
+    fn test_run_with_streams() {
+        let mut executor = LocalExecutor::new();
+        let (sender, receiver) = channel();
+        let handle = executor.spawn(run(Program::new(&COUNTDOWN), iter(vec![3]), sender));
+        assert_eq!(0, executor.run_until_stalled());
+        let (result, program) = handle.try_take().unwrap().unwrap();
+        assert_eq!(ProgramResult::Halted, result);
+        assert_eq!(vec![3, 2, 1], *program.outputs());
+        assert_eq!(vec![3, 2, 1], receiver.drain());
+
+        //Running out of input.
+        let (sender, _receiver) = channel();
+        let (result, _) = executor
+            .block_on(run(Program::new(&COUNTDOWN), iter(vec![]), sender))
+            .unwrap();
+        assert_eq!(ProgramResult::AwaitingInput, result);
+    }
+
+    #[test]
+    fn test_waits_for_input_without_polling() {
+        let mut executor = LocalExecutor::new();
+        let (input_sender, input_receiver) = channel();
+        let (output_sender, mut output_receiver) = channel();
+        let polls = Rc::new(Cell::new(0));
+        let counted = Rc::clone(&polls);
+        let mut adder = Box::pin(run(Program::new(&ADDER), input_receiver, output_sender));
+        let handle = executor.spawn(std::future::poll_fn(move |cx| {
+            counted.set(counted.get() + 1);
+            adder.as_mut().poll(cx)
+        }));
+
+        assert_eq!(1, executor.run_until_stalled());
+        assert_eq!(1, executor.run_until_stalled());
+        assert_eq!(1, polls.get());
+
+        input_sender.send(2).unwrap();
+        input_sender.send(3).unwrap();
+        assert_eq!(1, executor.run_until_stalled());
+        assert_eq!(vec![5], output_receiver.drain());
+        input_sender.send(10).unwrap();
+        assert_eq!(1, executor.run_until_stalled());
+        assert!(output_receiver.drain().is_empty());
+        let polls_so_far = polls.get();
+
+        //Closing the input stream lets it finish.
+        drop(input_sender);
+        assert_eq!(0, executor.run_until_stalled());
+        assert_eq!(polls_so_far + 1, polls.get());
+        let (result, _) = handle.try_take().unwrap().unwrap();
+        assert_eq!(ProgramResult::AwaitingInput, result);
+        assert!(executor
+            .block_on(async move { next(&mut output_receiver).await })
+            .is_none());
+    }
+
+    #[test]
+    fn test_feedback_loop() {
+        //Day 7's amplifiers, all on one executor.
+        let image = [
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+        let phases = [9, 8, 7, 6, 5];
+        let mut executor = LocalExecutor::new();
+        let channels: Vec<_> = phases
+            .iter()
+            .map(|&phase| {
+                let (sender, receiver) = channel();
+                sender.send(phase).unwrap();
+                (sender, receiver)
+            })
+            .collect();
+        channels[0].0.send(0).unwrap();
+
+        let (senders, receivers): (Vec<_>, Vec<_>) = channels.into_iter().unzip();
+        let mut handles = vec![];
+        for (amplifier, receiver) in receivers.into_iter().enumerate() {
+            let sender = senders[(amplifier + 1) % phases.len()].clone();
+            handles.push(executor.spawn(run(Program::new(&image), receiver, sender)));
+        }
+        drop(senders);
+
+        assert_eq!(0, executor.run_until_stalled());
+        let (result, last) = handles.pop().unwrap().try_take().unwrap().unwrap();
+        assert_eq!(ProgramResult::Halted, result);
+        assert_eq!(Some(&139629729), last.outputs().last());
+    }
+}
@@ -0,0 +1,1044 @@
+// A compiler for a tiny C-like language, producing Intcode images - for writing test programs (or NIC firmware) without
+// hand-assembling them. A program is a list of functions, starting at main:
+//
+//     // Outputs the factorial of each input, until it gets a 0.
+//     fn factorial(n) {
+//         if (n <= 1) { return 1; }
+//         return n * factorial(n - 1);
+//     }
+//
+//     fn main() {
+//         let n = in();
+//         while (n != 0) {
+//             out(factorial(n));
+//             n = in();
+//         }
+//     }
+//
+// Everything is an integer. There's + - * (no division - Intcode doesn't have it), comparisons < <= > >= == != giving
+// 0 or 1, and ! && || treating any non-zero value as true (both sides of && and || are always evaluated). in() reads
+// an input and out(x) writes an output. Variables are declared with let and are local to their block; there are no
+// globals.
+//
+// The relative base is the frame pointer. A function's frame holds the return address at [rb+0], then its
+// parameters, then its locals, then temporaries for evaluating expressions. To call, the caller writes the return
+// address and arguments into the cells just past its own frame, moves the relative base up to them and jumps; the
+// callee leaves its return value in [rb+1] and jumps back through [rb+0], and the caller moves the relative base back
+// down. The stack starts just after the code.
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompileError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+impl Position {
+    fn error<T>(&self, message: String) -> Result<T, CompileError> {
+        Err(CompileError {
+            line: self.line,
+            column: self.column,
+            message,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Number(isize),
+    Identifier(String),
+    Symbol(&'static str),
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "'{}'", n),
+            Token::Identifier(name) => write!(f, "'{}'", name),
+            Token::Symbol(symbol) => write!(f, "'{}'", symbol),
+            Token::End => write!(f, "end of input"),
+        }
+    }
+}
+
+//Longest first, so that e.g. <= isn't read as < then =.
+const SYMBOLS: [&str; 19] = [
+    "<=", ">=", "==", "!=", "&&", "||", "(", ")", "{", "}", ",", ";", "=", "+", "-", "*", "<", ">",
+    "!",
+];
+
+fn tokenize(source: &str) -> Result<Vec<(Token, Position)>, CompileError> {
+    let mut tokens = vec![];
+    for (line_index, line) in source.lines().enumerate() {
+        let line = line.split("//").next().unwrap_or("");
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            let position = Position {
+                line: line_index + 1,
+                column: i + 1,
+            };
+            let c = chars[i];
+            if c.is_whitespace() {
+                i += 1;
+            } else if c.is_ascii_digit() {
+                let start = i;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let digits: String = chars[start..i].iter().collect();
+                let value = digits
+                    .parse()
+                    .or_else(|_| position.error(format!("Number {} is too big", digits)))?;
+                tokens.push((Token::Number(value), position));
+            } else if c.is_alphabetic() || c == '_' {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push((
+                    Token::Identifier(chars[start..i].iter().collect()),
+                    position,
+                ));
+            } else {
+                let rest: String = chars[i..].iter().collect();
+                let symbol = SYMBOLS
+                    .iter()
+                    .find(|symbol| rest.starts_with(*symbol))
+                    .ok_or(CompileError {
+                        line: position.line,
+                        column: position.column,
+                        message: format!("Unexpected character '{}'", c),
+                    })?;
+                tokens.push((Token::Symbol(symbol), position));
+                i += symbol.len();
+            }
+        }
+    }
+    let end = Position {
+        line: source.lines().count().max(1),
+        column: source.lines().last().map_or(0, |line| line.chars().count()) + 1,
+    };
+    tokens.push((Token::End, end));
+    Ok(tokens)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    LessThan,
+    LessOrEqual,
+    GreaterThan,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum UnaryOp {
+    Negate,
+    Not,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expression {
+    Number(isize),
+    Variable(String, Position),
+    Input,
+    Call(String, Vec<Expression>, Position),
+    Unary(UnaryOp, Box<Expression>),
+    Binary(BinaryOp, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Statement {
+    Let(String, Expression),
+    Assign(String, Expression, Position),
+    If(Expression, Vec<Statement>, Vec<Statement>),
+    While(Expression, Vec<Statement>),
+    Return(Option<Expression>),
+    Output(Expression),
+    Expression(Expression),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Function {
+    name: String,
+    parameters: Vec<String>,
+    body: Vec<Statement>,
+    position: Position,
+}
+
+struct Parser {
+    tokens: Vec<(Token, Position)>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next].0
+    }
+
+    fn position(&self) -> Position {
+        self.tokens[self.next].1
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.next].0.clone();
+        if token != Token::End {
+            self.next += 1;
+        }
+        token
+    }
+
+    // Consumes symbol if it's next.
+    fn accept(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Token::Symbol(s) if *s == symbol) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), CompileError> {
+        if self.accept(symbol) {
+            Ok(())
+        } else {
+            self.position()
+                .error(format!("Expected '{}' but found {}", symbol, self.peek()))
+        }
+    }
+
+    fn accept_keyword(&mut self, keyword: &str) -> bool {
+        if matches!(self.peek(), Token::Identifier(name) if name == keyword) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, CompileError> {
+        let position = self.position();
+        match self.advance() {
+            Token::Identifier(name) if !KEYWORDS.contains(&name.as_str()) => Ok(name),
+            token => position.error(format!("Expected a name but found {}", token)),
+        }
+    }
+
+    fn program(&mut self) -> Result<Vec<Function>, CompileError> {
+        let mut functions = vec![];
+        while *self.peek() != Token::End {
+            let position = self.position();
+            if !self.accept_keyword("fn") {
+                return position.error(format!("Expected 'fn' but found {}", self.peek()));
+            }
+            let name = self.identifier()?;
+            self.expect("(")?;
+            let mut parameters = vec![];
+            if !self.accept(")") {
+                loop {
+                    parameters.push(self.identifier()?);
+                    if self.accept(")") {
+                        break;
+                    }
+                    self.expect(",")?;
+                }
+            }
+            let body = self.block()?;
+            functions.push(Function {
+                name,
+                parameters,
+                body,
+                position,
+            });
+        }
+        Ok(functions)
+    }
+
+    fn block(&mut self) -> Result<Vec<Statement>, CompileError> {
+        self.expect("{")?;
+        let mut statements = vec![];
+        while !self.accept("}") {
+            statements.push(self.statement()?);
+        }
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Statement, CompileError> {
+        let position = self.position();
+        if self.accept_keyword("let") {
+            let name = self.identifier()?;
+            self.expect("=")?;
+            let value = self.expression()?;
+            self.expect(";")?;
+            Ok(Statement::Let(name, value))
+        } else if self.accept_keyword("if") {
+            self.expect("(")?;
+            let condition = self.expression()?;
+            self.expect(")")?;
+            let then = self.block()?;
+            let otherwise = if self.accept_keyword("else") {
+                if matches!(self.peek(), Token::Identifier(name) if name == "if") {
+                    vec![self.statement()?]
+                } else {
+                    self.block()?
+                }
+            } else {
+                vec![]
+            };
+            Ok(Statement::If(condition, then, otherwise))
+        } else if self.accept_keyword("while") {
+            self.expect("(")?;
+            let condition = self.expression()?;
+            self.expect(")")?;
+            Ok(Statement::While(condition, self.block()?))
+        } else if self.accept_keyword("return") {
+            let value = if self.accept(";") {
+                return Ok(Statement::Return(None));
+            } else {
+                self.expression()?
+            };
+            self.expect(";")?;
+            Ok(Statement::Return(Some(value)))
+        } else if self.accept_keyword("out") {
+            self.expect("(")?;
+            let value = self.expression()?;
+            self.expect(")")?;
+            self.expect(";")?;
+            Ok(Statement::Output(value))
+        } else if matches!(
+            self.tokens.get(self.next + 1),
+            Some((Token::Symbol("="), _))
+        ) {
+            let name = self.identifier()?;
+            self.expect("=")?;
+            let value = self.expression()?;
+            self.expect(";")?;
+            Ok(Statement::Assign(name, value, position))
+        } else {
+            let expression = self.expression()?;
+            self.expect(";")?;
+            Ok(Statement::Expression(expression))
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expression, CompileError> {
+        self.binary(0)
+    }
+
+    // Operators at precedence level and above.
+    fn binary(&mut self, level: usize) -> Result<Expression, CompileError> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        'outer: loop {
+            for (symbol, op) in PRECEDENCE[level] {
+                if self.accept(symbol) {
+                    let right = self.binary(level + 1)?;
+                    left = Expression::Binary(*op, Box::new(left), Box::new(right));
+                    continue 'outer;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expression, CompileError> {
+        if self.accept("-") {
+            Ok(Expression::Unary(UnaryOp::Negate, Box::new(self.unary()?)))
+        } else if self.accept("!") {
+            Ok(Expression::Unary(UnaryOp::Not, Box::new(self.unary()?)))
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expression, CompileError> {
+        let position = self.position();
+        match self.advance() {
+            Token::Number(value) => Ok(Expression::Number(value)),
+            Token::Symbol("(") => {
+                let expression = self.expression()?;
+                self.expect(")")?;
+                Ok(expression)
+            }
+            Token::Identifier(name) if name == "in" => {
+                self.expect("(")?;
+                self.expect(")")?;
+                Ok(Expression::Input)
+            }
+            Token::Identifier(name) if !KEYWORDS.contains(&name.as_str()) => {
+                if self.accept("(") {
+                    let mut arguments = vec![];
+                    if !self.accept(")") {
+                        loop {
+                            arguments.push(self.expression()?);
+                            if self.accept(")") {
+                                break;
+                            }
+                            self.expect(",")?;
+                        }
+                    }
+                    Ok(Expression::Call(name, arguments, position))
+                } else {
+                    Ok(Expression::Variable(name, position))
+                }
+            }
+            token => position.error(format!("Expected an expression but found {}", token)),
+        }
+    }
+}
+
+const KEYWORDS: [&str; 8] = ["fn", "let", "if", "else", "while", "return", "in", "out"];
+
+//Lowest precedence first.
+const PRECEDENCE: [&[(&str, BinaryOp)]; 6] = [
+    &[("||", BinaryOp::Or)],
+    &[("&&", BinaryOp::And)],
+    &[("==", BinaryOp::Equal), ("!=", BinaryOp::NotEqual)],
+    &[
+        ("<=", BinaryOp::LessOrEqual),
+        (">=", BinaryOp::GreaterOrEqual),
+        ("<", BinaryOp::LessThan),
+        (">", BinaryOp::GreaterThan),
+    ],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Subtract)],
+    &[("*", BinaryOp::Multiply)],
+];
+
+// A cell of the output whose value may not be known until everything has been generated.
+#[derive(Clone, Copy, Debug)]
+enum Cell {
+    Value(isize),
+    Label(usize),
+    //sign * (size of function's frame) + offset
+    FrameSize {
+        function: usize,
+        sign: isize,
+        offset: isize,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Operand {
+    Immediate(Cell),
+    Relative(Cell),
+}
+
+impl Operand {
+    fn mode(&self) -> isize {
+        match self {
+            Operand::Immediate(_) => 1,
+            Operand::Relative(_) => 2,
+        }
+    }
+
+    fn cell(&self) -> Cell {
+        match self {
+            Operand::Immediate(cell) | Operand::Relative(cell) => *cell,
+        }
+    }
+
+    fn constant(&self) -> Option<isize> {
+        match self {
+            Operand::Immediate(Cell::Value(value)) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+fn immediate(value: isize) -> Operand {
+    Operand::Immediate(Cell::Value(value))
+}
+
+fn slot(offset: isize) -> Operand {
+    Operand::Relative(Cell::Value(offset))
+}
+
+struct FunctionInfo {
+    label: usize,
+    parameters: usize,
+    frame_size: isize, //Known once the function has been generated
+}
+
+struct Generator {
+    code: Vec<Cell>,
+    labels: Vec<Option<usize>>,
+    functions: HashMap<String, usize>,
+    function_info: Vec<FunctionInfo>,
+    //For the function being generated:
+    current: usize,
+    scopes: Vec<HashMap<String, isize>>, //Variable name -> frame offset
+    next_local: isize,
+    temporaries_start: isize,
+    next_temporary: isize,
+    frame_size: isize,
+}
+
+impl Generator {
+    fn new_label(&mut self) -> usize {
+        self.labels.push(None);
+        self.labels.len() - 1
+    }
+
+    fn place_label(&mut self, label: usize) {
+        self.labels[label] = Some(self.code.len());
+    }
+
+    fn emit(&mut self, op_code: isize, operands: &[Operand]) {
+        let modes = operands
+            .iter()
+            .rev()
+            .fold(0, |modes, operand| modes * 10 + operand.mode());
+        self.code.push(Cell::Value(modes * 100 + op_code));
+        self.code
+            .extend(operands.iter().map(|operand| operand.cell()));
+    }
+
+    fn temporary(&mut self) -> Operand {
+        let offset = self.next_temporary;
+        self.next_temporary += 1;
+        self.frame_size = self.frame_size.max(self.next_temporary);
+        slot(offset)
+    }
+
+    // This function's frame size plus offset, as a cell to be filled in later.
+    fn past_frame(&self, sign: isize, offset: isize) -> Cell {
+        Cell::FrameSize {
+            function: self.current,
+            sign,
+            offset,
+        }
+    }
+
+    fn variable(&self, name: &str, position: Position) -> Result<Operand, CompileError> {
+        match self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            Some(offset) => Ok(slot(*offset)),
+            None => position.error(format!("Unknown variable '{}'", name)),
+        }
+    }
+
+    fn function(&mut self, index: usize, function: &Function) -> Result<(), CompileError> {
+        self.current = index;
+        let label = self.function_info[index].label;
+        self.place_label(label);
+        self.scopes = vec![function
+            .parameters
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.clone(), i as isize + 1))
+            .collect()];
+        self.next_local = function.parameters.len() as isize + 1;
+        self.temporaries_start = self.next_local + count_lets(&function.body) as isize;
+        self.next_temporary = self.temporaries_start;
+        //Room for the return address and value at least.
+        self.frame_size = self.temporaries_start.max(2);
+
+        self.statements(&function.body)?;
+        self.emit_return(immediate(0));
+        self.function_info[index].frame_size = self.frame_size;
+        Ok(())
+    }
+
+    fn statements(&mut self, statements: &[Statement]) -> Result<(), CompileError> {
+        self.scopes.push(HashMap::new());
+        for statement in statements {
+            self.statement(statement)?;
+            self.next_temporary = self.temporaries_start;
+        }
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), CompileError> {
+        match statement {
+            Statement::Let(name, value) => {
+                let value = self.expression(value)?;
+                let offset = self.next_local;
+                self.next_local += 1;
+                self.scopes.last_mut().unwrap().insert(name.clone(), offset);
+                self.emit(1, &[value, immediate(0), slot(offset)]);
+            }
+            Statement::Assign(name, value, position) => {
+                let target = self.variable(name, *position)?;
+                let value = self.expression(value)?;
+                self.emit(1, &[value, immediate(0), target]);
+            }
+            Statement::If(condition, then, otherwise) => {
+                let else_label = self.new_label();
+                let end_label = self.new_label();
+                let condition = self.expression(condition)?;
+                self.emit(6, &[condition, Operand::Immediate(Cell::Label(else_label))]);
+                self.statements(then)?;
+                self.emit(
+                    5,
+                    &[immediate(1), Operand::Immediate(Cell::Label(end_label))],
+                );
+                self.place_label(else_label);
+                self.statements(otherwise)?;
+                self.place_label(end_label);
+            }
+            Statement::While(condition, body) => {
+                let start_label = self.new_label();
+                let end_label = self.new_label();
+                self.place_label(start_label);
+                let condition = self.expression(condition)?;
+                self.emit(6, &[condition, Operand::Immediate(Cell::Label(end_label))]);
+                self.next_temporary = self.temporaries_start;
+                self.statements(body)?;
+                self.emit(
+                    5,
+                    &[immediate(1), Operand::Immediate(Cell::Label(start_label))],
+                );
+                self.place_label(end_label);
+            }
+            Statement::Return(value) => {
+                let value = match value {
+                    Some(value) => self.expression(value)?,
+                    None => immediate(0),
+                };
+                self.emit_return(value);
+            }
+            Statement::Output(value) => {
+                let value = self.expression(value)?;
+                self.emit(4, &[value]);
+            }
+            Statement::Expression(expression) => {
+                self.expression(expression)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn emit_return(&mut self, value: Operand) {
+        self.emit(1, &[value, immediate(0), slot(1)]);
+        self.emit(6, &[immediate(0), slot(0)]);
+    }
+
+    // Generates code to evaluate expression, returning where its value ends up.
+    fn expression(&mut self, expression: &Expression) -> Result<Operand, CompileError> {
+        match expression {
+            Expression::Number(value) => Ok(immediate(*value)),
+            Expression::Variable(name, position) => self.variable(name, *position),
+            Expression::Input => {
+                let result = self.temporary();
+                self.emit(3, &[result]);
+                Ok(result)
+            }
+            Expression::Call(name, arguments, position) => self.call(name, arguments, *position),
+            Expression::Unary(op, operand) => {
+                let operand = self.expression(operand)?;
+                match op {
+                    UnaryOp::Negate => self.arithmetic(2, operand, immediate(-1)),
+                    UnaryOp::Not => self.arithmetic(8, operand, immediate(0)),
+                }
+            }
+            Expression::Binary(op, left, right) => {
+                let left = self.expression(left)?;
+                let right = self.expression(right)?;
+                self.binary(*op, left, right)
+            }
+        }
+    }
+
+    fn binary(
+        &mut self,
+        op: BinaryOp,
+        left: Operand,
+        right: Operand,
+    ) -> Result<Operand, CompileError> {
+        use BinaryOp::*;
+        match op {
+            Add => self.arithmetic(1, left, right),
+            Multiply => self.arithmetic(2, left, right),
+            Subtract => {
+                let negated = self.arithmetic(2, right, immediate(-1))?;
+                self.arithmetic(1, left, negated)
+            }
+            LessThan => self.arithmetic(7, left, right),
+            GreaterThan => self.arithmetic(7, right, left),
+            LessOrEqual => {
+                let greater = self.arithmetic(7, right, left)?;
+                self.arithmetic(8, greater, immediate(0))
+            }
+            GreaterOrEqual => {
+                let less = self.arithmetic(7, left, right)?;
+                self.arithmetic(8, less, immediate(0))
+            }
+            Equal => self.arithmetic(8, left, right),
+            NotEqual => {
+                let equal = self.arithmetic(8, left, right)?;
+                self.arithmetic(8, equal, immediate(0))
+            }
+            And | Or => {
+                let left_false = self.arithmetic(8, left, immediate(0))?;
+                let right_false = self.arithmetic(8, right, immediate(0))?;
+                if op == And {
+                    //Neither is false
+                    let either_false = self.arithmetic(1, left_false, right_false)?;
+                    self.arithmetic(8, either_false, immediate(0))
+                } else {
+                    //Not both false
+                    let both_false = self.arithmetic(2, left_false, right_false)?;
+                    self.arithmetic(8, both_false, immediate(0))
+                }
+            }
+        }
+    }
+
+    // Emits add, multiply, less than or equals into a new temporary - or works the answer out now, if both operands
+    // are constants.
+    fn arithmetic(
+        &mut self,
+        op_code: isize,
+        left: Operand,
+        right: Operand,
+    ) -> Result<Operand, CompileError> {
+        if let (Some(a), Some(b)) = (left.constant(), right.constant()) {
+            let folded = match op_code {
+                1 => a.checked_add(b),
+                2 => a.checked_mul(b),
+                7 => Some((a < b) as isize),
+                _ => Some((a == b) as isize),
+            };
+            if let Some(value) = folded {
+                return Ok(immediate(value));
+            }
+        }
+        let result = self.temporary();
+        self.emit(op_code, &[left, right, result]);
+        Ok(result)
+    }
+
+    fn call(
+        &mut self,
+        name: &str,
+        arguments: &[Expression],
+        position: Position,
+    ) -> Result<Operand, CompileError> {
+        let callee = match self.functions.get(name) {
+            Some(callee) => *callee,
+            None => return position.error(format!("Unknown function '{}'", name)),
+        };
+        let expected = self.function_info[callee].parameters;
+        if arguments.len() != expected {
+            return position.error(format!(
+                "'{}' takes {} argument(s) but was given {}",
+                name,
+                expected,
+                arguments.len()
+            ));
+        }
+
+        let values = arguments
+            .iter()
+            .map(|argument| self.expression(argument))
+            .collect::<Result<Vec<Operand>, CompileError>>()?;
+        let return_label = self.new_label();
+        for (i, value) in values.into_iter().enumerate() {
+            let destination = Operand::Relative(self.past_frame(1, i as isize + 1));
+            self.emit(1, &[value, immediate(0), destination]);
+        }
+        let return_address = Operand::Relative(self.past_frame(1, 0));
+        self.emit(
+            1,
+            &[
+                Operand::Immediate(Cell::Label(return_label)),
+                immediate(0),
+                return_address,
+            ],
+        );
+        self.emit(9, &[Operand::Immediate(self.past_frame(1, 0))]);
+        let callee_label = self.function_info[callee].label;
+        self.emit(
+            5,
+            &[immediate(1), Operand::Immediate(Cell::Label(callee_label))],
+        );
+        self.place_label(return_label);
+        self.emit(9, &[Operand::Immediate(self.past_frame(-1, 0))]);
+
+        //The return value is in the callee's [rb+1], which the next call would overwrite.
+        let result = self.temporary();
+        let returned = Operand::Relative(self.past_frame(1, 1));
+        self.emit(1, &[returned, immediate(0), result]);
+        Ok(result)
+    }
+
+    fn resolve(&self) -> Vec<isize> {
+        self.code
+            .iter()
+            .map(|cell| match cell {
+                Cell::Value(value) => *value,
+                Cell::Label(label) => self.labels[*label].expect("Label never placed") as isize,
+                Cell::FrameSize {
+                    function,
+                    sign,
+                    offset,
+                } => sign * self.function_info[*function].frame_size + offset,
+            })
+            .collect()
+    }
+}
+
+// Number of variables declared anywhere in statements.
+fn count_lets(statements: &[Statement]) -> usize {
+    statements
+        .iter()
+        .map(|statement| match statement {
+            Statement::Let(..) => 1,
+            Statement::If(_, then, otherwise) => count_lets(then) + count_lets(otherwise),
+            Statement::While(_, body) => count_lets(body),
+            _ => 0,
+        })
+        .sum()
+}
+
+// Compiles source to an Intcode image, ready to run from address 0.
+pub fn compile(source: &str) -> Result<Vec<isize>, CompileError> {
+    let functions = Parser {
+        tokens: tokenize(source)?,
+        next: 0,
+    }
+    .program()?;
+
+    let mut generator = Generator {
+        code: vec![],
+        labels: vec![],
+        functions: HashMap::new(),
+        function_info: vec![],
+        current: 0,
+        scopes: vec![],
+        next_local: 0,
+        temporaries_start: 0,
+        next_temporary: 0,
+        frame_size: 0,
+    };
+    for (index, function) in functions.iter().enumerate() {
+        if generator
+            .functions
+            .insert(function.name.clone(), index)
+            .is_some()
+        {
+            return function
+                .position
+                .error(format!("Function '{}' is defined twice", function.name));
+        }
+        let label = generator.new_label();
+        generator.function_info.push(FunctionInfo {
+            label,
+            parameters: function.parameters.len(),
+            frame_size: 0,
+        });
+    }
+    let main = match generator.functions.get("main") {
+        Some(&main) if functions[main].parameters.is_empty() => main,
+        Some(&main) => {
+            return functions[main]
+                .position
+                .error("main can't take any parameters".to_string())
+        }
+        None => {
+            return Err(CompileError {
+                line: 1,
+                column: 1,
+                message: "No main function".to_string(),
+            })
+        }
+    };
+
+    //Start-up: point the relative base at the stack, call main, then halt.
+    let stack = generator.new_label();
+    let halt = generator.new_label();
+    let main_label = generator.function_info[main].label;
+    generator.emit(9, &[Operand::Immediate(Cell::Label(stack))]);
+    generator.emit(
+        1,
+        &[Operand::Immediate(Cell::Label(halt)), immediate(0), slot(0)],
+    );
+    generator.emit(
+        5,
+        &[immediate(1), Operand::Immediate(Cell::Label(main_label))],
+    );
+    generator.place_label(halt);
+    generator.emit(99, &[]);
+
+    for (index, function) in functions.iter().enumerate() {
+        generator.function(index, function)?;
+    }
+    generator.place_label(stack);
+    Ok(generator.resolve())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{Program, ProgramResult};
+
+    fn run(source: &str, inputs: &[isize]) -> Vec<isize> {
+        let image = compile(source).unwrap_or_else(|err| panic!("{err}"));
+        let mut program = Program::new(&image);
+        for &input in inputs {
+            program.add_input(input);
+        }
+        assert_eq!(Ok(ProgramResult::Halted), program.run_checked());
+        program.outputs().clone()
+    }
+
+    fn error(source: &str) -> String {
+        compile(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_arithmetic_and_io() {
+        assert_eq!(
+            vec![7, 6, -12, 35],
+            run(
+                "fn main() {
+                    out(1 + 2 * 3);
+                    out(-(4 - 10));
+                    let x = in();
+                    out(x * -2 - x + x);
+                    out((in() + 2) * 5); // Precedence and brackets
+                }",
+                &[6, 5]
+            )
+        );
+    }
+
+    #[test]
+    fn test_comparisons_and_logic() {
+        let source = "fn main() {
+            let a = in();
+            let b = in();
+            out(a < b); out(a <= b); out(a > b); out(a >= b); out(a == b); out(a != b);
+            out(a && b); out(a || b); out(!a); out(!!b);
+        }";
+        assert_eq!(vec![1, 1, 0, 0, 0, 1, 0, 1, 1, 1], run(source, &[0, 5]));
+        assert_eq!(vec![0, 1, 0, 1, 1, 0, 1, 1, 0, 1], run(source, &[3, 3]));
+        assert_eq!(vec![0, 0, 1, 1, 0, 1, 1, 1, 0, 1], run(source, &[-2, -4]));
+    }
+
+    #[test]
+    fn test_control_flow() {
+        //Counts down, then says whether the input was odd or even.
+        let source = "fn main() {
+            let n = in();
+            let odd = 0;
+            while (n > 0) {
+                out(n);
+                n = n - 1;
+                odd = !odd;
+            }
+            if (odd) {
+                out(1);
+            } else if (n == 0) {
+                out(2);
+            } else {
+                out(3);
+            }
+        }";
+        assert_eq!(vec![3, 2, 1, 1], run(source, &[3]));
+        assert_eq!(vec![4, 3, 2, 1, 2], run(source, &[4]));
+        assert_eq!(vec![3], run(source, &[-1]));
+
+        //Block scope.
+        assert_eq!(
+            vec![2, 1],
+            run(
+                "fn main() { let x = 1; if (1) { let x = 2; out(x); } out(x); }",
+                &[]
+            )
+        );
+    }
+
+    #[test]
+    fn test_functions() {
+        let source = "
+            fn factorial(n) {
+                if (n <= 1) { return 1; }
+                return n * factorial(n - 1);
+            }
+
+            fn fibonacci(n) {
+                if (n < 2) { return n; }
+                return fibonacci(n - 1) + fibonacci(n - 2);
+            }
+
+            fn sum3(a, b, c) { let total = a + b; return total + c; }
+
+            fn say(x) { out(x); }
+
+            fn main() {
+                let n = in();
+                while (n != 0) {
+                    out(factorial(n));
+                    n = in();
+                }
+                out(fibonacci(15));
+                out(sum3(1, factorial(3), sum3(10, 20, 30)) * 2);
+                say(99);
+                out(say(98));
+            }";
+        assert_eq!(
+            vec![3628800, 1, 120, 610, 134, 99, 98, 0],
+            run(source, &[10, 1, 5, 0])
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            "3:13: Unknown variable 'y'",
+            error("fn main() {\n    let x = 1;\n    out(x + y);\n}")
+        );
+        assert_eq!("1:13: Unknown variable 'x'", error("fn main() { x = 1; }"));
+        assert_eq!(
+            "1:17: Unknown function 'f'",
+            error("fn main() { out(f()); }")
+        );
+        assert_eq!(
+            "1:28: 'f' takes 1 argument(s) but was given 2",
+            error("fn f(a) {} fn main() { out(f(1, 2)); }")
+        );
+        assert_eq!(
+            "1:20: Expected ';' but found '}'",
+            error("fn main() { out(1) }")
+        );
+        assert_eq!("1:13: Unexpected character '#'", error("fn main() { # }"));
+        assert_eq!("1:1: No main function", error("fn f() {}"));
+        assert_eq!(
+            "1:11: Function 'f' is defined twice",
+            error("fn f() {} fn f() {} fn main() {}")
+        );
+        assert_eq!(
+            "1:17: Expected an expression but found ')'",
+            error("fn main() { out() ; }")
+        );
+    }
+}
@@ -0,0 +1,428 @@
+// Instruction sets for Intcode machines. The machine grew over the puzzles - day 2 only had add, multiply and halt,
+// day 5 added input/output and immediate mode and then jumps and comparisons, and day 9 added the relative base - and
+// Program looks every op code up in an InstructionSet rather than assuming the final machine. So a program can be
+// run on an earlier machine (to check it really is a day 2 program, say), and host code can add its own instructions,
+// like a debug print or a trap, in the unused op codes.
+use crate::intcode::{
+    DecodedInstruction, InstructionResult, IntcodeError, Mode, OpCode, Parameter, Program, Word,
+};
+use crate::intcode_analysis::ControlFlowGraph;
+use std::any::{Any, TypeId};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock};
+
+// Implementation of a custom instruction. It gets the program and the instruction's parameters, and says what the
+// program should do next just as the built in instructions do - usually InstructionResult::OkIncrement(number of
+// parameters + 1). Writes should go through Parameter::write, and inputs be taken with Program::take_input, so that
+// history can undo them.
+pub type CustomOperation<W> = Arc<
+    dyn Fn(&mut Program<W>, &[Parameter<W>]) -> Result<InstructionResult<W>, IntcodeError>
+        + Send
+        + Sync,
+>;
+
+#[derive(Clone)]
+pub enum Operation<W> {
+    Builtin(OpCode),
+    Custom(CustomOperation<W>),
+}
+
+#[derive(Clone)]
+pub struct Definition<W> {
+    pub mnemonic: String,
+    pub num_parameters: usize,
+    pub operation: Operation<W>,
+}
+
+#[derive(Clone)]
+pub struct InstructionSet<W = isize> {
+    definitions: BTreeMap<isize, Definition<W>>, //Keyed by op code number (1 to 99)
+    modes: Vec<Mode>,
+    standard: bool, //Still exactly the standard set
+}
+
+impl<W: Word> InstructionSet<W> {
+    // The complete machine, as of day 9.
+    pub fn standard() -> InstructionSet<W> {
+        Dialect::Day9.instruction_set()
+    }
+
+    // The standard set, shared by every program with this word type that doesn't change it. Building one isn't free,
+    // and lots of programs get made (a few thousand for day 19).
+    pub fn shared_standard() -> Arc<InstructionSet<W>> {
+        static STANDARD: OnceLock<Mutex<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>> =
+            OnceLock::new();
+        STANDARD
+            .get_or_init(|| Mutex::new(HashMap::new()))
+            .lock()
+            .unwrap_or_else(|err| panic!("Standard instruction set lock poisoned: {err}"))
+            .entry(TypeId::of::<W>())
+            .or_insert_with(|| Arc::new(InstructionSet::<W>::standard()))
+            .clone()
+            .downcast()
+            .unwrap_or_else(|_| panic!("Standard instruction set stored under the wrong type"))
+    }
+
+    // True if this is the standard set, untouched - every op code is a builtin.
+    pub fn is_standard(&self) -> bool {
+        self.standard
+    }
+
+    pub fn get(&self, code: isize) -> Option<&Definition<W>> {
+        self.definitions.get(&code)
+    }
+
+    pub fn allows_mode(&self, mode: Mode) -> bool {
+        self.standard || self.modes.contains(&mode)
+    }
+
+    // Adds an instruction, replacing whatever op code code did before. Panics unless code is a valid op code number
+    // (1 to 99).
+    #[allow(dead_code)]
+    pub fn define<F>(&mut self, code: isize, mnemonic: &str, num_parameters: usize, operation: F)
+    where
+        F: Fn(&mut Program<W>, &[Parameter<W>]) -> Result<InstructionResult<W>, IntcodeError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        assert!((1..=99).contains(&code), "Invalid op code number {code}");
+        self.standard = false;
+        self.definitions.insert(
+            code,
+            Definition {
+                mnemonic: mnemonic.to_string(),
+                num_parameters,
+                operation: Operation::Custom(Arc::new(operation)),
+            },
+        );
+    }
+
+    // Removes an op code, so that programs using it fail with an invalid op code error.
+    #[allow(dead_code)]
+    pub fn disable(&mut self, code: isize) {
+        self.standard = false;
+        self.definitions.remove(&code);
+    }
+
+    #[allow(dead_code)]
+    pub fn disable_mode(&mut self, mode: Mode) {
+        self.standard = false;
+        self.modes.retain(|allowed| *allowed != mode);
+    }
+}
+
+impl<W> fmt::Debug for InstructionSet<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InstructionSet")
+            .field(
+                "definitions",
+                &self
+                    .definitions
+                    .iter()
+                    .map(|(code, definition)| (code, &definition.mnemonic))
+                    .collect::<Vec<_>>(),
+            )
+            .field("modes", &self.modes)
+            .finish()
+    }
+}
+
+// The machines the puzzles built up, in order - each can run everything the ones before it could.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Dialect {
+    Day2,      //Add, multiply and halt, position mode only
+    Day5Part1, //Plus input, output and immediate mode
+    Day5,      //Plus jumps and comparisons
+    Day9,      //Plus the relative base and relative mode
+}
+
+impl Dialect {
+    fn introducing_op_code(op_code: OpCode) -> Dialect {
+        use OpCode::*;
+        match op_code {
+            Add | Multiply | Halt => Dialect::Day2,
+            Input | Output => Dialect::Day5Part1,
+            JumpIfTrue | JumpIfFalse | LessThan | Equals => Dialect::Day5,
+            RelativeBaseOffset => Dialect::Day9,
+        }
+    }
+
+    fn introducing_mode(mode: Mode) -> Dialect {
+        match mode {
+            Mode::Position => Dialect::Day2,
+            Mode::Immediate => Dialect::Day5Part1,
+            Mode::Relative => Dialect::Day9,
+        }
+    }
+
+    pub fn instruction_set<W: Word>(&self) -> InstructionSet<W> {
+        InstructionSet {
+            definitions: OpCode::ALL
+                .iter()
+                .filter(|op_code| Dialect::introducing_op_code(**op_code) <= *self)
+                .map(|op_code| {
+                    (
+                        op_code.code(),
+                        Definition {
+                            mnemonic: op_code.mnemonic().to_string(),
+                            num_parameters: op_code.num_parameters(),
+                            operation: Operation::Builtin(*op_code),
+                        },
+                    )
+                })
+                .collect(),
+            modes: [Mode::Position, Mode::Immediate, Mode::Relative]
+                .into_iter()
+                .filter(|mode| Dialect::introducing_mode(*mode) <= *self)
+                .collect(),
+            standard: *self == Dialect::Day9,
+        }
+    }
+
+    // The earliest dialect that has this instruction's op code and all of its modes. Custom instructions aren't part
+    // of any dialect, so only their modes count.
+    fn needed_by(instruction: &DecodedInstruction) -> Dialect {
+        instruction
+            .parameters
+            .iter()
+            .map(|(mode, _)| Dialect::introducing_mode(*mode))
+            .fold(
+                instruction
+                    .op_code()
+                    .map_or(Dialect::Day2, Dialect::introducing_op_code),
+                Dialect::max,
+            )
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Dialect::Day2 => "day 2",
+            Dialect::Day5Part1 => "day 5 part 1",
+            Dialect::Day5 => "day 5",
+            Dialect::Day9 => "day 9",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DialectReport {
+    pub dialect: Dialect,
+    pub needed_by: Option<DecodedInstruction>, //First instruction that needs that dialect (None for day 2)
+    //False if there may be code the static analysis couldn't see - it found jumps it couldn't follow for certain,
+    //writes into code, or invalid instructions.
+    pub complete: bool,
+}
+
+impl fmt::Display for DialectReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Needs the {} machine", self.dialect)?;
+        if let Some(instruction) = &self.needed_by {
+            write!(f, " (for {})", instruction.to_string().trim_start())?;
+        }
+        if !self.complete {
+            write!(f, ", or possibly later - not all code could be found")?;
+        }
+        Ok(())
+    }
+}
+
+// Works out the earliest machine that can run image, from the instructions reachable from the start.
+#[allow(dead_code)]
+pub fn required_dialect(image: &[isize]) -> DialectReport {
+    let graph = ControlFlowGraph::new(image);
+    let mut report = DialectReport {
+        dialect: Dialect::Day2,
+        needed_by: None,
+        complete: graph.unresolved_jumps.is_empty()
+            && graph.self_modifying_writes.is_empty()
+            && graph.invalid.is_empty(),
+    };
+    for instruction in graph.blocks.values().flat_map(|block| &block.instructions) {
+        let dialect = Dialect::needed_by(instruction);
+        if dialect > report.dialect {
+            report.dialect = dialect;
+            report.needed_by = Some(instruction.clone());
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::ProgramResult;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_earlier_machines() {
+        //Day 5 style: outputs input * 3.
+        let image = [3, 9, 1002, 9, 3, 9, 4, 9, 99, 0];
+        let mut program = Program::new(&image);
+        program.set_instruction_set(Dialect::Day5Part1.instruction_set());
+        program.add_input(7);
+        assert_eq!(Ok(ProgramResult::Halted), program.run_checked());
+        assert_eq!(vec![21], *program.outputs());
+
+        //Too new for day 2, both for its op codes and its immediate mode.
+        let mut program = Program::new(&image);
+        program.set_instruction_set(Dialect::Day2.instruction_set());
+        assert_eq!(
+            Err(IntcodeError::InvalidOpCode {
+                instruction_pointer: 0,
+                op_code: "3".to_string()
+            }),
+            program.run_checked()
+        );
+        let mut program = Program::new(&[1101, 1, 2, 0, 99]);
+        program.set_instruction_set(Dialect::Day2.instruction_set());
+        assert_eq!(
+            Err(IntcodeError::InvalidMode {
+                instruction_pointer: 0,
+                mode: 1
+            }),
+            program.run_checked()
+        );
+
+        //Disabling by hand.
+        let mut instruction_set = InstructionSet::standard();
+        instruction_set.disable(OpCode::Multiply.code());
+        let mut program = Program::new(&[1, 0, 0, 0, 2, 0, 0, 0, 99]);
+        program.set_instruction_set(instruction_set);
+        assert_eq!(
+            "Invalid OpCode '2' at address 4",
+            program.run_checked().unwrap_err().to_string()
+        );
+        assert_eq!(2, program.get_value_at(0));
+    }
+
+    #[test]
+    fn test_shared_standard_set() {
+        //Programs share one standard set per word type.
+        let first = Program::new(&[99]);
+        let second = Program::new(&[1101, 1, 2, 0, 99]);
+        assert!(std::ptr::eq(
+            first.instruction_set(),
+            second.instruction_set()
+        ));
+        assert!(first.instruction_set().is_standard());
+        let big = Program::<i128>::from_words(&[99]);
+        assert!(big.instruction_set().is_standard());
+
+        //Changing a set in any way means its op codes have to be looked up again.
+        let mut instruction_set = InstructionSet::<isize>::standard();
+        instruction_set.disable_mode(Mode::Relative);
+        assert!(!instruction_set.is_standard());
+        assert!(!Dialect::Day5.instruction_set::<isize>().is_standard());
+    }
+
+    #[test]
+    fn test_custom_instructions() {
+        let printed = Arc::new(Mutex::new(vec![]));
+        let mut instruction_set = InstructionSet::standard();
+        //dbg a: records a's value
+        let log = Arc::clone(&printed);
+        instruction_set.define(50, "dbg", 1, move |program, parameters| {
+            let value = parameters[0].mode_adjusted_value(program)?;
+            log.lock().unwrap().push(value);
+            Ok(InstructionResult::OkIncrement(2))
+        });
+        //swap a b: exchanges two cells
+        instruction_set.define(51, "swap", 2, |program, parameters| {
+            let a = parameters[0].mode_adjusted_value(program)?;
+            let b = parameters[1].mode_adjusted_value(program)?;
+            parameters[0].write(program, b)?;
+            parameters[1].write(program, a)?;
+            Ok(InstructionResult::OkIncrement(3))
+        });
+        //trap: stops the program with an error
+        instruction_set.define(52, "trap", 0, |_, _| {
+            Err(IntcodeError::Trap {
+                instruction_pointer: 0,
+                message: "Reached a trap".to_string(),
+            })
+        });
+
+        let image = [50, 10, 51, 10, 11, 250, 10, 1150, 7, 52, 3, 4];
+        let mut program = Program::new(&image);
+        program.set_instruction_set(instruction_set.clone());
+        program.enable_history();
+        assert_eq!(
+            Err(IntcodeError::Trap {
+                instruction_pointer: 9,
+                message: "Reached a trap".to_string()
+            }),
+            program.run_checked()
+        );
+        assert_eq!(vec![3, 4, 7], *printed.lock().unwrap());
+        assert_eq!(4, program.get_value_at(10));
+        assert_eq!(3, program.get_value_at(11));
+
+        //Custom writes can be undone like any other.
+        program.rewind_to(1);
+        assert_eq!(3, program.get_value_at(10));
+        assert_eq!(4, program.get_value_at(11));
+
+        //Standard programs run as normal.
+        let mut program = Program::new(&[1101, 2, 3, 5, 99, 0]);
+        program.set_instruction_set(instruction_set.clone());
+        program.run();
+        assert_eq!(5, program.get_value_at(5));
+
+        //The tools see custom instructions the way the program does.
+        let image = [1150, 7, 104, 1, 99];
+        let mut program = Program::new(&image);
+        program.enable_profiling();
+        program.set_instruction_set(instruction_set.clone());
+        program.run();
+        assert_eq!(
+            "           1 |     0: dbg 7
+           1 |     2: out 1
+           1 |     4: halt
+",
+            program.profile().unwrap().annotated_listing()
+        );
+        let graph = ControlFlowGraph::with_instruction_set(&image, &instruction_set);
+        assert_eq!(3, graph.blocks[&0].instructions.len());
+        assert_eq!(vec![0], graph.unresolved_jumps);
+        assert!(graph.invalid.is_empty());
+        assert_eq!(
+            vec![(0, "Invalid OpCode '50'".to_string())],
+            ControlFlowGraph::new(&image).invalid
+        );
+    }
+
+    #[test]
+    fn test_required_dialect() {
+        let report = required_dialect(&[1, 0, 0, 0, 2, 0, 0, 0, 99]);
+        assert_eq!(Dialect::Day2, report.dialect);
+        assert_eq!(None, report.needed_by);
+        //Writes into its own code, so we can't be sure.
+        assert!(!report.complete);
+
+        let report = required_dialect(&[3, 9, 1002, 9, 3, 9, 4, 9, 99, 0]);
+        assert_eq!(Dialect::Day5Part1, report.dialect);
+        assert_eq!(0, report.needed_by.unwrap().address);
+
+        let report = required_dialect(&[3, 13, 1008, 13, 8, 13, 1005, 13, 10, 99, 4, 13, 99, 0]);
+        assert_eq!(Dialect::Day5, report.dialect);
+        assert!(report.complete);
+        assert_eq!(
+            "Needs the day 5 machine (for 2: eq [13] 8 [13])",
+            report.to_string()
+        );
+
+        //Relative mode on its own is enough to need day 9.
+        let report = required_dialect(&[204, 0, 99]);
+        assert_eq!(Dialect::Day9, report.dialect);
+
+        //Dead code doesn't count.
+        let report = required_dialect(&[99, 109, 1]);
+        assert_eq!(Dialect::Day2, report.dialect);
+    }
+}
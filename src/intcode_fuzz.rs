@@ -0,0 +1,443 @@
+// Differential fuzzing of the Intcode interpreters. Random - but well-formed and always terminating - programs are
+// run on Program (and the threaded backend) and on a deliberately simple reference interpreter written straight from
+// the puzzle text, and everything observable has to agree: outputs, memory, relative base, instruction pointer and
+// how the run ended.
+//
+// Generated programs are laid out as code, then a data region (which the relative base points into), then one loop
+// counter per nesting level, then a jump table for position-mode jump targets, with a few cells far beyond the end of
+// the image to exercise sparse memory. Writes only go to the data and far regions, so the code never changes, and
+// every loop is a countdown on its own counter, so every program finishes.
+use crate::intcode::{IntcodeError, Program, ProgramResult};
+use crate::intcode_threaded::ThreadedProgram;
+
+const DATA_START: isize = 2000;
+const DATA_LEN: isize = 16;
+const MAX_RELATIVE_SHIFT: isize = 4; //Relative base is DATA_START plus at most this
+const MAX_LOOP_DEPTH: usize = 3;
+const COUNTER_START: isize = DATA_START + DATA_LEN;
+const JUMP_TABLE_START: isize = COUNTER_START + MAX_LOOP_DEPTH as isize;
+const MAX_JUMP_TABLE_LEN: isize = 64;
+const FAR_START: isize = 10000;
+const FAR_LEN: isize = 8;
+
+// SplitMix64 - plenty for generating test programs, and means we don't need a dependency.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in low..=high
+    fn range(&mut self, low: isize, high: isize) -> isize {
+        low + (self.next() % (high - low + 1) as u64) as isize
+    }
+
+    fn chance(&mut self, percent: u64) -> bool {
+        self.next() % 100 < percent
+    }
+}
+
+pub struct FuzzProgram {
+    pub image: Vec<isize>,
+    pub code_len: usize, //Code is image[..code_len]
+    pub inputs: Vec<isize>,
+}
+
+struct Generator {
+    rng: Rng,
+    code: Vec<isize>,
+    jump_table: Vec<isize>,
+    shifted: bool, //Inside an arb pair, so the relative base is already shifted
+}
+
+impl Generator {
+    // (mode, parameter) for a value that's read.
+    fn read_parameter(&mut self) -> (isize, isize) {
+        match self.rng.range(0, 9) {
+            0..=3 => (0, self.readable_address()),
+            4..=6 => (1, self.rng.range(-50, 50)),
+            _ => (2, self.rng.range(0, DATA_LEN - MAX_RELATIVE_SHIFT - 1)),
+        }
+    }
+
+    fn readable_address(&mut self) -> isize {
+        match self.rng.range(0, 9) {
+            0 if !self.code.is_empty() => self.rng.range(0, self.code.len() as isize - 1),
+            1 => FAR_START + self.rng.range(0, FAR_LEN - 1),
+            _ => DATA_START + self.rng.range(0, DATA_LEN - 1),
+        }
+    }
+
+    fn write_parameter(&mut self) -> (isize, isize) {
+        match self.rng.range(0, 9) {
+            0..=4 => (0, DATA_START + self.rng.range(0, DATA_LEN - 1)),
+            5 => (0, FAR_START + self.rng.range(0, FAR_LEN - 1)),
+            _ => (2, self.rng.range(0, DATA_LEN - MAX_RELATIVE_SHIFT - 1)),
+        }
+    }
+
+    fn emit(&mut self, op_code: isize, parameters: &[(isize, isize)]) {
+        let modes = parameters
+            .iter()
+            .rev()
+            .fold(0, |modes, (mode, _)| modes * 10 + mode);
+        self.code.push(modes * 100 + op_code);
+        self.code.extend(parameters.iter().map(|(_, value)| value));
+    }
+
+    // A jump target parameter - either immediate, or via a jump table entry (filled in later by set_target).
+    fn target_parameter(&mut self) -> (isize, isize) {
+        if self.rng.chance(30) && (self.jump_table.len() as isize) < MAX_JUMP_TABLE_LEN {
+            self.jump_table.push(0);
+            (0, JUMP_TABLE_START + self.jump_table.len() as isize - 1)
+        } else {
+            (1, 0)
+        }
+    }
+
+    // Sets the target of the jump whose target parameter is at code[index].
+    fn set_target(&mut self, index: usize, target: usize) {
+        if self.code[index - 2] / 1000 % 10 == 0 {
+            let entry = (self.code[index] - JUMP_TABLE_START) as usize;
+            self.jump_table[entry] = target as isize;
+        } else {
+            self.code[index] = target as isize;
+        }
+    }
+
+    fn block(&mut self, depth: usize, statements: usize) {
+        for _ in 0..statements {
+            match self.rng.range(0, 20) {
+                20 => self.emit(99, &[]),
+                8..=9 => {
+                    let address = self.write_parameter();
+                    self.emit(3, &[address]);
+                }
+                10..=12 => {
+                    let value = self.read_parameter();
+                    self.emit(4, &[value]);
+                }
+                13..=14 if !self.shifted => {
+                    let shift = self.rng.range(1, MAX_RELATIVE_SHIFT);
+                    self.emit(9, &[(1, shift)]);
+                    self.shifted = true;
+                    let statements = self.rng.range(1, 4) as usize;
+                    self.block(depth, statements);
+                    self.shifted = false;
+                    self.emit(9, &[(1, -shift)]);
+                }
+                15..=16 => {
+                    //Skip the body if the condition is true (jnz) or false (jz).
+                    let op_code = self.rng.range(5, 6);
+                    let condition = self.read_parameter();
+                    let target = self.target_parameter();
+                    self.emit(op_code, &[condition, target]);
+                    let target_index = self.code.len() - 1;
+                    let statements = self.rng.range(1, 4) as usize;
+                    self.block(depth, statements);
+                    let end = self.code.len();
+                    self.set_target(target_index, end);
+                }
+                17..=19 if depth < MAX_LOOP_DEPTH => {
+                    let counter = COUNTER_START + depth as isize;
+                    let iterations = self.rng.range(1, 4);
+                    self.emit(1, &[(1, 0), (1, iterations), (0, counter)]);
+                    let start = self.code.len();
+                    let statements = self.rng.range(1, 5) as usize;
+                    self.block(depth + 1, statements);
+                    self.emit(1, &[(0, counter), (1, -1), (0, counter)]);
+                    let target = self.target_parameter();
+                    self.emit(5, &[(0, counter), target]);
+                    let target_index = self.code.len() - 1;
+                    self.set_target(target_index, start);
+                }
+                _ => {
+                    let op_code = [1, 2, 7, 8][self.rng.range(0, 3) as usize];
+                    let (a, b) = (self.read_parameter(), self.read_parameter());
+                    let c = self.write_parameter();
+                    self.emit(op_code, &[a, b, c]);
+                }
+            }
+        }
+    }
+}
+
+// A random program built from seed.
+pub fn generate(seed: u64) -> FuzzProgram {
+    let mut generator = Generator {
+        rng: Rng(seed),
+        code: vec![],
+        jump_table: vec![],
+        shifted: false,
+    };
+    generator.emit(9, &[(1, DATA_START)]);
+    let statements = generator.rng.range(1, 12) as usize;
+    generator.block(0, statements);
+    generator.emit(99, &[]);
+    assert!(
+        generator.code.len() as isize <= DATA_START,
+        "Program too long"
+    );
+
+    let code_len = generator.code.len();
+    let mut image = generator.code;
+    image.resize(DATA_START as usize, 0);
+    for _ in 0..DATA_LEN {
+        image.push(generator.rng.range(-20, 20));
+    }
+    image.extend((0..MAX_LOOP_DEPTH).map(|_| 0));
+    image.extend(&generator.jump_table);
+
+    let input_count = generator.rng.range(0, 12);
+    let inputs = (0..input_count)
+        .map(|_| generator.rng.range(-100, 100))
+        .collect();
+    FuzzProgram {
+        image,
+        code_len,
+        inputs,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Ending {
+    Halted,
+    AwaitingInput,
+    Overflow,
+}
+
+// Everything observable about a finished run.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Observation {
+    pub ending: Ending,
+    pub instruction_pointer: usize,
+    pub relative_base: isize,
+    pub outputs: Vec<isize>,
+    pub memory: Vec<isize>, //The image's cells followed by the far region
+}
+
+// The addresses included in an Observation's memory.
+fn observed_addresses(image_len: usize) -> impl Iterator<Item = isize> {
+    (0..image_len as isize).chain(FAR_START..FAR_START + FAR_LEN)
+}
+
+// As simple as possible, and written separately from Program so that they don't share mistakes.
+pub fn reference_run(image: &[isize], inputs: &[isize]) -> Observation {
+    let mut memory = image.to_vec();
+    let mut inputs = inputs.iter();
+    let mut outputs = vec![];
+    let mut ip: isize = 0;
+    let mut relative_base: isize = 0;
+
+    let ending = loop {
+        let instruction = memory[ip as usize];
+        let op_code = instruction % 100;
+        let mode = |n: u32| instruction / 10_isize.pow(n + 1) % 10;
+        let address = |n: u32| {
+            let parameter = *memory.get((ip + n as isize) as usize).unwrap_or(&0);
+            match mode(n) {
+                0 => parameter,
+                2 => relative_base + parameter,
+                _ => panic!("Parameter {n} at {ip} has no address"),
+            }
+        };
+        let read = |n: u32| {
+            if mode(n) == 1 {
+                *memory.get((ip + n as isize) as usize).unwrap_or(&0)
+            } else {
+                *memory.get(address(n) as usize).unwrap_or(&0)
+            }
+        };
+
+        let next_ip = match op_code {
+            1 | 2 | 7 | 8 => ip + 4,
+            3 | 4 | 9 => ip + 2,
+            5 if read(1) != 0 => read(2),
+            6 if read(1) == 0 => read(2),
+            5 | 6 => ip + 3,
+            _ => ip + 1,
+        };
+        let relative_base_offset = if op_code == 9 { read(1) } else { 0 };
+        //The cell written by this instruction, if any, and its new value.
+        let written = match op_code {
+            1 | 2 => {
+                let result = if op_code == 1 {
+                    read(1).checked_add(read(2))
+                } else {
+                    read(1).checked_mul(read(2))
+                };
+                match result {
+                    Some(result) => Some((address(3), result)),
+                    None => break Ending::Overflow,
+                }
+            }
+            3 => match inputs.next() {
+                Some(&input) => Some((address(1), input)),
+                None => break Ending::AwaitingInput,
+            },
+            4 => {
+                outputs.push(read(1));
+                None
+            }
+            7 => Some((address(3), (read(1) < read(2)) as isize)),
+            8 => Some((address(3), (read(1) == read(2)) as isize)),
+            5 | 6 | 9 | 99 => None,
+            _ => panic!("Bad op code {instruction} at {ip}"),
+        };
+        if let Some((address, value)) = written {
+            if address as usize >= memory.len() {
+                memory.resize(address as usize + 1, 0);
+            }
+            memory[address as usize] = value;
+        }
+        relative_base += relative_base_offset;
+        ip = next_ip;
+        if op_code == 99 {
+            break Ending::Halted;
+        }
+    };
+
+    Observation {
+        ending,
+        instruction_pointer: ip as usize,
+        relative_base,
+        outputs,
+        memory: observed_addresses(image.len())
+            .map(|address| *memory.get(address as usize).unwrap_or(&0))
+            .collect(),
+    }
+}
+
+pub fn program_run(image: &[isize], inputs: &[isize]) -> Observation {
+    observe(Program::new(image), image.len(), inputs)
+}
+
+// Runs program, which was loaded from an image image_len cells long, and observes the result.
+pub fn observe(mut program: Program, image_len: usize, inputs: &[isize]) -> Observation {
+    for &input in inputs {
+        program.add_input(input);
+    }
+    let (ending, instruction_pointer) = match program.run_checked() {
+        Ok(ProgramResult::Halted) => (Ending::Halted, program.instruction_pointer()),
+        Ok(ProgramResult::AwaitingInput) => (Ending::AwaitingInput, program.instruction_pointer()),
+        Err(IntcodeError::Overflow {
+            instruction_pointer,
+            ..
+        }) => (Ending::Overflow, instruction_pointer),
+        Err(err) => panic!("Unexpected error from a well-formed program: {err}"),
+    };
+    Observation {
+        ending,
+        instruction_pointer,
+        relative_base: program.relative_base(),
+        outputs: program.outputs().clone(),
+        memory: observed_addresses(image_len)
+            .map(|address| program.get_value_at(address))
+            .collect(),
+    }
+}
+
+// The threaded backend doesn't check for overflow, and doesn't expose its registers, so this only covers outputs,
+// memory and the ending of runs that don't overflow.
+pub fn threaded_run(image: &[isize], inputs: &[isize]) -> (Ending, Vec<isize>, Vec<isize>) {
+    let mut program = ThreadedProgram::new(image);
+    for &input in inputs {
+        program.add_input(input);
+    }
+    let ending = match program.run() {
+        ProgramResult::Halted => Ending::Halted,
+        ProgramResult::AwaitingInput => Ending::AwaitingInput,
+    };
+    let memory = observed_addresses(image.len())
+        .map(|address| program.get_value_at(address))
+        .collect();
+    (ending, program.outputs().clone(), memory)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{decode, DecodedInstruction};
+    use crate::intcode_dialect::InstructionSet;
+    use std::collections::HashSet;
+
+    const SEEDS: u64 = 2000;
+
+    fn instructions(program: &FuzzProgram) -> Vec<DecodedInstruction> {
+        let instruction_set = InstructionSet::<isize>::standard();
+        let mut instructions = vec![];
+        let mut address = 0;
+        while address < program.code_len {
+            let instruction = decode(&program.image, address, &instruction_set).unwrap();
+            address = instruction.next_address();
+            instructions.push(instruction);
+        }
+        instructions
+    }
+
+    fn listing(program: &FuzzProgram) -> String {
+        instructions(program)
+            .iter()
+            .map(|instruction| instruction.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_program_matches_reference() {
+        for seed in 0..SEEDS {
+            let program = generate(seed);
+            let expected = reference_run(&program.image, &program.inputs);
+            let actual = program_run(&program.image, &program.inputs);
+            assert_eq!(
+                expected,
+                actual,
+                "Seed {seed} with inputs {:?}:\n{}",
+                program.inputs,
+                listing(&program)
+            );
+        }
+    }
+
+    #[test]
+    fn test_threaded_matches_reference() {
+        for seed in 0..SEEDS {
+            let program = generate(seed);
+            let expected = reference_run(&program.image, &program.inputs);
+            if expected.ending == Ending::Overflow {
+                continue;
+            }
+            assert_eq!(
+                (expected.ending, expected.outputs, expected.memory),
+                threaded_run(&program.image, &program.inputs),
+                "Seed {seed} with inputs {:?}:\n{}",
+                program.inputs,
+                listing(&program)
+            );
+        }
+    }
+
+    #[test]
+    fn test_generator_coverage() {
+        //Between them the programs should use every op code and mode, and end in every possible way.
+        let mut op_codes = HashSet::new();
+        let mut modes = HashSet::new();
+        let mut endings = HashSet::new();
+        for seed in 0..SEEDS {
+            let program = generate(seed);
+            for instruction in instructions(&program) {
+                op_codes.insert(instruction.op_code());
+                modes.extend(instruction.parameters.iter().map(|(mode, _)| *mode));
+            }
+            endings.insert(reference_run(&program.image, &program.inputs).ending);
+        }
+        assert_eq!(10, op_codes.len());
+        assert_eq!(3, modes.len());
+        assert_eq!(3, endings.len());
+    }
+}
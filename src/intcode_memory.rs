@@ -0,0 +1,249 @@
+// Tools for looking at an Intcode program's memory: dumping ranges of it, diffing it before and after something
+// happens, searching it for values, and hunting down the cells that hold something interesting (a game's score or
+// lives, say) by narrowing the candidates down as the program runs.
+#![allow(dead_code)]
+
+use crate::intcode::{Program, Word};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::ops::Range;
+
+const DUMP_WIDTH: isize = 8; //Values per line
+
+// The values in range, DUMP_WIDTH to a line, each line starting with the address of its first value.
+pub fn dump<W: Word>(program: &Program<W>, range: Range<isize>) -> String {
+    let values: Vec<String> = range
+        .clone()
+        .map(|address| program.get_value_at(address).to_string())
+        .collect();
+    let width = values.iter().map(|value| value.len()).max().unwrap_or(0);
+    let mut dump = String::new();
+    for (line, chunk) in values.chunks(DUMP_WIDTH as usize).enumerate() {
+        write!(dump, "{:>6}:", range.start + line as isize * DUMP_WIDTH).unwrap();
+        for value in chunk {
+            write!(dump, " {:>width$}", value).unwrap();
+        }
+        dump.push('\n');
+    }
+    dump
+}
+
+// A copy of all of a program's memory at some point - the image and anything written outside it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot<W = isize> {
+    cells: BTreeMap<isize, W>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change<W = isize> {
+    pub address: isize,
+    pub old: W,
+    pub new: W,
+}
+
+impl<W: Word> Snapshot<W> {
+    pub fn of(program: &Program<W>) -> Snapshot<W> {
+        Snapshot {
+            cells: program
+                .cells()
+                .map(|(address, value)| (address, value.clone()))
+                .collect(),
+        }
+    }
+
+    // Cells never written to are zero, as they are in the program.
+    pub fn get(&self, address: isize) -> W {
+        self.cells.get(&address).cloned().unwrap_or_else(W::zero)
+    }
+
+    // Every cell that's different in later, in address order.
+    pub fn diff(&self, later: &Snapshot<W>) -> Vec<Change<W>> {
+        let mut addresses: Vec<isize> = self
+            .cells
+            .keys()
+            .chain(later.cells.keys())
+            .copied()
+            .collect();
+        addresses.sort_unstable();
+        addresses.dedup();
+        addresses
+            .into_iter()
+            .map(|address| Change {
+                address,
+                old: self.get(address),
+                new: later.get(address),
+            })
+            .filter(|change| change.old != change.new)
+            .collect()
+    }
+
+    // Addresses holding value, in order.
+    pub fn find(&self, value: &W) -> Vec<isize> {
+        self.cells
+            .iter()
+            .filter(|(_, cell)| *cell == value)
+            .map(|(address, _)| *address)
+            .collect()
+    }
+
+    // Addresses at which values appear in consecutive cells, in order.
+    pub fn find_sequence(&self, values: &[W]) -> Vec<isize> {
+        match values.first() {
+            Some(first) => self
+                .find(first)
+                .into_iter()
+                .filter(|&start| {
+                    values
+                        .iter()
+                        .zip(start..)
+                        .all(|(value, address)| self.get(address) == *value)
+                })
+                .collect(),
+            None => vec![],
+        }
+    }
+}
+
+// Diff of two programs' memory (e.g. a program and a clone of it from earlier).
+pub fn diff<W: Word>(before: &Program<W>, after: &Program<W>) -> Vec<Change<W>> {
+    Snapshot::of(before).diff(&Snapshot::of(after))
+}
+
+// Narrows down which cell holds some value of interest. Every cell that has a value when the hunt starts is a
+// candidate; then, each time something happens, only the cells whose values changed the way the thing being hunted
+// would have are kept. Cells first written after the start aren't considered.
+#[derive(Clone, Debug)]
+pub struct Hunt<W = isize> {
+    candidates: BTreeMap<isize, W>, //Address -> value when last checked
+}
+
+impl<W: Word> Hunt<W> {
+    pub fn new(program: &Program<W>) -> Hunt<W> {
+        Hunt {
+            candidates: Snapshot::of(program).cells,
+        }
+    }
+
+    // Keeps the candidates for which keep(value when last checked, value now) is true.
+    pub fn refine(&mut self, program: &Program<W>, keep: impl Fn(&W, &W) -> bool) {
+        self.candidates.retain(|address, old| {
+            let new = program.get_value_at(*address);
+            let kept = keep(old, &new);
+            *old = new;
+            kept
+        });
+    }
+
+    pub fn retain_value(&mut self, program: &Program<W>, value: &W) {
+        self.refine(program, |_, new| new == value);
+    }
+
+    pub fn retain_changed(&mut self, program: &Program<W>) {
+        self.refine(program, |old, new| old != new);
+    }
+
+    pub fn retain_unchanged(&mut self, program: &Program<W>) {
+        self.refine(program, |old, new| old == new);
+    }
+
+    pub fn candidates(&self) -> Vec<isize> {
+        self.candidates.keys().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Reads a number each round, adds it to the score (at 101) and loses a life (lives at 103) if it was 0. Both are
+    //outside the image.
+    const GAME: [isize; 20] = [
+        3, 100, 1, 101, 100, 101, 1008, 100, 0, 102, 1006, 102, 17, 1001, 103, -1, 103, 1105, 1, 0,
+    ];
+
+    fn play(program: &mut Program, input: isize) {
+        program.add_input(input);
+        program.run();
+    }
+
+    #[test]
+    fn test_dump() {
+        let mut program = Program::new(&GAME);
+        program.set_value_at(103, 3);
+        assert_eq!(
+            "     0:    3  100    1  101  100  101 1008  100
+     8:    0  102 1006  102   17 1001  103   -1
+    16:  103 1105    1    0
+",
+            dump(&program, 0..20)
+        );
+        assert_eq!("   100: 0 0 0 3\n", dump(&program, 100..104));
+        assert_eq!("", dump(&program, 5..5));
+    }
+
+    #[test]
+    fn test_diff_and_find() {
+        let mut program = Program::new(&GAME);
+        program.set_value_at(103, 3);
+        play(&mut program, 7);
+        let before = program.clone();
+        play(&mut program, 0);
+        assert_eq!(
+            vec![
+                Change {
+                    address: 100,
+                    old: 7,
+                    new: 0
+                },
+                Change {
+                    address: 102,
+                    old: 0,
+                    new: 1
+                },
+                Change {
+                    address: 103,
+                    old: 3,
+                    new: 2
+                },
+            ],
+            diff(&before, &program)
+        );
+        assert!(diff(&program, &program).is_empty());
+
+        let snapshot = Snapshot::of(&program);
+        assert_eq!(vec![2, 18, 102], snapshot.find(&1));
+        assert_eq!(vec![101], snapshot.find(&7));
+        assert_eq!(vec![2], snapshot.find_sequence(&[1, 101, 100]));
+        assert_eq!(vec![101], snapshot.find_sequence(&[7, 1, 2]));
+        assert!(snapshot.find_sequence(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_hunt() {
+        let mut program = Program::new(&GAME);
+        program.set_value_at(103, 3);
+        play(&mut program, 0);
+
+        //The score goes up by whatever we enter.
+        let mut score = Hunt::new(&program);
+        play(&mut program, 5);
+        score.refine(&program, |old, new| *new == old + 5);
+        assert_eq!(vec![100, 101], score.candidates());
+        play(&mut program, 3);
+        score.refine(&program, |old, new| *new == old + 3);
+        assert_eq!(vec![101], score.candidates());
+        score.retain_value(&program, &8);
+        assert_eq!(vec![101], score.candidates());
+
+        //Lives only change when we enter 0, and then go down by one.
+        let mut lives = Hunt::new(&program);
+        play(&mut program, 4);
+        lives.retain_unchanged(&program);
+        play(&mut program, 0);
+        lives.retain_changed(&program);
+        assert_eq!(vec![102, 103], lives.candidates());
+        play(&mut program, 0);
+        lives.refine(&program, |old, new| *new == old - 1);
+        assert_eq!(vec![103], lives.candidates());
+    }
+}
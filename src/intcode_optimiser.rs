@@ -0,0 +1,849 @@
+// A peephole optimiser for Intcode images. optimise rewrites instructions in place, so every address stays where it
+// was and hosts can still poke at fixed cells (like day 2's noun and verb): jumps to unconditional jumps go straight
+// to the end of the chain, and arithmetic or comparisons on two immediates become a store of the answer. It works on
+// whatever code it can find - from address 0, and from every constant pushed onto the stack, as that's how calls save
+// their return address - and reports every change it makes.
+// optimise_and_compact also removes unreachable code and jumps that never do anything, moving everything after them
+// down. That's only possible when every address the program uses can be found and fixed up: all jump targets must be
+// immediate, the code can't modify itself and there can't be any relative mode (a relative base is just a number,
+// which could be an address).
+//
+// Cells that found code reads or writes in position mode are data, and rewrites never change or rely on them. Code it
+// couldn't find, relative mode and the host could still touch anything, so a Program running an optimised image
+// guards every cell a rewrite changed or relied on: reading one gives the original value, and writing one, or running
+// an instruction that overlaps one from anywhere but its start, puts the original image back before carrying on. Up
+// to then the two images behave the same, so the program can't tell. Passing --optimise to the runner optimises every
+// program before it runs, to check the answers don't change, and reports any program that needed its image put back.
+#![allow(dead_code)]
+
+use crate::intcode::{DecodedInstruction, Mode, OpCode, Program};
+use crate::intcode_analysis::{ControlFlowGraph, SelfModifyingWrite};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rewrite {
+    Threaded {
+        address: usize,
+        from: usize,
+        to: usize,
+    },
+    Folded {
+        address: usize,
+        op_code: OpCode,
+        value: isize,
+    },
+    RemovedUnreachable(Range<usize>),
+    RemovedNoOp {
+        address: usize,
+        op_code: OpCode,
+    },
+}
+
+// Addresses are always those in the original image.
+impl fmt::Display for Rewrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rewrite::Threaded { address, from, to } => write!(
+                f,
+                "Threaded the jump at {} through {} to {}",
+                address, from, to
+            ),
+            Rewrite::Folded {
+                address,
+                op_code,
+                value,
+            } => write!(
+                f,
+                "Folded the {} at {} into a store of {}",
+                op_code.mnemonic(),
+                address,
+                value
+            ),
+            Rewrite::RemovedUnreachable(range) => write!(
+                f,
+                "Removed unreachable cells {}..{}",
+                range.start, range.end
+            ),
+            Rewrite::RemovedNoOp { address, op_code } => {
+                write!(f, "Removed the no-op {} at {}", op_code.mnemonic(), address)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OptimiseError {
+    UnresolvedJumps(Vec<usize>), //Addresses of the jumps the control flow graph can't follow
+    SelfModifying(Vec<SelfModifyingWrite>),
+    NotRelocatable(String), //Why the image can't be compacted
+}
+
+impl fmt::Display for OptimiseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptimiseError::UnresolvedJumps(jumps) => write!(
+                f,
+                "Not all the code can be found ({} jump(s) that can't be followed, the first at {})",
+                jumps.len(),
+                jumps[0]
+            ),
+            OptimiseError::SelfModifying(writes) => write!(
+                f,
+                "The code modifies itself ({} write(s), the first by the instruction at {})",
+                writes.len(),
+                writes[0].instruction
+            ),
+            OptimiseError::NotRelocatable(reason) => write!(f, "Can't compact: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for OptimiseError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Optimised {
+    pub image: Vec<isize>,
+    pub rewrites: Vec<Rewrite>,
+    original: Vec<isize>,
+    guarded: Vec<bool>, //Cells a rewrite changed or relied on, by address. Compacted images aren't guarded.
+    starts: Vec<bool>,  //Starts of the instructions the guarded cells belong to
+}
+
+impl Optimised {
+    fn guard(&mut self, instruction: &DecodedInstruction) {
+        self.guarded[instruction.address..instruction.next_address()].fill(true);
+        self.starts[instruction.address] = true;
+    }
+
+    pub fn guards(&self, address: usize) -> bool {
+        self.guarded.get(address) == Some(&true)
+    }
+
+    pub fn original(&self, address: usize) -> isize {
+        self.original[address]
+    }
+
+    pub fn guarded_cells(&self) -> impl Iterator<Item = usize> + '_ {
+        self.guarded
+            .iter()
+            .enumerate()
+            .filter(|(_, guarded)| **guarded)
+            .map(|(address, _)| address)
+    }
+
+    // True if running size cells from address as an instruction reads guarded cells as something other than the
+    // instruction they were guarded as.
+    pub fn breaks(&self, address: usize, size: usize) -> bool {
+        self.starts.get(address) != Some(&true)
+            && (address..address + size).any(|address| self.guards(address))
+    }
+}
+
+// Encodes an instruction with op code number code back into cells.
+fn encode(code: isize, parameters: &[(Mode, isize)]) -> Vec<isize> {
+    let modes = parameters.iter().rev().fold(0, |modes, (mode, _)| {
+        modes * 10
+            + match mode {
+                Mode::Position => 0,
+                Mode::Immediate => 1,
+                Mode::Relative => 2,
+            }
+    });
+    let mut cells = vec![modes * 100 + code];
+    cells.extend(parameters.iter().map(|(_, value)| value));
+    cells
+}
+
+// The reachable instructions, in address order.
+fn instructions(cfg: &ControlFlowGraph) -> impl Iterator<Item = &DecodedInstruction> {
+    cfg.blocks
+        .values()
+        .flat_map(|block| block.instructions.iter())
+}
+
+// Cells reachable code reads or writes in position mode.
+fn data_cells(cfg: &ControlFlowGraph) -> BTreeSet<usize> {
+    instructions(cfg)
+        .flat_map(|instruction| instruction.parameters.iter())
+        .filter(|(mode, address)| *mode == Mode::Position && *address >= 0)
+        .map(|(_, address)| *address as usize)
+        .collect()
+}
+
+fn overlaps_data(instruction: &DecodedInstruction, data: &BTreeSet<usize>) -> bool {
+    data.range(instruction.address..instruction.next_address())
+        .next()
+        .is_some()
+}
+
+// Where a jump with these parameters always goes, if it always goes somewhere known.
+fn unconditional_target(instruction: &DecodedInstruction) -> Option<usize> {
+    match instruction.parameters[..] {
+        [(Mode::Immediate, condition), (Mode::Immediate, target)]
+            if instruction.is_jump()
+                && (condition != 0) == (instruction.op_code() == Some(OpCode::JumpIfTrue))
+                && target >= 0 =>
+        {
+            Some(target as usize)
+        }
+        _ => None,
+    }
+}
+
+// The value an arithmetic or comparison instruction with two immediate operands always writes.
+fn constant_result(instruction: &DecodedInstruction) -> Option<isize> {
+    let (a, b) = match instruction.parameters[..] {
+        [(Mode::Immediate, a), (Mode::Immediate, b), _] => (a, b),
+        _ => return None,
+    };
+    match instruction.op_code()? {
+        OpCode::Add => a.checked_add(b),
+        OpCode::Multiply => a.checked_mul(b),
+        OpCode::LessThan => Some((a < b) as isize),
+        OpCode::Equals => Some((a == b) as isize),
+        _ => None,
+    }
+}
+
+// Constants pushed onto the stack (written in relative mode) by found code, that could be addresses in the image.
+fn pushed_addresses(cfg: &ControlFlowGraph, image_len: usize) -> BTreeSet<usize> {
+    instructions(cfg)
+        .filter(|instruction| matches!(instruction.parameters.last(), Some((Mode::Relative, _))))
+        .filter_map(constant_result)
+        .filter(|&value| value >= 0 && (value as usize) < image_len)
+        .map(|value| value as usize)
+        .collect()
+}
+
+// The control flow graph, also followed from every return address calls push. Each one can lead to more calls.
+fn find_code(image: &[isize]) -> ControlFlowGraph {
+    let mut entry_points = BTreeSet::new();
+    loop {
+        let cfg = ControlFlowGraph::with_entry_points(
+            image,
+            &entry_points.iter().copied().collect::<Vec<_>>(),
+        );
+        let pushed = pushed_addresses(&cfg, image.len());
+        if pushed.is_subset(&entry_points) {
+            return cfg;
+        }
+        entry_points.extend(pushed);
+    }
+}
+
+// Rewrites instructions in place. The result says which cells the program has to guard to be sure of behaving the
+// same - see the header comment.
+pub fn optimise(image: &[isize]) -> Optimised {
+    let cfg = find_code(image);
+    let data = data_cells(&cfg);
+    let by_address: BTreeMap<usize, &DecodedInstruction> = instructions(&cfg)
+        .filter(|instruction| !overlaps_data(instruction, &data))
+        .map(|instruction| (instruction.address, instruction))
+        .collect();
+
+    let mut optimised = Optimised {
+        image: image.to_vec(),
+        rewrites: vec![],
+        original: image.to_vec(),
+        guarded: vec![false; image.len()],
+        starts: vec![false; image.len()],
+    };
+    for instruction in by_address.values() {
+        if let Some(value) = constant_result(instruction) {
+            let already_a_store = instruction.op_code() == Some(OpCode::Add)
+                && (instruction.parameters[0].1 == 0 || instruction.parameters[1].1 == 0);
+            if !already_a_store {
+                optimised.image[instruction.address..instruction.next_address()].copy_from_slice(
+                    &encode(
+                        OpCode::Add.code(),
+                        &[
+                            (Mode::Immediate, value),
+                            (Mode::Immediate, 0),
+                            instruction.parameters[2],
+                        ],
+                    ),
+                );
+                optimised.guard(instruction);
+                optimised.rewrites.push(Rewrite::Folded {
+                    address: instruction.address,
+                    op_code: instruction.op_code().unwrap(),
+                    value,
+                });
+            }
+            continue;
+        }
+
+        if !instruction.is_jump() {
+            continue;
+        }
+        let from = match instruction.parameters[1] {
+            (Mode::Immediate, target) if target >= 0 => target as usize,
+            _ => continue,
+        };
+        let mut to = from;
+        let mut chain = vec![];
+        let mut seen = BTreeSet::from([instruction.address]);
+        while let Some((jump, next)) = by_address
+            .get(&to)
+            .and_then(|jump| unconditional_target(jump).map(|next| (*jump, next)))
+        {
+            if !seen.insert(to) {
+                break; //A loop of jumps - leave it be
+            }
+            chain.push(jump);
+            to = next;
+        }
+        if to != from {
+            optimised.image[instruction.address + 2] = to as isize;
+            optimised.guard(instruction);
+            for jump in chain {
+                optimised.guard(jump);
+            }
+            optimised.rewrites.push(Rewrite::Threaded {
+                address: instruction.address,
+                from,
+                to,
+            });
+        }
+    }
+
+    optimised
+}
+
+// Rewrites instructions in place, then removes unreachable code and no-op jumps. Addresses change, so hosts mustn't
+// poke at fixed cells of the result.
+pub fn optimise_and_compact(image: &[isize]) -> Result<Optimised, OptimiseError> {
+    //Nothing can be guarded once addresses change, so all the code has to be found, and stay as it is.
+    let cfg = ControlFlowGraph::new(image);
+    if !cfg.unresolved_jumps.is_empty() {
+        return Err(OptimiseError::UnresolvedJumps(cfg.unresolved_jumps));
+    }
+    if !cfg.self_modifying_writes.is_empty() {
+        return Err(OptimiseError::SelfModifying(cfg.self_modifying_writes));
+    }
+    let Optimised {
+        image,
+        mut rewrites,
+        ..
+    } = optimise(image);
+    let cfg = ControlFlowGraph::new(&image);
+    if let Some((address, err)) = cfg.invalid.first() {
+        return Err(OptimiseError::NotRelocatable(format!(
+            "control reaches {}, which isn't a valid instruction ({})",
+            address, err
+        )));
+    }
+    if let Some(instruction) = instructions(&cfg).find(|instruction| {
+        instruction.op_code() == Some(OpCode::RelativeBaseOffset)
+            || instruction
+                .parameters
+                .iter()
+                .any(|(mode, _)| *mode == Mode::Relative)
+    }) {
+        return Err(OptimiseError::NotRelocatable(format!(
+            "the instruction at {} uses the relative base",
+            instruction.address
+        )));
+    }
+    let data = data_cells(&cfg);
+
+    let mut removed = vec![false; image.len()];
+    for range in &cfg.unreachable {
+        //Runs of cells that aren't data
+        let mut run: Option<Range<usize>> = None;
+        for address in range.clone() {
+            if data.contains(&address) {
+                rewrites.extend(run.take().map(Rewrite::RemovedUnreachable));
+                continue;
+            }
+            removed[address] = true;
+            run = match run {
+                Some(run) => Some(run.start..address + 1),
+                None => Some(address..address + 1),
+            };
+        }
+        rewrites.extend(run.map(Rewrite::RemovedUnreachable));
+    }
+    for instruction in instructions(&cfg) {
+        let op_code = match instruction.op_code() {
+            Some(op_code) if op_code.is_jump() => op_code,
+            _ => continue,
+        };
+        let no_op = instruction.parameters[0].0 == Mode::Immediate
+            && (unconditional_target(instruction).is_none()
+                || unconditional_target(instruction) == Some(instruction.next_address()));
+        if no_op && !overlaps_data(instruction, &data) {
+            removed[instruction.address..instruction.next_address()].fill(true);
+            rewrites.push(Rewrite::RemovedNoOp {
+                address: instruction.address,
+                op_code,
+            });
+        }
+    }
+
+    //Where each old address ends up. Removed cells map to the next cell that's kept, which is where control would
+    //have ended up anyway: nothing jumps into unreachable code, and a no-op falls through. Addresses past the end of
+    //the image move down by however much the image shrank.
+    let mut new_address = vec![0; image.len() + 1];
+    let mut next = image.len() - removed.iter().filter(|removed| **removed).count();
+    for address in (0..image.len()).rev() {
+        if !removed[address] {
+            next -= 1;
+        }
+        new_address[address] = next;
+    }
+    new_address[image.len()] = image.len() - removed.iter().filter(|removed| **removed).count();
+    let relocate = |address: isize| -> isize {
+        if address < 0 {
+            address
+        } else if (address as usize) < image.len() {
+            new_address[address as usize] as isize
+        } else {
+            address - (image.len() - new_address[image.len()]) as isize
+        }
+    };
+
+    let mut compacted = image.clone();
+    for instruction in instructions(&cfg) {
+        let parameters: Vec<(Mode, isize)> = instruction
+            .parameters
+            .iter()
+            .enumerate()
+            .map(|(i, &(mode, value))| match mode {
+                Mode::Position => (mode, relocate(value)),
+                Mode::Immediate if instruction.is_jump() && i == 1 => (mode, relocate(value)),
+                _ => (mode, value),
+            })
+            .collect();
+        compacted[instruction.address..instruction.next_address()]
+            .copy_from_slice(&encode(instruction.code(), &parameters));
+    }
+    let compacted = compacted
+        .into_iter()
+        .zip(removed)
+        .filter(|(_, removed)| !removed)
+        .map(|(cell, _)| cell)
+        .collect();
+
+    Ok(Optimised {
+        image: compacted,
+        rewrites,
+        original: vec![],
+        guarded: vec![],
+        starts: vec![],
+    })
+}
+
+// Runs original and optimised with the same inputs until they stop, and checks they behave the same way: they must
+// stop for the same reason (or both fail) having produced the same outputs.
+pub fn check_equivalence(
+    original: &[isize],
+    optimised: &[isize],
+    inputs: &[isize],
+) -> Result<(), String> {
+    let run = |image: &[isize]| {
+        let mut program = Program::from_words(image);
+        for &input in inputs {
+            program.add_input(input);
+        }
+        let result = program.run_checked().map_err(|_| ());
+        (result, program.outputs().clone())
+    };
+    let (original_result, original_outputs) = run(original);
+    let (optimised_result, optimised_outputs) = run(optimised);
+    if original_outputs != optimised_outputs {
+        let diverges_at = original_outputs
+            .iter()
+            .zip(&optimised_outputs)
+            .take_while(|(a, b)| a == b)
+            .count();
+        return Err(format!(
+            "Outputs differ from output {}: {:?} vs {:?}",
+            diverges_at,
+            &original_outputs[diverges_at.min(original_outputs.len())..],
+            &optimised_outputs[diverges_at.min(optimised_outputs.len())..]
+        ));
+    }
+    if original_result != optimised_result {
+        return Err(format!(
+            "The original stopped with {:?} but the optimised version with {:?}",
+            original_result, optimised_result
+        ));
+    }
+    Ok(())
+}
+
+static OPTIMISING: AtomicBool = AtomicBool::new(false);
+static OPTIMISED: Mutex<Option<HashMap<Vec<isize>, Arc<Optimised>>>> = Mutex::new(None); //Keyed by the original image
+static REWRITES: Mutex<Vec<Rewrite>> = Mutex::new(vec![]);
+static PUT_BACK: Mutex<Vec<String>> = Mutex::new(vec![]); //Why programs needed their original image back
+
+// Turns on optimisation of the image of every Program loaded from now on (in place, so addresses don't change).
+pub fn start_optimising() {
+    OPTIMISING.store(true, Ordering::SeqCst);
+}
+
+pub fn optimising() -> bool {
+    OPTIMISING.load(Ordering::SeqCst)
+}
+
+// The optimised version of image. Each different image is only optimised (and its rewrites recorded) once.
+pub fn optimised(image: &[isize]) -> Arc<Optimised> {
+    let mut cache = OPTIMISED
+        .lock()
+        .unwrap_or_else(|err| panic!("Optimiser cache poisoned: {err}"));
+    cache
+        .get_or_insert_with(HashMap::new)
+        .entry(image.to_vec())
+        .or_insert_with(|| {
+            let optimised = optimise(image);
+            REWRITES
+                .lock()
+                .unwrap_or_else(|err| panic!("Optimiser rewrites poisoned: {err}"))
+                .extend(optimised.rewrites.iter().cloned());
+            Arc::new(optimised)
+        })
+        .clone()
+}
+
+// Rewrites recorded since the last call.
+pub fn take_rewrites() -> Vec<Rewrite> {
+    std::mem::take(
+        &mut *REWRITES
+            .lock()
+            .unwrap_or_else(|err| panic!("Optimiser rewrites poisoned: {err}")),
+    )
+}
+
+// Called by a Program that's had to put its original image back, with the reason.
+pub fn record_put_back(reason: String) {
+    PUT_BACK
+        .lock()
+        .unwrap_or_else(|err| panic!("Optimiser put backs poisoned: {err}"))
+        .push(reason);
+}
+
+// Reasons programs have put their original image back since the last call.
+pub fn take_put_backs() -> Vec<String> {
+    std::mem::take(
+        &mut *PUT_BACK
+            .lock()
+            .unwrap_or_else(|err| panic!("Optimiser put backs poisoned: {err}")),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode_compiler;
+    use crate::intcode_fuzz;
+
+    #[test]
+    fn test_threading() {
+        //2 jumps to 12, which jumps to 15, which jumps to 18. 21 is a jump to itself, taken if the input is 2.
+        let image = [
+            3, 24, 1006, 24, 12, 1008, 24, 2, 25, 1005, 25, 21, 1106, 0, 15, 1105, 1, 18, 104, 7,
+            99, 1105, 1, 21, 0, 0,
+        ];
+        let optimised = optimise(&image);
+        assert_eq!(
+            vec![
+                Rewrite::Threaded {
+                    address: 2,
+                    from: 12,
+                    to: 18
+                },
+                Rewrite::Threaded {
+                    address: 12,
+                    from: 15,
+                    to: 18
+                },
+            ],
+            optimised.rewrites
+        );
+        assert_eq!(
+            "Threaded the jump at 2 through 12 to 18",
+            optimised.rewrites[0].to_string()
+        );
+        assert_eq!((18, 18), (optimised.image[4], optimised.image[14]));
+        for input in [0, 1] {
+            check_equivalence(&image, &optimised.image, &[input]).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_folding() {
+        //Outputs 6 * 7, 2 < 1 and 0 + 5 - the last is already a store.
+        let image = [
+            1102, 6, 7, 19, 4, 19, 1107, 2, 1, 20, 4, 20, 1101, 0, 5, 21, 4, 21, 99, 0, 0, 0,
+        ];
+        let optimised = optimise(&image);
+        assert_eq!(
+            vec![
+                Rewrite::Folded {
+                    address: 0,
+                    op_code: OpCode::Multiply,
+                    value: 42
+                },
+                Rewrite::Folded {
+                    address: 6,
+                    op_code: OpCode::LessThan,
+                    value: 0
+                },
+            ],
+            optimised.rewrites
+        );
+        assert_eq!(
+            "Folded the mul at 0 into a store of 42",
+            optimised.rewrites[0].to_string()
+        );
+        assert_eq!(
+            (&[1101, 42, 0, 19][..], &[1101, 0, 0, 20][..]),
+            (&optimised.image[0..4], &optimised.image[6..10])
+        );
+        check_equivalence(&image, &optimised.image, &[]).unwrap();
+
+        //Overflow is left for run time.
+        let image = [1102, isize::MAX, 2, 5, 99, 0];
+        assert!(optimise(&image).rewrites.is_empty());
+    }
+
+    // Runs image as it is and optimised, and checks they stop the same way with the same outputs.
+    fn check_optimised_program(image: &[isize], inputs: &[isize]) {
+        let run = |mut program: Program| {
+            for &input in inputs {
+                program.add_input(input);
+            }
+            let result = program.run_checked();
+            (result, program.outputs().clone())
+        };
+        assert_eq!(
+            run(Program::from_words(image)),
+            run(Program::optimised(image)),
+            "Inputs {inputs:?}"
+        );
+    }
+
+    #[test]
+    fn test_guarding() {
+        //The program writes, in relative mode, into an instruction that's been folded. That puts the original back,
+        //so it multiplies 50 by 3 rather than outputting the folded 6.
+        let image = [
+            109, 9, 21101, 50, 0, 1, 1106, 0, 9, 1102, 2, 3, 16, 4, 16, 99, 0,
+        ];
+        let optimised = optimise(&image);
+        assert_eq!(
+            vec![Rewrite::Folded {
+                address: 9,
+                op_code: OpCode::Multiply,
+                value: 6
+            }],
+            optimised.rewrites
+        );
+        assert!(check_equivalence(&image, &optimised.image, &[]).is_err());
+        let mut program = Program::optimised(&image);
+        program.run();
+        assert_eq!(vec![150], *program.outputs());
+        assert!(take_put_backs().contains(&"address 10 was written".to_string()));
+
+        //Reading a folded cell in relative mode sees the original value.
+        let image = [109, 4, 204, 1, 1102, 2, 3, 11, 4, 11, 99];
+        let mut program = Program::optimised(&image);
+        program.run();
+        assert_eq!(vec![2, 6], *program.outputs());
+
+        //Returning into the middle of a folded instruction runs the original cells there, which output 1.
+        let image = [109, 20, 1102, 104, 1, 99, 21101, 3, 0, 1, 2105, 1, 1];
+        let optimised = optimise(&image);
+        assert_eq!(1, optimised.rewrites.len());
+        assert!(check_equivalence(&image, &optimised.image, &[]).is_err());
+        check_optimised_program(&image, &[]);
+
+        //The host poking at a rewritten cell puts the original back too.
+        let mut program = Program::optimised(&[1102, 2, 3, 7, 4, 7, 99, 0]);
+        program.set_value_at(1, 5);
+        program.run();
+        assert_eq!(vec![15], *program.outputs());
+    }
+
+    #[test]
+    fn test_refusals() {
+        //The input overwrites the jump condition.
+        let image = [3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1];
+        let err = optimise_and_compact(&image).unwrap_err();
+        assert_eq!(
+            "The code modifies itself (1 write(s), the first by the instruction at 0)",
+            err.to_string()
+        );
+        //optimise leaves the jump alone instead, as its condition is data.
+        assert!(optimise(&image).rewrites.is_empty());
+
+        let image = [109, 5, 204, 0, 99, 42];
+        assert_eq!(
+            Err(OptimiseError::NotRelocatable(
+                "the instruction at 0 uses the relative base".to_string()
+            )),
+            optimise_and_compact(&image)
+        );
+        let image = [3, 7, 6, 7, 8, 99, 99, 0, 5];
+        assert_eq!(
+            Err(OptimiseError::UnresolvedJumps(vec![2])),
+            optimise_and_compact(&image)
+        );
+    }
+
+    #[test]
+    fn test_code_only_reached_by_returning() {
+        //The function at 16 jumps through 19 and returns through the relative base to 9, which the control flow
+        //graph can't follow. The code there redirects the jump at 19 to 25 and calls the function again, so the
+        //program outputs 2. The return address is pushed at 2, so the optimiser finds the code at 9 and sees the jump
+        //at 19 is data - threading the calls straight through to 22 would skip the redirection and go round forever.
+        let image = [
+            109, 100, 21101, 9, 0, 0, 1105, 1, 16, 1101, 0, 25, 21, 1105, 1, 16, 1105, 1, 19, 1105,
+            1, 22, 2105, 1, 0, 104, 2, 99,
+        ];
+        let cfg = ControlFlowGraph::new(&image);
+        assert_eq!(vec![22], cfg.unresolved_jumps);
+        assert!(cfg.unreachable.contains(&(9..16)));
+        let optimised = optimise(&image);
+        assert_eq!(
+            vec![
+                Rewrite::Threaded {
+                    address: 6,
+                    from: 16,
+                    to: 19
+                },
+                Rewrite::Threaded {
+                    address: 13,
+                    from: 16,
+                    to: 19
+                },
+            ],
+            optimised.rewrites
+        );
+        check_equivalence(&image, &optimised.image, &[]).unwrap();
+        check_optimised_program(&image, &[]);
+    }
+
+    #[test]
+    fn test_day_style_programs() {
+        //Day 2's example, the day 5 examples (one with position mode jumps, one comparing with 8) and the day 9 quine.
+        check_optimised_program(&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50], &[]);
+        for input in [0, 7, 8, 9] {
+            check_optimised_program(
+                &[3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
+                &[input],
+            );
+            check_optimised_program(
+                &[
+                    3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0,
+                    36, 98, 0, 0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46,
+                    1101, 1000, 1, 20, 4, 20, 1105, 1, 46, 98, 99,
+                ],
+                &[input],
+            );
+        }
+        check_optimised_program(
+            &[
+                109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+            ],
+            &[],
+        );
+
+        //Compiled programs call main like any other function and return through the relative base, but the code
+        //after each call is found from its pushed return address, so they're still optimised - here the jump over
+        //the else, straight after the call to triangle, is threaded through to the top of the loop.
+        let image = intcode_compiler::compile(
+            "fn triangle(n) {
+                let total = 0;
+                while (n > 0) {
+                    total = total + n;
+                    n = n - 1;
+                }
+                return total;
+            }
+            fn main() {
+                let n = in();
+                while (n > 0) {
+                    n = n - 1;
+                    if (n > 10) { out(triangle(n)); } else { out(0); }
+                }
+            }",
+        )
+        .unwrap();
+        let optimised = optimise(&image);
+        assert!(
+            matches!(optimised.rewrites[..], [Rewrite::Threaded { .. }]),
+            "{:?}",
+            optimised.rewrites
+        );
+        for input in [0, 5, 12] {
+            check_optimised_program(&image, &[input]);
+        }
+    }
+
+    #[test]
+    fn test_compaction() {
+        //Skips a dead block, then has a jump that's never taken and a jump to the next instruction. Data at 23 and
+        //beyond the image at 100.
+        let image = [
+            1105, 1, 8, 104, 666, 104, 666, 99, 1105, 0, 3, 1105, 1, 14, 4, 23, 1001, 23, 1, 100,
+            4, 100, 99, 41,
+        ];
+        let optimised = optimise_and_compact(&image).unwrap();
+        assert_eq!(
+            vec![
+                Rewrite::RemovedUnreachable(3..8),
+                Rewrite::RemovedNoOp {
+                    address: 8,
+                    op_code: OpCode::JumpIfTrue
+                },
+                Rewrite::RemovedNoOp {
+                    address: 11,
+                    op_code: OpCode::JumpIfTrue
+                },
+            ],
+            optimised.rewrites
+        );
+        //The first jump now goes to what was 14, which is now 3.
+        assert_eq!(
+            vec![1105, 1, 3, 4, 12, 1001, 12, 1, 89, 4, 89, 99, 41],
+            optimised.image
+        );
+        check_equivalence(&image, &optimised.image, &[]).unwrap();
+    }
+
+    #[test]
+    fn test_fuzzed_programs() {
+        let mut rewrites = 0;
+        for seed in 0..500 {
+            let program = intcode_fuzz::generate(seed);
+            rewrites += optimise(&program.image).rewrites.len();
+            let original = intcode_fuzz::reference_run(&program.image, &program.inputs);
+            let run = intcode_fuzz::observe(
+                Program::optimised(&program.image),
+                program.image.len(),
+                &program.inputs,
+            );
+            assert_eq!(original, run, "Seed {seed}");
+        }
+        assert!(rewrites > 0);
+    }
+
+    #[test]
+    fn test_check_equivalence() {
+        assert_eq!(
+            Err("Outputs differ from output 1: [2] vs [3]".to_string()),
+            check_equivalence(&[104, 1, 104, 2, 99], &[104, 1, 104, 3, 99], &[])
+        );
+        assert_eq!(
+            Err("The original stopped with Ok(Halted) but the optimised version with Ok(AwaitingInput)"
+                .to_string()),
+            check_equivalence(&[99, 0], &[3, 0], &[])
+        );
+    }
+}
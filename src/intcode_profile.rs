@@ -0,0 +1,321 @@
+// Execution profiling for Intcode programs - how often each instruction ran, which op codes dominate and which loops
+// the time goes in. Profile a single program with Program::enable_profiling, or every program a day creates by
+// passing --profile to the runner, which turns on collection here and writes a report for each day.
+use crate::intcode::{self, DecodedInstruction, OpCode, Word};
+use crate::intcode_dialect::InstructionSet;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static COLLECTING: AtomicBool = AtomicBool::new(false);
+static COLLECTED: Mutex<Option<Profile>> = Mutex::new(None);
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+    image: Vec<isize>, //The program as it was when profiling started, for the listing
+    instructions: BTreeMap<usize, DecodedInstruction>, //Everywhere in image that decodes, as the program runs it
+    address_hits: BTreeMap<usize, usize>,
+    op_code_hits: HashMap<OpCode, usize>,
+    jumps_taken: HashMap<(usize, usize), usize>, //(from, to) -> count
+}
+
+// A loop found from a backwards jump - the body runs from start to the jump at end.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Loop {
+    pub start: usize,
+    pub end: usize,
+    pub iterations: usize, //Number of times the backwards jump was taken
+    pub instructions_executed: usize, //Total instructions executed within start..=end
+}
+
+impl Profile {
+    pub fn new<W: Word>(image: Vec<isize>, instruction_set: &InstructionSet<W>) -> Profile {
+        let mut profile = Profile {
+            image,
+            ..Default::default()
+        };
+        profile.set_instruction_set(instruction_set);
+        profile
+    }
+
+    // Decodes the image for the listing as a program with instruction_set sees it, custom instructions and all.
+    pub fn set_instruction_set<W: Word>(&mut self, instruction_set: &InstructionSet<W>) {
+        self.instructions = (0..self.image.len())
+            .filter_map(|address| intcode::decode(&self.image, address, instruction_set).ok())
+            .map(|instruction| (instruction.address, instruction))
+            .collect();
+    }
+
+    // op_code is None for custom instructions, which are only counted by address.
+    pub fn record(&mut self, address: usize, op_code: Option<OpCode>) {
+        *self.address_hits.entry(address).or_insert(0) += 1;
+        if let Some(op_code) = op_code {
+            *self.op_code_hits.entry(op_code).or_insert(0) += 1;
+        }
+    }
+
+    pub fn record_jump(&mut self, from: usize, to: usize) {
+        *self.jumps_taken.entry((from, to)).or_insert(0) += 1;
+    }
+
+    pub fn hits_at(&self, address: usize) -> usize {
+        *self.address_hits.get(&address).unwrap_or(&0)
+    }
+
+    #[allow(dead_code)]
+    pub fn op_code_hits(&self, op_code: OpCode) -> usize {
+        *self.op_code_hits.get(&op_code).unwrap_or(&0)
+    }
+
+    pub fn total_instructions(&self) -> usize {
+        self.address_hits.values().sum()
+    }
+
+    // Moves the counts out, leaving this profile empty (but with the same image).
+    pub fn take_counts(&mut self) -> Profile {
+        let empty = Profile {
+            image: self.image.clone(),
+            instructions: self.instructions.clone(),
+            ..Default::default()
+        };
+        std::mem::replace(self, empty)
+    }
+
+    // Adds other's counts to these. Keeps this profile's image unless it doesn't have one yet.
+    pub fn merge(&mut self, other: Profile) {
+        if self.image.is_empty() {
+            self.image = other.image;
+            self.instructions = other.instructions;
+        }
+        for (address, hits) in other.address_hits {
+            *self.address_hits.entry(address).or_insert(0) += hits;
+        }
+        for (op_code, hits) in other.op_code_hits {
+            *self.op_code_hits.entry(op_code).or_insert(0) += hits;
+        }
+        for (jump, count) in other.jumps_taken {
+            *self.jumps_taken.entry(jump).or_insert(0) += count;
+        }
+    }
+
+    // Loops (backwards jumps) ordered by the number of instructions executed inside them, busiest first.
+    pub fn top_loops(&self, count: usize) -> Vec<Loop> {
+        let mut loops: Vec<Loop> = self
+            .jumps_taken
+            .iter()
+            .filter(|((from, to), _)| to <= from)
+            .map(|(&(from, to), &iterations)| Loop {
+                start: to,
+                end: from,
+                iterations,
+                instructions_executed: self
+                    .address_hits
+                    .range(to..=from)
+                    .map(|(_, hits)| hits)
+                    .sum(),
+            })
+            .collect();
+        loops.sort_by(|a, b| {
+            b.instructions_executed
+                .cmp(&a.instructions_executed)
+                .then(a.start.cmp(&b.start))
+        });
+        loops.truncate(count);
+        loops
+    }
+
+    // Disassembly of every instruction that ran, with its hit count. Stretches of memory that never ran (data, or
+    // dead code) are collapsed to a single line.
+    pub fn annotated_listing(&self) -> String {
+        let mut listing = String::new();
+        let mut address = 0;
+        let mut not_run_from: Option<usize> = None;
+        let end = self
+            .image
+            .len()
+            .max(self.address_hits.keys().next_back().map_or(0, |a| a + 1));
+
+        while address < end {
+            let hits = self.hits_at(address);
+            let instruction = if hits > 0 {
+                self.instructions.get(&address)
+            } else {
+                None
+            };
+            match instruction {
+                Some(instruction) => {
+                    if let Some(from) = not_run_from.take() {
+                        write_not_run(&mut listing, from, address);
+                    }
+                    writeln!(listing, "{:>12} | {}", hits, instruction).unwrap();
+                    address = instruction.next_address();
+                }
+                None if hits > 0 => {
+                    //Ran, but doesn't decode in the original image - the code must have been modified.
+                    if let Some(from) = not_run_from.take() {
+                        write_not_run(&mut listing, from, address);
+                    }
+                    writeln!(listing, "{:>12} | {:>5}: (modified code)", hits, address).unwrap();
+                    address += 1;
+                }
+                None => {
+                    not_run_from.get_or_insert(address);
+                    address += 1;
+                }
+            }
+        }
+        if let Some(from) = not_run_from {
+            write_not_run(&mut listing, from, end);
+        }
+
+        listing
+    }
+
+    // Summary of op code counts and the busiest loops.
+    pub fn report(&self, loop_count: usize) -> String {
+        let total = self.total_instructions();
+        let mut report = String::new();
+        writeln!(report, "{} instructions executed", total).unwrap();
+
+        let mut op_codes: Vec<(&OpCode, &usize)> = self.op_code_hits.iter().collect();
+        op_codes.sort_by(|a, b| b.1.cmp(a.1).then(a.0.mnemonic().cmp(b.0.mnemonic())));
+        for (op_code, hits) in op_codes {
+            writeln!(
+                report,
+                "{:>6} {:>12} {:>6.2}%",
+                op_code.mnemonic(),
+                hits,
+                100.0 * *hits as f64 / total as f64
+            )
+            .unwrap();
+        }
+
+        let loops = self.top_loops(loop_count);
+        if !loops.is_empty() {
+            writeln!(report, "Top loops:").unwrap();
+        }
+        for l in loops {
+            writeln!(
+                report,
+                "{:>5}..={:<5} {:>10} iterations {:>12} instructions {:>6.2}%",
+                l.start,
+                l.end,
+                l.iterations,
+                l.instructions_executed,
+                100.0 * l.instructions_executed as f64 / total as f64
+            )
+            .unwrap();
+        }
+        report
+    }
+}
+
+fn write_not_run(listing: &mut String, from: usize, to: usize) {
+    writeln!(
+        listing,
+        "{:>12} | {:>5}..{}: {} cells never executed",
+        "-",
+        from,
+        to,
+        to - from
+    )
+    .unwrap();
+}
+
+// Turns on profiling for every Program created from now on. Their counts are collected here each time they stop
+// running.
+pub fn start_collecting() {
+    COLLECTING.store(true, Ordering::SeqCst);
+}
+
+pub fn collecting() -> bool {
+    COLLECTING.load(Ordering::SeqCst)
+}
+
+pub fn collect(profile: Profile) {
+    let mut collected = COLLECTED
+        .lock()
+        .unwrap_or_else(|err| panic!("Profile collector poisoned: {err}"));
+    collected
+        .get_or_insert_with(Profile::default)
+        .merge(profile);
+}
+
+// Everything collected since the last call.
+pub fn take_collected() -> Option<Profile> {
+    COLLECTED
+        .lock()
+        .unwrap_or_else(|err| panic!("Profile collector poisoned: {err}"))
+        .take()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_loop() {
+        //Counts down from the input, outputting each value.
+        let image = vec![3, 12, 4, 12, 1001, 12, -1, 12, 1005, 12, 2, 99, 0];
+        let mut program = intcode::Program::new(&image);
+        program.enable_profiling();
+        program.add_input(5);
+        program.run();
+        assert_eq!(vec![5, 4, 3, 2, 1], *program.outputs());
+
+        let profile = program.profile().unwrap();
+        assert_eq!(1, profile.hits_at(0));
+        assert_eq!(5, profile.hits_at(2));
+        assert_eq!(5, profile.hits_at(8));
+        assert_eq!(1, profile.hits_at(11));
+        assert_eq!(5, profile.op_code_hits(OpCode::Output));
+        assert_eq!(17, profile.total_instructions());
+        assert_eq!(
+            vec![Loop {
+                start: 2,
+                end: 8,
+                iterations: 4,
+                instructions_executed: 15
+            }],
+            profile.top_loops(3)
+        );
+
+        assert_eq!(
+            "           1 |     0: in [12]
+           5 |     2: out [12]
+           5 |     4: add [12] -1 [12]
+           5 |     8: jnz [12] 2
+           1 |    11: halt
+           - |    12..13: 1 cells never executed
+",
+            profile.annotated_listing()
+        );
+
+        let report = profile.report(3);
+        assert!(report.starts_with("17 instructions executed\n"));
+        assert!(report.contains("\n   out            5  29.41%\n"));
+        assert!(report
+            .contains("\n    2..=8              4 iterations           15 instructions  88.24%\n"));
+    }
+
+    #[test]
+    fn test_merge() {
+        let image = vec![3, 12, 4, 12, 1001, 12, -1, 12, 1005, 12, 2, 99, 0];
+        let mut program = intcode::Program::new(&image);
+        program.enable_profiling();
+        program.add_input(2);
+        program.run();
+
+        let mut merged = Profile::default();
+        merged.merge(program.profile().unwrap().clone());
+        merged.merge(program.profile().unwrap().clone());
+        assert_eq!(image, merged.image);
+        assert_eq!(4, merged.hits_at(2));
+        assert_eq!(2, merged.top_loops(1)[0].iterations);
+
+        let counts = program.take_profile().unwrap();
+        assert_eq!(2, counts.hits_at(2));
+        assert_eq!(0, program.profile().unwrap().total_instructions());
+    }
+}
@@ -0,0 +1,324 @@
+// Transcripts of Intcode sessions, and replaying them. Days like 13, 15 and 25 work out each input from the outputs
+// so far, so a run can't be reproduced without the host logic that drove it - unless the program kept a transcript
+// (Program::start_transcript), which can be saved as text and replayed against the program later with no host at
+// all. Replaying feeds the program the recorded inputs as it asks for them and checks each output against the
+// recording, stopping at the first place they differ.
+#![allow(dead_code)]
+
+use crate::intcode::{Program, ProgramResult, Word};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event<W = isize> {
+    Input(W),
+    Output(W),
+}
+
+impl<W: fmt::Display> fmt::Display for Event<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Input(value) => write!(f, "in {}", value),
+            Event::Output(value) => write!(f, "out {}", value),
+        }
+    }
+}
+
+// Every input taken and output produced, in the order they happened. Inputs count when an instruction takes them, not
+// when they're added.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Transcript<W = isize> {
+    pub events: Vec<Event<W>>,
+}
+
+impl<W: Word> Transcript<W> {
+    pub fn new() -> Transcript<W> {
+        Transcript { events: vec![] }
+    }
+
+    pub fn inputs(&self) -> impl Iterator<Item = &W> {
+        self.events.iter().filter_map(|event| match event {
+            Event::Input(value) => Some(value),
+            Event::Output(_) => None,
+        })
+    }
+
+    pub fn outputs(&self) -> impl Iterator<Item = &W> {
+        self.events.iter().filter_map(|event| match event {
+            Event::Output(value) => Some(value),
+            Event::Input(_) => None,
+        })
+    }
+}
+
+// One event per line.
+impl<W: fmt::Display> fmt::Display for Transcript<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for event in &self.events {
+            writeln!(f, "{}", event)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: FromStr> FromStr for Transcript<W> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let events = s
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                let bad_line = || format!("Bad transcript line {}: '{}'", i + 1, line);
+                let (kind, value) = line.trim().split_once(' ').ok_or_else(bad_line)?;
+                let value = value.trim().parse().map_err(|_| bad_line())?;
+                match kind {
+                    "in" => Ok(Event::Input(value)),
+                    "out" => Ok(Event::Output(value)),
+                    _ => Err(bad_line()),
+                }
+            })
+            .collect::<Result<Vec<Event<W>>, String>>()?;
+        Ok(Transcript { events })
+    }
+}
+
+// What the program did instead of what the transcript said it would.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Happened<W = isize> {
+    Output(W),
+    AwaitingInput,
+    Halted,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence<W = isize> {
+    pub event: usize, //Index into the transcript's events
+    pub instruction_pointer: usize,
+    pub expected: Option<Event<W>>, //None if the transcript had ended
+    pub happened: Happened<W>,
+}
+
+impl<W: fmt::Display> fmt::Display for Divergence<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Diverged at event {} (instruction {}): expected ",
+            self.event, self.instruction_pointer
+        )?;
+        match &self.expected {
+            Some(Event::Input(value)) => write!(f, "the program to take input {}", value)?,
+            Some(Event::Output(value)) => write!(f, "output {}", value)?,
+            None => write!(f, "the end of the transcript")?,
+        }
+        match &self.happened {
+            Happened::Output(value) => write!(f, " but it output {}", value),
+            Happened::AwaitingInput => write!(f, " but it wanted input"),
+            Happened::Halted => write!(f, " but it halted"),
+        }
+    }
+}
+
+// Replays transcript against program, which should be in the state the transcript started from (usually fresh, with
+// no inputs queued). Stops when the program halts or wants input after the last event, returning which, or at the
+// first divergence.
+pub fn replay<W: Word>(
+    program: &mut Program<W>,
+    transcript: &Transcript<W>,
+) -> Result<ProgramResult, Divergence<W>> {
+    let mut next = 0;
+    let diverged = |program: &Program<W>, next: usize, happened| Divergence {
+        event: next,
+        instruction_pointer: program.instruction_pointer(),
+        expected: transcript.events.get(next).cloned(),
+        happened,
+    };
+    loop {
+        let outputs_before = program.outputs().len();
+        let instruction_pointer = program.instruction_pointer();
+        let result = program.step();
+        if let Some(output) = program.outputs().get(outputs_before).cloned() {
+            match transcript.events.get(next) {
+                Some(Event::Output(expected)) if *expected == output => next += 1,
+                _ => {
+                    return Err(Divergence {
+                        instruction_pointer,
+                        ..diverged(program, next, Happened::Output(output))
+                    })
+                }
+            }
+        }
+        match result {
+            None => {}
+            Some(ProgramResult::AwaitingInput) => match transcript.events.get(next) {
+                Some(Event::Input(input)) => {
+                    program.add_input(input.clone());
+                    next += 1;
+                }
+                None => return Ok(ProgramResult::AwaitingInput),
+                Some(Event::Output(_)) => {
+                    return Err(diverged(program, next, Happened::AwaitingInput))
+                }
+            },
+            Some(ProgramResult::Halted) => {
+                return if next == transcript.events.len() {
+                    Ok(ProgramResult::Halted)
+                } else {
+                    Err(Divergence {
+                        instruction_pointer,
+                        ..diverged(program, next, Happened::Halted)
+                    })
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode_compiler;
+
+    //Guess the number: the program outputs -1, 0 or 1 for too low, right or too high, and halts when it's right.
+    fn game(secret: isize) -> Vec<isize> {
+        intcode_compiler::compile(
+            &"fn main() {
+                let secret = SECRET;
+                let guess = in();
+                while (guess != secret) {
+                    out((guess > secret) - (guess < secret));
+                    guess = in();
+                }
+                out(0);
+            }"
+            .replace("SECRET", &secret.to_string()),
+        )
+        .unwrap()
+    }
+
+    //Plays the game with a binary search, working out each guess from the last output.
+    fn play(program: &mut Program) {
+        let (mut low, mut high) = (0, 100);
+        loop {
+            let guess = (low + high) / 2;
+            program.add_input(guess);
+            program.run();
+            match program.outputs().last() {
+                Some(-1) => low = guess + 1,
+                Some(1) => high = guess - 1,
+                _ => return,
+            }
+        }
+    }
+
+    #[test]
+    fn test_transcript() {
+        let mut program = Program::new(&game(37));
+        program.start_transcript();
+        play(&mut program);
+        let transcript = program.take_transcript().unwrap();
+        assert_eq!(
+            vec![
+                Event::Input(50),
+                Event::Output(1),
+                Event::Input(24),
+                Event::Output(-1),
+                Event::Input(37),
+                Event::Output(0)
+            ],
+            transcript.events
+        );
+        assert_eq!(
+            vec![50, 24, 37],
+            transcript.inputs().copied().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            *program.outputs(),
+            transcript.outputs().copied().collect::<Vec<_>>()
+        );
+        assert!(program.transcript().is_none());
+
+        let text = transcript.to_string();
+        assert_eq!("in 50\nout 1\nin 24\nout -1\nin 37\nout 0\n", text);
+        assert_eq!(Ok(transcript), text.parse());
+        assert_eq!(
+            Err("Bad transcript line 2: 'put 3'".to_string()),
+            "in 1\nput 3".parse::<Transcript>()
+        );
+    }
+
+    #[test]
+    fn test_transcript_follows_undo() {
+        let mut program = Program::new(&game(37));
+        program.start_transcript();
+        program.enable_history();
+        program.add_input(50);
+        program.run();
+        program.add_input(24);
+        program.run();
+        //Undo back to before the second guess was taken.
+        while program.transcript().unwrap().events.len() > 2 {
+            program.step_back();
+        }
+        assert_eq!(
+            vec![Event::Input(50), Event::Output(1)],
+            program.transcript().unwrap().events
+        );
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut program = Program::new(&game(37));
+        program.start_transcript();
+        play(&mut program);
+        let transcript = program.take_transcript().unwrap();
+
+        assert_eq!(
+            Ok(ProgramResult::Halted),
+            replay(&mut Program::new(&game(37)), &transcript)
+        );
+
+        //Stopping part way through leaves the program waiting for the next guess.
+        let partial = Transcript {
+            events: transcript.events[..2].to_vec(),
+        };
+        let mut program = Program::new(&game(37));
+        assert_eq!(
+            Ok(ProgramResult::AwaitingInput),
+            replay(&mut program, &partial)
+        );
+        assert_eq!(vec![1], *program.outputs());
+
+        //A different secret gives a different answer to the second guess.
+        let changed = game(20);
+        let divergence = replay(&mut Program::new(&changed), &transcript).unwrap_err();
+        assert_eq!(3, divergence.event);
+        assert_eq!(Some(Event::Output(-1)), divergence.expected);
+        assert_eq!(Happened::Output(1), divergence.happened);
+        assert_eq!(4, changed[divergence.instruction_pointer] % 100); //At the out instruction
+        assert_eq!(
+            format!(
+                "Diverged at event 3 (instruction {}): expected output -1 but it output 1",
+                divergence.instruction_pointer
+            ),
+            divergence.to_string()
+        );
+
+        //Ending early, and going on too long.
+        let mut extra = transcript.clone();
+        extra.events.push(Event::Output(0));
+        let divergence = replay(&mut Program::new(&game(37)), &extra).unwrap_err();
+        assert_eq!(
+            (6, Happened::Halted),
+            (divergence.event, divergence.happened)
+        );
+        let mut wants_output = partial.clone();
+        wants_output.events.push(Event::Output(5));
+        let divergence = replay(&mut Program::new(&game(37)), &wants_output).unwrap_err();
+        assert_eq!(
+            "expected output 5 but it wanted input",
+            divergence.to_string().split(": ").nth(1).unwrap()
+        );
+    }
+}
@@ -0,0 +1,262 @@
+// Searches for the settings that make an Intcode program produce a particular result - day 2's noun and verb being
+// the obvious example. A search has some knobs (memory cells to poke before running, or inputs to feed it), each with
+// a range of values to try, and a target that's checked once the program stops. Every run starts from the original
+// image, so this uses the threaded backend, which is quick to reset.
+use crate::intcode_threaded::ThreadedProgram;
+use std::ops::RangeInclusive;
+use std::thread;
+
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Knob {
+    Memory {
+        address: isize,
+        range: RangeInclusive<isize>,
+    },
+    Input {
+        range: RangeInclusive<isize>, //Input knobs are fed to the program in the order they're listed
+    },
+}
+
+impl Knob {
+    fn range(&self) -> &RangeInclusive<isize> {
+        match self {
+            Knob::Memory { range, .. } | Knob::Input { range } => range,
+        }
+    }
+
+    fn len(&self) -> usize {
+        let range = self.range();
+        if range.is_empty() {
+            0
+        } else {
+            (range.end() - range.start()) as usize + 1
+        }
+    }
+}
+
+// What we're looking for, checked against the program's memory and outputs once it stops.
+#[allow(dead_code)]
+pub enum Target {
+    Predicate(Box<dyn Fn(&ThreadedProgram) -> bool + Sync>),
+    //A measure of the program (e.g. the value at some address) equal to value.
+    Equals {
+        measure: Box<dyn Fn(&ThreadedProgram) -> isize + Sync>,
+        value: isize,
+    },
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    Exhaustive,      //Try everything
+    Parallel(usize), //Try everything, split over this many threads
+    //Bisect the last knob for every combination of the others. Only valid if the target is monotonic in the last
+    //knob - a predicate that's false then true as it increases, or a measure that never decreases.
+    Bisection,
+}
+
+pub struct Search {
+    image: Vec<isize>,
+    knobs: Vec<Knob>,
+    target: Target,
+}
+
+impl Search {
+    pub fn new(image: &[isize], knobs: Vec<Knob>, target: Target) -> Search {
+        Search {
+            image: image.to_owned(),
+            knobs,
+            target,
+        }
+    }
+
+    // Returns the knob values (in the order the knobs were given) for every setting that hits the target, in
+    // ascending order.
+    pub fn run(&self, strategy: Strategy) -> Vec<Vec<isize>> {
+        let mut matches = match strategy {
+            Strategy::Exhaustive => self.search_indices(0, 1),
+            Strategy::Parallel(threads) => {
+                let threads = threads.max(1);
+                thread::scope(|scope| {
+                    let handles: Vec<_> = (0..threads)
+                        .map(|offset| scope.spawn(move || self.search_indices(offset, threads)))
+                        .collect();
+                    handles
+                        .into_iter()
+                        .flat_map(|handle| handle.join().unwrap())
+                        .collect()
+                })
+            }
+            Strategy::Bisection => self.bisect(),
+        };
+        matches.sort();
+        matches
+    }
+
+    fn combinations(knobs: &[Knob]) -> usize {
+        knobs.iter().map(|knob| knob.len()).product()
+    }
+
+    // Knob values for the index'th combination of knobs, counting with the last knob changing fastest.
+    fn values(knobs: &[Knob], mut index: usize) -> Vec<isize> {
+        let mut values = vec![0; knobs.len()];
+        for (value, knob) in values.iter_mut().zip(knobs).rev() {
+            *value = knob.range().start() + (index % knob.len()) as isize;
+            index /= knob.len();
+        }
+        values
+    }
+
+    // Tries every combination whose index is offset plus a multiple of step.
+    fn search_indices(&self, offset: usize, step: usize) -> Vec<Vec<isize>> {
+        let mut program = ThreadedProgram::new(&self.image);
+        (offset..Search::combinations(&self.knobs))
+            .step_by(step)
+            .map(|index| Search::values(&self.knobs, index))
+            .filter(|values| {
+                self.set_up_and_run(&mut program, values);
+                match &self.target {
+                    Target::Predicate(predicate) => predicate(&program),
+                    Target::Equals { measure, value } => measure(&program) == *value,
+                }
+            })
+            .collect()
+    }
+
+    fn bisect(&self) -> Vec<Vec<isize>> {
+        let (last, others) = match self.knobs.split_last() {
+            Some(split) => split,
+            None => return self.search_indices(0, 1),
+        };
+        let mut program = ThreadedProgram::new(&self.image);
+        let mut matches = vec![];
+
+        for index in 0..Search::combinations(others) {
+            let mut values = Search::values(others, index);
+            //First value of the last knob for which test holds, or one past the end of its range if none.
+            let mut first_where = |test: &dyn Fn(&ThreadedProgram) -> bool| {
+                let (mut low, mut high) = (*last.range().start(), *last.range().end() + 1);
+                while low < high {
+                    let middle = low + (high - low) / 2;
+                    let mut trial = values.clone();
+                    trial.push(middle);
+                    self.set_up_and_run(&mut program, &trial);
+                    if test(&program) {
+                        high = middle;
+                    } else {
+                        low = middle + 1;
+                    }
+                }
+                low
+            };
+
+            let hits = match &self.target {
+                Target::Predicate(predicate) => {
+                    first_where(predicate.as_ref())..*last.range().end() + 1
+                }
+                Target::Equals { measure, value } => {
+                    first_where(&|program| measure(program) >= *value)..first_where(&|program| {
+                        measure(program) > *value
+                    })
+                }
+            };
+            for hit in hits {
+                values.push(hit);
+                matches.push(values.clone());
+                values.pop();
+            }
+        }
+        matches
+    }
+
+    fn set_up_and_run(&self, program: &mut ThreadedProgram, values: &[isize]) {
+        program.initialize(&self.image);
+        for (knob, &value) in self.knobs.iter().zip(values) {
+            match knob {
+                Knob::Memory { address, .. } => program.set_value_at(*address, value),
+                Knob::Input { .. } => program.add_input(value),
+            }
+        }
+        program.run();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_knobs() {
+        //Day 2 style: address 0 ends up as 3 * noun + verb, with noun at address 1 and verb at address 6.
+        let image = [1102, 0, 3, 20, 1001, 20, 0, 0, 99];
+        let search = Search::new(
+            &image,
+            vec![
+                Knob::Memory {
+                    address: 1,
+                    range: 0..=20,
+                },
+                Knob::Memory {
+                    address: 6,
+                    range: 0..=20,
+                },
+            ],
+            Target::Equals {
+                measure: Box::new(|program| program.output_deprecated()),
+                value: 34,
+            },
+        );
+        let expected: Vec<Vec<isize>> = (5..=11).map(|noun| vec![noun, 34 - 3 * noun]).collect();
+        assert_eq!(expected, search.run(Strategy::Exhaustive));
+        assert_eq!(expected, search.run(Strategy::Parallel(4)));
+        assert_eq!(expected, search.run(Strategy::Parallel(0)));
+        assert_eq!(expected, search.run(Strategy::Bisection));
+    }
+
+    #[test]
+    fn test_bisection() {
+        //Outputs 3 * first input + second input.
+        let image = [3, 20, 3, 21, 1002, 20, 3, 20, 1, 20, 21, 22, 4, 22, 99];
+        let search = Search::new(
+            &image,
+            vec![Knob::Input { range: 0..=9 }, Knob::Input { range: -5..=30 }],
+            Target::Equals {
+                measure: Box::new(|program| *program.outputs().last().unwrap()),
+                value: 25,
+            },
+        );
+        let expected: Vec<Vec<isize>> = (0..=9)
+            .map(|a| vec![a, 25 - 3 * a])
+            .filter(|values| values[1] <= 30)
+            .collect();
+        assert_eq!(expected, search.run(Strategy::Exhaustive));
+        assert_eq!(expected, search.run(Strategy::Bisection));
+
+        //Monotonic predicate - everything from the threshold up matches.
+        let search = Search::new(
+            &image,
+            vec![Knob::Input { range: 2..=2 }, Knob::Input { range: 0..=10 }],
+            Target::Predicate(Box::new(|program| program.outputs()[0] >= 14)),
+        );
+        assert_eq!(
+            vec![vec![2, 8], vec![2, 9], vec![2, 10]],
+            search.run(Strategy::Bisection)
+        );
+        assert_eq!(
+            search.run(Strategy::Exhaustive),
+            search.run(Strategy::Bisection)
+        );
+
+        //No matches at all.
+        let search = Search::new(
+            &image,
+            vec![Knob::Input { range: 0..=3 }, Knob::Input { range: 0..=3 }],
+            Target::Equals {
+                measure: Box::new(|program| program.outputs()[0]),
+                value: 100,
+            },
+        );
+        assert!(search.run(Strategy::Bisection).is_empty());
+    }
+}
@@ -0,0 +1,582 @@
+// Symbolic execution of Intcode programs. Some memory cells and/or inputs are marked as symbols rather than numbers,
+// and the program is run as usual except that values are linear expressions in those symbols - so, for example, day
+// 2's output can be found as a formula in the noun and verb rather than by trying them all.
+//
+// Anything that can't be kept linear - multiplying two symbolic values, comparing them, or reading memory through a
+// symbolic address - produces an unknown value, which is fine as long as nothing depends on it. Branching on one, or
+// writing through a symbolic address, means the program's path depends on the symbols and the run fails.
+#![allow(dead_code)]
+
+use crate::intcode::{Mode, OpCode, ProgramResult};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+
+// constant + sum of coefficient * symbol
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LinearExpression {
+    pub constant: isize,
+    pub coefficients: BTreeMap<String, isize>, //Never holds zero coefficients
+}
+
+impl LinearExpression {
+    pub fn constant(value: isize) -> LinearExpression {
+        LinearExpression {
+            constant: value,
+            coefficients: BTreeMap::new(),
+        }
+    }
+
+    pub fn symbol(name: &str) -> LinearExpression {
+        LinearExpression {
+            constant: 0,
+            coefficients: BTreeMap::from([(name.to_string(), 1)]),
+        }
+    }
+
+    // The value, if it doesn't depend on any symbols
+    pub fn as_constant(&self) -> Option<isize> {
+        if self.coefficients.is_empty() {
+            Some(self.constant)
+        } else {
+            None
+        }
+    }
+
+    pub fn coefficient(&self, name: &str) -> isize {
+        *self.coefficients.get(name).unwrap_or(&0)
+    }
+
+    fn add(&self, other: &LinearExpression) -> LinearExpression {
+        let mut sum = self.clone();
+        sum.constant += other.constant;
+        for (name, coefficient) in &other.coefficients {
+            *sum.coefficients.entry(name.clone()).or_insert(0) += coefficient;
+        }
+        sum.coefficients.retain(|_, coefficient| *coefficient != 0);
+        sum
+    }
+
+    fn scale(&self, factor: isize) -> LinearExpression {
+        let mut product = LinearExpression::constant(self.constant * factor);
+        if factor != 0 {
+            for (name, coefficient) in &self.coefficients {
+                product
+                    .coefficients
+                    .insert(name.clone(), coefficient * factor);
+            }
+        }
+        product
+    }
+
+    // Value of the expression with every symbol given a value. Panics if any are missing.
+    pub fn evaluate(&self, values: &HashMap<&str, isize>) -> isize {
+        self.coefficients
+            .iter()
+            .map(|(name, coefficient)| {
+                coefficient
+                    * values
+                        .get(name.as_str())
+                        .unwrap_or_else(|| panic!("No value for symbol {name}"))
+            })
+            .sum::<isize>()
+            + self.constant
+    }
+}
+
+impl fmt::Display for LinearExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        for (name, &coefficient) in &self.coefficients {
+            let sign = if coefficient < 0 { "-" } else { "+" };
+            match (first, coefficient.abs()) {
+                (true, 1) => write!(f, "{}{}", if coefficient < 0 { "-" } else { "" }, name)?,
+                (true, magnitude) => write!(f, "{}*{}", coefficient.signum() * magnitude, name)?,
+                (false, 1) => write!(f, " {} {}", sign, name)?,
+                (false, magnitude) => write!(f, " {} {}*{}", sign, magnitude, name)?,
+            }
+            first = false;
+        }
+        match (first, self.constant) {
+            (true, constant) => write!(f, "{}", constant),
+            (false, 0) => Ok(()),
+            (false, constant) if constant < 0 => write!(f, " - {}", -constant),
+            (false, constant) => write!(f, " + {}", constant),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnknownReason {
+    NonLinear,          //Product of two symbolic values
+    SymbolicComparison, //Comparison whose outcome depends on the symbols
+    SymbolicAddress,    //Read from an address that depends on the symbols
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Linear(LinearExpression),
+    //Can't be expressed linearly, because of what happened at instruction_pointer (or derived from such a value).
+    Unknown {
+        instruction_pointer: usize,
+        reason: UnknownReason,
+    },
+}
+
+impl Value {
+    fn constant(value: isize) -> Value {
+        Value::Linear(LinearExpression::constant(value))
+    }
+
+    pub fn as_constant(&self) -> Option<isize> {
+        match self {
+            Value::Linear(expression) => expression.as_constant(),
+            Value::Unknown { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Linear(expression) => write!(f, "{}", expression),
+            Value::Unknown {
+                instruction_pointer,
+                reason,
+            } => {
+                let reason = match reason {
+                    UnknownReason::NonLinear => "non-linear",
+                    UnknownReason::SymbolicComparison => "comparison of symbolic values",
+                    UnknownReason::SymbolicAddress => "read from a symbolic address",
+                };
+                write!(f, "unknown ({} at {})", reason, instruction_pointer)
+            }
+        }
+    }
+}
+
+// Reasons a symbolic run can't continue.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SymbolicError {
+    DataDependentBranch {
+        instruction_pointer: usize,
+        condition: Value,
+    },
+    SymbolicJumpTarget {
+        instruction_pointer: usize,
+        target: Value,
+    },
+    SymbolicWrite {
+        instruction_pointer: usize,
+        address: Value,
+    },
+    SymbolicRelativeBase {
+        instruction_pointer: usize,
+        offset: Value,
+    },
+    SymbolicInstruction {
+        instruction_pointer: usize,
+    },
+    Invalid {
+        instruction_pointer: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use SymbolicError::*;
+        match self {
+            DataDependentBranch {
+                instruction_pointer,
+                condition,
+            } => write!(
+                f,
+                "Data-dependent branch at {instruction_pointer} on {condition}"
+            ),
+            SymbolicJumpTarget {
+                instruction_pointer,
+                target,
+            } => write!(
+                f,
+                "Jump at {instruction_pointer} to symbolic target {target}"
+            ),
+            SymbolicWrite {
+                instruction_pointer,
+                address,
+            } => write!(
+                f,
+                "Write at {instruction_pointer} to symbolic address {address}"
+            ),
+            SymbolicRelativeBase {
+                instruction_pointer,
+                offset,
+            } => write!(
+                f,
+                "Relative base adjusted at {instruction_pointer} by symbolic {offset}"
+            ),
+            SymbolicInstruction {
+                instruction_pointer,
+            } => write!(f, "Op code at {instruction_pointer} is symbolic"),
+            Invalid {
+                instruction_pointer,
+                message,
+            } => write!(f, "{message} at {instruction_pointer}"),
+        }
+    }
+}
+
+impl std::error::Error for SymbolicError {}
+
+#[derive(Clone, Debug)]
+pub struct SymbolicProgram {
+    program: Vec<Value>,
+    memory: HashMap<isize, Value>,
+    instruction_pointer: usize,
+    inputs: VecDeque<Value>,
+    outputs: Vec<Value>,
+    relative_base: isize,
+}
+
+impl SymbolicProgram {
+    pub fn new(program: &[isize]) -> SymbolicProgram {
+        SymbolicProgram {
+            program: program.iter().map(|&x| Value::constant(x)).collect(),
+            memory: HashMap::new(),
+            instruction_pointer: 0,
+            inputs: VecDeque::new(),
+            outputs: vec![],
+            relative_base: 0,
+        }
+    }
+
+    // Replaces the value at address with the symbol name.
+    pub fn set_symbolic(&mut self, address: isize, name: &str) {
+        self.set_value_at(address, Value::Linear(LinearExpression::symbol(name)));
+    }
+
+    pub fn add_input(&mut self, input: isize) {
+        self.inputs.push_back(Value::constant(input));
+    }
+
+    pub fn add_symbolic_input(&mut self, name: &str) {
+        self.inputs
+            .push_back(Value::Linear(LinearExpression::symbol(name)));
+    }
+
+    pub fn outputs(&self) -> &Vec<Value> {
+        &self.outputs
+    }
+
+    pub fn get_value_at(&self, index: isize) -> Value {
+        if index >= 0 && (index as usize) < self.program.len() {
+            self.program[index as usize].clone()
+        } else {
+            self.memory
+                .get(&index)
+                .cloned()
+                .unwrap_or_else(|| Value::constant(0))
+        }
+    }
+
+    fn set_value_at(&mut self, index: isize, value: Value) {
+        if index >= 0 && (index as usize) < self.program.len() {
+            self.program[index as usize] = value;
+        } else {
+            self.memory.insert(index, value);
+        }
+    }
+
+    // Runs until the program halts or needs input, or until it does something that depends on the symbols in a way
+    // that can't be followed.
+    pub fn run(&mut self) -> Result<ProgramResult, SymbolicError> {
+        loop {
+            if let Some(result) = self.execute_instruction()? {
+                return Ok(result);
+            }
+        }
+    }
+
+    fn execute_instruction(&mut self) -> Result<Option<ProgramResult>, SymbolicError> {
+        let instruction_pointer = self.instruction_pointer;
+        let raw_op_code = self
+            .get_value_at(instruction_pointer as isize)
+            .as_constant()
+            .ok_or(SymbolicError::SymbolicInstruction {
+                instruction_pointer,
+            })?;
+        let op_code = OpCode::try_from(raw_op_code).map_err(|message| SymbolicError::Invalid {
+            instruction_pointer,
+            message,
+        })?;
+        let mut mode_digits = raw_op_code / 100;
+        let mut parameters = vec![];
+        for offset in 1..=op_code.num_parameters() {
+            let mode =
+                Mode::try_from(mode_digits % 10).map_err(|message| SymbolicError::Invalid {
+                    instruction_pointer,
+                    message,
+                })?;
+            parameters.push((
+                mode,
+                self.get_value_at((instruction_pointer + offset) as isize),
+            ));
+            mode_digits /= 10;
+        }
+        let next = instruction_pointer + op_code.num_parameters() + 1;
+
+        match op_code {
+            OpCode::Add | OpCode::Multiply | OpCode::LessThan | OpCode::Equals => {
+                let a = self.read(&parameters[0]);
+                let b = self.read(&parameters[1]);
+                let value = match op_code {
+                    OpCode::Add => add(&a, &b),
+                    OpCode::Multiply => multiply(&a, &b, instruction_pointer),
+                    _ => compare(&a, &b, op_code, instruction_pointer),
+                };
+                let address = self.write_address(&parameters[2])?;
+                self.set_value_at(address, value);
+            }
+            OpCode::Input => {
+                let address = self.write_address(&parameters[0])?;
+                match self.inputs.pop_front() {
+                    Some(value) => self.set_value_at(address, value),
+                    None => return Ok(Some(ProgramResult::AwaitingInput)),
+                }
+            }
+            OpCode::Output => {
+                let value = self.read(&parameters[0]);
+                self.outputs.push(value);
+            }
+            OpCode::JumpIfTrue | OpCode::JumpIfFalse => {
+                let condition = self.read(&parameters[0]);
+                let condition =
+                    condition
+                        .as_constant()
+                        .ok_or_else(|| SymbolicError::DataDependentBranch {
+                            instruction_pointer,
+                            condition: condition.clone(),
+                        })?;
+                if (condition != 0) == (op_code == OpCode::JumpIfTrue) {
+                    let target = self.read(&parameters[1]);
+                    self.instruction_pointer = target
+                        .as_constant()
+                        .filter(|target| *target >= 0)
+                        .ok_or(SymbolicError::SymbolicJumpTarget {
+                            instruction_pointer,
+                            target,
+                        })? as usize;
+                    return Ok(None);
+                }
+            }
+            OpCode::RelativeBaseOffset => {
+                let offset = self.read(&parameters[0]);
+                self.relative_base +=
+                    offset
+                        .as_constant()
+                        .ok_or_else(|| SymbolicError::SymbolicRelativeBase {
+                            instruction_pointer,
+                            offset: offset.clone(),
+                        })?;
+            }
+            OpCode::Halt => {
+                self.instruction_pointer += 1;
+                return Ok(Some(ProgramResult::Halted));
+            }
+        }
+
+        self.instruction_pointer = next;
+        Ok(None)
+    }
+
+    // Value of a parameter. Reading through a symbolic address gives an unknown.
+    fn read(&self, parameter: &(Mode, Value)) -> Value {
+        match parameter {
+            (Mode::Immediate, value) => value.clone(),
+            (mode, value) => match value.as_constant() {
+                Some(address) => self.get_value_at(self.address(*mode, address)),
+                None => Value::Unknown {
+                    instruction_pointer: self.instruction_pointer,
+                    reason: UnknownReason::SymbolicAddress,
+                },
+            },
+        }
+    }
+
+    fn write_address(&self, parameter: &(Mode, Value)) -> Result<isize, SymbolicError> {
+        let (mode, value) = parameter;
+        match value.as_constant() {
+            Some(address) => Ok(self.address(*mode, address)),
+            None => Err(SymbolicError::SymbolicWrite {
+                instruction_pointer: self.instruction_pointer,
+                address: value.clone(),
+            }),
+        }
+    }
+
+    fn address(&self, mode: Mode, value: isize) -> isize {
+        match mode {
+            Mode::Relative => value + self.relative_base,
+            _ => value,
+        }
+    }
+}
+
+fn add(a: &Value, b: &Value) -> Value {
+    match (a, b) {
+        (Value::Linear(a), Value::Linear(b)) => Value::Linear(a.add(b)),
+        (unknown @ Value::Unknown { .. }, _) | (_, unknown @ Value::Unknown { .. }) => {
+            unknown.clone()
+        }
+    }
+}
+
+fn multiply(a: &Value, b: &Value, instruction_pointer: usize) -> Value {
+    match (a, b) {
+        (unknown @ Value::Unknown { .. }, _) | (_, unknown @ Value::Unknown { .. }) => {
+            unknown.clone()
+        }
+        (Value::Linear(a), Value::Linear(b)) => match (a.as_constant(), b.as_constant()) {
+            (Some(factor), _) => Value::Linear(b.scale(factor)),
+            (_, Some(factor)) => Value::Linear(a.scale(factor)),
+            (None, None) => Value::Unknown {
+                instruction_pointer,
+                reason: UnknownReason::NonLinear,
+            },
+        },
+    }
+}
+
+// LessThan or Equals. Decidable whenever the difference between the two is constant.
+fn compare(a: &Value, b: &Value, op_code: OpCode, instruction_pointer: usize) -> Value {
+    let difference = match (a, b) {
+        (Value::Linear(a), Value::Linear(b)) => a.add(&b.scale(-1)).as_constant(),
+        _ => None,
+    };
+    match (difference, a, b) {
+        (Some(difference), _, _) => Value::constant(match op_code {
+            OpCode::LessThan => (difference < 0) as isize,
+            _ => (difference == 0) as isize,
+        }),
+        (None, unknown @ Value::Unknown { .. }, _) | (None, _, unknown @ Value::Unknown { .. }) => {
+            unknown.clone()
+        }
+        (None, _, _) => Value::Unknown {
+            instruction_pointer,
+            reason: UnknownReason::SymbolicComparison,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode;
+
+    #[test]
+    fn test_day2_style_formula() {
+        //Instruction 0 reads through the noun and verb, but its result is overwritten. Then address 0 ends up as
+        //5 * noun + noun + verb.
+        let image = [1, 0, 0, 3, 1, 1, 2, 3, 2, 1, 19, 0, 1, 0, 3, 0, 99, 0, 0, 5];
+        let mut program = SymbolicProgram::new(&image);
+        program.set_symbolic(1, "noun");
+        program.set_symbolic(2, "verb");
+        assert_eq!(Ok(ProgramResult::Halted), program.run());
+
+        let result = program.get_value_at(0);
+        assert_eq!("6*noun + verb", result.to_string());
+        let formula = match result {
+            Value::Linear(formula) => formula,
+            Value::Unknown { .. } => panic!("Expected a formula"),
+        };
+        assert_eq!(6, formula.coefficient("noun"));
+
+        //Check the formula against the real thing.
+        for noun in 0..20 {
+            for verb in 0..20 {
+                let mut concrete = intcode::Program::new(&image);
+                concrete.set_noun_verb_inputs(noun, verb);
+                concrete.run();
+                assert_eq!(
+                    concrete.output_deprecated(),
+                    formula.evaluate(&HashMap::from([("noun", noun), ("verb", verb)]))
+                );
+            }
+        }
+
+        //The value instruction 0 wrote was garbage, though.
+        let mut program = SymbolicProgram::new(&image[0..4]);
+        program.set_symbolic(1, "noun");
+        program.set_symbolic(2, "verb");
+        program.run().unwrap_err();
+        assert_eq!(
+            "unknown (read from a symbolic address at 0)",
+            program.get_value_at(3).to_string()
+        );
+    }
+
+    #[test]
+    fn test_symbolic_inputs() {
+        //Outputs 3 * a - b + 7 and b - b.
+        let image = [
+            3, 30, 3, 31, 1002, 30, 3, 32, 1002, 31, -1, 33, 1, 32, 33, 34, 1001, 34, 7, 34, 4, 34,
+            1, 31, 33, 35, 4, 35, 99,
+        ];
+        let mut program = SymbolicProgram::new(&image);
+        program.add_symbolic_input("a");
+        program.add_symbolic_input("b");
+        assert_eq!(Ok(ProgramResult::Halted), program.run());
+        let outputs: Vec<String> = program.outputs().iter().map(|x| x.to_string()).collect();
+        assert_eq!(vec!["3*a - b + 7", "0"], outputs);
+
+        //Stops for more input like the real thing.
+        let mut program = SymbolicProgram::new(&image);
+        program.add_input(2);
+        assert_eq!(Ok(ProgramResult::AwaitingInput), program.run());
+        program.add_symbolic_input("b");
+        assert_eq!(Ok(ProgramResult::Halted), program.run());
+        assert_eq!("-b + 13", program.outputs()[0].to_string());
+    }
+
+    #[test]
+    fn test_non_linear_and_branches() {
+        //Squares the input.
+        let mut program = SymbolicProgram::new(&[3, 9, 2, 9, 9, 10, 4, 10, 99, 0, 0]);
+        program.add_symbolic_input("x");
+        assert_eq!(Ok(ProgramResult::Halted), program.run());
+        assert_eq!(
+            Value::Unknown {
+                instruction_pointer: 2,
+                reason: UnknownReason::NonLinear
+            },
+            program.outputs()[0]
+        );
+        assert_eq!(
+            "unknown (non-linear at 2)",
+            program.outputs()[0].to_string()
+        );
+
+        //Branches on whether the input is 8.
+        let mut program = SymbolicProgram::new(&[3, 3, 1108, -1, 8, 3, 4, 3, 99]);
+        program.add_symbolic_input("x");
+        assert_eq!(Ok(ProgramResult::Halted), program.run());
+        assert_eq!(None, program.outputs()[0].as_constant());
+        let mut program =
+            SymbolicProgram::new(&[3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9]);
+        program.add_symbolic_input("x");
+        let err = program.run().unwrap_err();
+        assert_eq!(
+            SymbolicError::DataDependentBranch {
+                instruction_pointer: 2,
+                condition: Value::Linear(LinearExpression::symbol("x"))
+            },
+            err
+        );
+        assert_eq!("Data-dependent branch at 2 on x", err.to_string());
+
+        //Comparisons that don't really depend on the symbol are fine.
+        let mut program = SymbolicProgram::new(&[3, 20, 1001, 20, 1, 21, 7, 20, 21, 22, 4, 22, 99]);
+        program.add_symbolic_input("x");
+        assert_eq!(Ok(ProgramResult::Halted), program.run());
+        assert_eq!(Some(1), program.outputs()[0].as_constant());
+    }
+}
@@ -1,5 +1,8 @@
 use std::env;
+use std::fs;
 use std::time;
+mod animation;
+mod cycle;
 mod day1;
 mod day10;
 mod day11;
@@ -25,8 +28,27 @@ mod day6;
 mod day7;
 mod day8;
 mod day9;
+mod geometry;
+mod grid;
+mod image;
 mod intcode;
+mod intcode_analysis;
+mod intcode_async;
+mod intcode_compiler;
+mod intcode_dialect;
+#[cfg(test)]
+mod intcode_fuzz;
+mod intcode_memory;
+mod intcode_optimiser;
+mod intcode_profile;
+mod intcode_replay;
+mod intcode_search;
+mod intcode_symbolic;
+mod intcode_threaded;
+mod math;
+mod search;
 mod utils;
+mod visibility;
 
 //With thanks to CJP for the logic behind this framework.
 //I tried just to understand what he'd done and reproduce something similar
@@ -56,24 +78,69 @@ static DAYS: [fn() -> (usize, usize); 25] = [
     day20::day20,
     day21::day21,
     day22::day22,
-    day23::day23,
+    //Day 23's network can be configured into one that never finishes, and says so rather than answering.
+    || day23::day23().unwrap_or_else(|err| panic!("Day 23 failed: {err}")),
     day24::day24,
     day25::day25,
 ];
 
+//Where --profile writes each day's annotated Intcode listing.
+const PROFILE_DIRECTORY: &str = "profile";
+
+//Where --images saves each day's visualisations, and --gif and --frames its animations.
+const IMAGE_DIRECTORY: &str = "images";
+
 fn main() {
     let mut min_day: usize = 1;
     let mut max_day: usize = DAYS.len();
-    let args: Vec<String> = env::args().collect();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (flags, days): (Vec<&String>, Vec<&String>) =
+        args.iter().partition(|arg| arg.starts_with("--"));
 
-    if args.len() > 1 {
+    if let Some(day) = days.first() {
         // Argument specified
-        min_day = args[1]
-            .parse()
-            .expect("Bad argument - must be a day number");
+        min_day = day.parse().expect("Bad argument - must be a day number");
         max_day = min_day;
     }
 
+    let mut profile = false;
+    for flag in flags {
+        match flag.as_str() {
+            "--profile" => profile = true,
+            "--optimise" => intcode_optimiser::start_optimising(),
+            "--images" => {
+                fs::create_dir_all(IMAGE_DIRECTORY)
+                    .unwrap_or_else(|err| panic!("Failed to create {IMAGE_DIRECTORY}: {err}"));
+                image::start_saving(IMAGE_DIRECTORY);
+            }
+            "--gif" | "--frames" => {
+                fs::create_dir_all(IMAGE_DIRECTORY)
+                    .unwrap_or_else(|err| panic!("Failed to create {IMAGE_DIRECTORY}: {err}"));
+                animation::start_recording(if flag == "--gif" {
+                    animation::Output::Gif(IMAGE_DIRECTORY.into())
+                } else {
+                    animation::Output::Frames(IMAGE_DIRECTORY.into())
+                });
+            }
+            //--play=4 plays four times as fast
+            "--play" => animation::start_recording(animation::Output::Play(1.0)),
+            other if other.starts_with("--play=") => {
+                let speed: f64 = other["--play=".len()..]
+                    .parse()
+                    .unwrap_or_else(|err| panic!("Bad playback speed in {other}: {err}"));
+                assert!(speed > 0.0, "Playback speed must be positive");
+                animation::start_recording(animation::Output::Play(speed));
+            }
+            _ => panic!("Unrecognized flag {flag}"),
+        }
+    }
+
+    if profile {
+        intcode_profile::start_collecting();
+        fs::create_dir_all(PROFILE_DIRECTORY)
+            .unwrap_or_else(|err| panic!("Failed to create {PROFILE_DIRECTORY}: {err}"));
+    }
+
     let total_now = time::Instant::now();
     for day in min_day..max_day + 1 {
         println!("Running day {}", day);
@@ -87,6 +154,35 @@ fn main() {
         );
         println!("Part1 answer: {}", part1);
         println!("Part2 answer: {}", part2);
+        if let Some(day_profile) = intcode_profile::take_collected() {
+            //Only Intcode days have anything to report
+            let listing_file = format!("{PROFILE_DIRECTORY}/day{day}.txt");
+            fs::write(&listing_file, day_profile.annotated_listing())
+                .unwrap_or_else(|err| panic!("Failed to write {listing_file}: {err}"));
+            print!("{}", day_profile.report(5));
+            println!("Annotated listing written to {listing_file}");
+        }
+        for image_file in image::take_saved() {
+            println!("Visualisation written to {}", image_file.display());
+        }
+        for animation_file in animation::take_saved() {
+            println!("Animation written to {}", animation_file.display());
+        }
+        let rewrites = intcode_optimiser::take_rewrites();
+        if !rewrites.is_empty() {
+            println!("Optimiser made {} rewrite(s):", rewrites.len());
+            for rewrite in rewrites {
+                println!("  {}", rewrite);
+            }
+        }
+        let put_backs = intcode_optimiser::take_put_backs();
+        if let Some(first) = put_backs.first() {
+            println!(
+                "Programs put their original image back {} time(s), the first because {}",
+                put_backs.len(),
+                first
+            );
+        }
     }
     let total_elapsed = total_now.elapsed();
     println!(
@@ -0,0 +1,219 @@
+// Number theory for the days that need it - modular arithmetic for day 22's shuffles, LCM for day 12's moon periods,
+// and the Chinese remainder theorem for lining periods up. Everything is generic over num's Integer, so it works for
+// the primitive types and BigInt alike, though the ones built on the extended Euclidean algorithm need signed types as
+// its coefficients go negative. Moduli are assumed positive, and results are always in 0..modulus.
+#![allow(dead_code)]
+
+use num::{Integer, Signed};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MathError<T> {
+    NotInvertible { value: T, modulus: T }, //They share a factor
+    NoSolution { residue: T, modulus: T },  //Congruence that contradicts the ones before it
+}
+
+impl<T: fmt::Display> fmt::Display for MathError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MathError::NotInvertible { value, modulus } => {
+                write!(f, "{} has no inverse mod {}", value, modulus)
+            }
+            MathError::NoSolution { residue, modulus } => write!(
+                f,
+                "x = {} mod {} contradicts the congruences before it",
+                residue, modulus
+            ),
+        }
+    }
+}
+
+impl<T: fmt::Debug + fmt::Display> std::error::Error for MathError<T> {}
+
+// a * b mod modulus without ever multiplying, by doubling and adding, so nothing bigger than 2 * modulus turns up on
+// the way. That means it can't overflow as long as the modulus is less than half the type's maximum - unlike a * b,
+// which overflows an i64 as soon as the modulus gets past about 3e9.
+pub fn mul_mod<T: Integer + Clone>(a: T, b: T, modulus: &T) -> T {
+    let two = T::one() + T::one();
+    let mut a = a.mod_floor(modulus);
+    let mut b = b.mod_floor(modulus);
+    let mut product = T::zero();
+    while !b.is_zero() {
+        if b.is_odd() {
+            product = (product + a.clone()).mod_floor(modulus);
+        }
+        a = (a.clone() + a).mod_floor(modulus);
+        b = b / two.clone();
+    }
+    product
+}
+
+// base^exponent mod modulus by repeated squaring. Panics on negative exponents - use mod_inverse first.
+pub fn pow_mod<T: Integer + Clone>(base: T, exponent: T, modulus: &T) -> T {
+    assert!(exponent >= T::zero(), "Negative exponent");
+    let two = T::one() + T::one();
+    let mut base = base.mod_floor(modulus);
+    let mut exponent = exponent;
+    let mut result = T::one().mod_floor(modulus); //Everything is 0 mod 1
+    while !exponent.is_zero() {
+        if exponent.is_odd() {
+            result = mul_mod(result, base.clone(), modulus);
+        }
+        base = mul_mod(base.clone(), base, modulus);
+        exponent = exponent / two.clone();
+    }
+    result
+}
+
+// Extended Euclidean algorithm: returns (gcd, x, y) such that a * x + b * y = gcd. The gcd is never negative.
+// See https://en.wikipedia.org/wiki/Extended_Euclidean_algorithm
+pub fn egcd<T: Integer + Signed + Clone>(a: T, b: T) -> (T, T, T) {
+    let (mut old_r, mut r) = (a, b);
+    let (mut old_s, mut s) = (T::one(), T::zero());
+    let (mut old_t, mut t) = (T::zero(), T::one());
+    while !r.is_zero() {
+        let q = old_r.clone() / r.clone();
+        (old_r, r) = (r.clone(), old_r - q.clone() * r);
+        (old_s, s) = (s.clone(), old_s - q.clone() * s);
+        (old_t, t) = (t.clone(), old_t - q * t);
+    }
+    if old_r.is_negative() {
+        (-old_r, -old_s, -old_t)
+    } else {
+        (old_r, old_s, old_t)
+    }
+}
+
+// The x in 0..modulus with value * x = 1 mod modulus, which only exists if value and modulus are coprime.
+pub fn mod_inverse<T: Integer + Signed + Clone>(value: T, modulus: &T) -> Result<T, MathError<T>> {
+    let (gcd, x, _) = egcd(value.mod_floor(modulus), modulus.clone());
+    if gcd.is_one() {
+        Ok(x.mod_floor(modulus))
+    } else {
+        Err(MathError::NotInvertible {
+            value,
+            modulus: modulus.clone(),
+        })
+    }
+}
+
+pub fn gcd_all<T: Integer + Clone>(values: &[T]) -> T {
+    values.iter().fold(T::zero(), |gcd, value| gcd.gcd(value))
+}
+
+// LCM of all the values - the first time things with these periods all line up again. 1 if there aren't any.
+pub fn lcm_all<T: Integer + Clone>(values: &[T]) -> T {
+    values.iter().fold(T::one(), |lcm, value| lcm.lcm(value))
+}
+
+// Chinese remainder theorem: the x satisfying x = residue mod modulus for every (residue, modulus) pair, returned along
+// with the LCM of the moduli (x is unique mod that). The moduli don't have to be coprime, in which case there may be no
+// solution. Merges the congruences one at a time, so intermediate values stay below the LCM.
+pub fn crt<T: Integer + Signed + Clone>(congruences: &[(T, T)]) -> Result<(T, T), MathError<T>> {
+    let mut x = T::zero();
+    let mut lcm = T::one();
+    for (residue, modulus) in congruences {
+        //x + lcm * k = residue mod modulus, so lcm * k = residue - x mod modulus, which needs gcd to divide residue - x.
+        let (gcd, _, _) = egcd(lcm.clone(), modulus.clone());
+        let difference = residue.clone() - x.clone();
+        if !difference.is_multiple_of(&gcd) {
+            return Err(MathError::NoSolution {
+                residue: residue.clone(),
+                modulus: modulus.clone(),
+            });
+        }
+        let reduced_modulus = modulus.clone() / gcd.clone();
+        let inverse = mod_inverse(lcm.clone() / gcd.clone(), &reduced_modulus)
+            .unwrap_or_else(|_| unreachable!("Dividing out the gcd leaves them coprime"));
+        let k = mul_mod(difference / gcd, inverse, &reduced_modulus);
+        x = x + lcm.clone() * k;
+        lcm = lcm * reduced_modulus;
+        x = x.mod_floor(&lcm);
+    }
+    Ok((x, lcm))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::bigint::BigInt;
+
+    #[test]
+    fn test_mul_mod_and_pow_mod() {
+        assert_eq!(6, mul_mod(7, 8, &10));
+        assert_eq!(4, mul_mod(-7, 8, &10));
+        //These overflow if multiplied directly.
+        let modulus: i64 = 119315717514047;
+        let a: i64 = 98765432109876;
+        assert_eq!(
+            ((a as i128 * a as i128) % modulus as i128) as i64,
+            mul_mod(a, a, &modulus)
+        );
+        let modulus = u64::MAX / 2;
+        assert_eq!(1, mul_mod(modulus - 1, modulus - 1, &modulus));
+
+        assert_eq!(1, pow_mod(3, 0, &7));
+        assert_eq!(0, pow_mod(3, 0, &1));
+        assert_eq!(5, pow_mod(3, 5, &7)); //243 = 34 * 7 + 5
+        assert_eq!(1, pow_mod(2, 119315717514046, &119315717514047_i64)); //Fermat - modulus is prime
+        assert_eq!(
+            BigInt::from(3).pow(100) % BigInt::from(1_000_000_007),
+            pow_mod(
+                BigInt::from(3),
+                BigInt::from(100),
+                &BigInt::from(1_000_000_007)
+            )
+        );
+    }
+
+    #[test]
+    fn test_egcd_and_mod_inverse() {
+        assert_eq!((2, -9, 47), egcd(240, 46));
+        assert_eq!((5, -1, 0), egcd(-5, 0));
+        let (gcd, x, y) = egcd(-240, 46);
+        assert_eq!((2, 2), (gcd, -240 * x + 46 * y));
+
+        assert_eq!(Ok(4), mod_inverse(3, &11));
+        assert_eq!(Ok(7), mod_inverse(-3, &11));
+        assert_eq!(
+            Err(MathError::NotInvertible {
+                value: 4,
+                modulus: 10
+            }),
+            mod_inverse(4, &10)
+        );
+        assert_eq!(
+            "4 has no inverse mod 10",
+            mod_inverse(4, &10).unwrap_err().to_string()
+        );
+        let modulus = BigInt::from(119315717514047_i64);
+        let inverse = mod_inverse(BigInt::from(2020), &modulus).unwrap();
+        assert_eq!(BigInt::from(1), (inverse * BigInt::from(2020)) % modulus);
+    }
+
+    #[test]
+    fn test_lcm_and_crt() {
+        assert_eq!(2772, lcm_all(&[18, 28, 44]));
+        assert_eq!(1, lcm_all::<usize>(&[]));
+        assert_eq!(6, gcd_all(&[18, 24, -30]));
+
+        //Sunzi's original: 23 is 2 mod 3, 3 mod 5 and 2 mod 7.
+        assert_eq!(Ok((23, 105)), crt(&[(2, 3), (3, 5), (2, 7)]));
+        //Moduli sharing a factor.
+        assert_eq!(Ok((10, 12)), crt(&[(4, 6), (2, 4)]));
+        assert_eq!(
+            Err(MathError::NoSolution {
+                residue: 3,
+                modulus: 4
+            }),
+            crt(&[(4, 6), (3, 4)])
+        );
+        assert_eq!(Ok((0, 1)), crt::<i32>(&[]));
+
+        let big = |n: i64| BigInt::from(n);
+        let (x, lcm) = crt(&[(big(17), big(1_000_000_007)), (big(3), big(998_244_353))]).unwrap();
+        assert_eq!(big(1_000_000_007) * big(998_244_353), lcm);
+        assert_eq!(big(17), &x % big(1_000_000_007));
+        assert_eq!(big(3), &x % big(998_244_353));
+    }
+}
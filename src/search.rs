@@ -0,0 +1,305 @@
+// Graph searches for the maze days. The graph is never built up front - the caller passes a neighbour function that
+// says where you can go from a node (and what it costs, for Dijkstra and A*), and a goal test. The search stops when
+// it takes a goal node off the frontier (pass |_| false to explore everything reachable) and returns the distances and
+// predecessor tree it built, from which you can read off the path to any node it reached.
+#![allow(dead_code)]
+
+use num::Zero;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::hash::Hash;
+
+#[derive(Clone, Debug)]
+pub struct Search<N, C = usize> {
+    distances: HashMap<N, C>,
+    predecessors: HashMap<N, N>, //No entry for the start nodes
+    goal: Option<N>,             //The goal node reached, if any
+}
+
+impl<N: Clone + Eq + Hash, C: Copy> Search<N, C> {
+    fn new() -> Search<N, C> {
+        Search {
+            distances: HashMap::new(),
+            predecessors: HashMap::new(),
+            goal: None,
+        }
+    }
+
+    pub fn goal(&self) -> Option<&N> {
+        self.goal.as_ref()
+    }
+
+    // Distance to the goal, if one was reached.
+    pub fn goal_distance(&self) -> Option<C> {
+        self.goal.as_ref().and_then(|goal| self.distance(goal))
+    }
+
+    // Path from a start node to the goal, both inclusive.
+    pub fn goal_path(&self) -> Option<Vec<N>> {
+        self.goal.as_ref().and_then(|goal| self.path_to(goal))
+    }
+
+    pub fn distance(&self, node: &N) -> Option<C> {
+        self.distances.get(node).copied()
+    }
+
+    pub fn distances(&self) -> &HashMap<N, C> {
+        &self.distances
+    }
+
+    pub fn predecessor(&self, node: &N) -> Option<&N> {
+        self.predecessors.get(node)
+    }
+
+    pub fn reached(&self, node: &N) -> bool {
+        self.distances.contains_key(node)
+    }
+
+    // Follows the predecessors back from node to the start it was reached from. Both ends are included.
+    pub fn path_to(&self, node: &N) -> Option<Vec<N>> {
+        if !self.reached(node) {
+            return None;
+        }
+        let mut path = vec![node.clone()];
+        while let Some(predecessor) = self.predecessors.get(path.last().unwrap()) {
+            path.push(predecessor.clone());
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+// Breadth-first search, where every step costs 1. Nodes are at the distance they're first reached.
+pub fn bfs<N, I>(
+    starts: impl IntoIterator<Item = N>,
+    mut neighbours: impl FnMut(&N) -> I,
+    mut is_goal: impl FnMut(&N) -> bool,
+) -> Search<N>
+where
+    N: Clone + Eq + Hash,
+    I: IntoIterator<Item = N>,
+{
+    let mut search = Search::new();
+    let mut frontier = VecDeque::new();
+    for start in starts {
+        if !search.reached(&start) {
+            search.distances.insert(start.clone(), 0);
+            frontier.push_back(start);
+        }
+    }
+
+    while let Some(node) = frontier.pop_front() {
+        if is_goal(&node) {
+            search.goal = Some(node);
+            break;
+        }
+        let distance = search.distances[&node] + 1;
+        for neighbour in neighbours(&node) {
+            if !search.reached(&neighbour) {
+                search.distances.insert(neighbour.clone(), distance);
+                search.predecessors.insert(neighbour.clone(), node.clone());
+                frontier.push_back(neighbour);
+            }
+        }
+    }
+
+    search
+}
+
+// Dijkstra's algorithm, for when steps have different (non-negative) costs. neighbours gives each neighbour along with
+// the cost of stepping to it.
+pub fn dijkstra<N, C, I>(
+    starts: impl IntoIterator<Item = N>,
+    neighbours: impl FnMut(&N) -> I,
+    is_goal: impl FnMut(&N) -> bool,
+) -> Search<N, C>
+where
+    N: Clone + Eq + Hash,
+    C: Copy + Ord + Zero,
+    I: IntoIterator<Item = (N, C)>,
+{
+    astar(starts, neighbours, |_| C::zero(), is_goal)
+}
+
+// A*: Dijkstra, but the frontier is ordered by cost so far plus heuristic's estimate of the cost left to a goal. The
+// heuristic must never overestimate, or the path found might not be the shortest.
+pub fn astar<N, C, I>(
+    starts: impl IntoIterator<Item = N>,
+    mut neighbours: impl FnMut(&N) -> I,
+    mut heuristic: impl FnMut(&N) -> C,
+    mut is_goal: impl FnMut(&N) -> bool,
+) -> Search<N, C>
+where
+    N: Clone + Eq + Hash,
+    C: Copy + Ord + Zero,
+    I: IntoIterator<Item = (N, C)>,
+{
+    let mut search = Search::new();
+    let mut frontier = BinaryHeap::new();
+    for start in starts {
+        if !search.reached(&start) {
+            search.distances.insert(start.clone(), C::zero());
+            frontier.push(Frontier {
+                estimate: heuristic(&start),
+                distance: C::zero(),
+                node: start,
+            });
+        }
+    }
+
+    while let Some(Frontier { distance, node, .. }) = frontier.pop() {
+        if distance > search.distances[&node] {
+            //Stale - we've found a shorter way here since this was pushed
+            continue;
+        }
+        if is_goal(&node) {
+            search.goal = Some(node);
+            break;
+        }
+        for (neighbour, cost) in neighbours(&node) {
+            let new_distance = distance + cost;
+            if search
+                .distance(&neighbour)
+                .is_none_or(|old_distance| new_distance < old_distance)
+            {
+                search.distances.insert(neighbour.clone(), new_distance);
+                search.predecessors.insert(neighbour.clone(), node.clone());
+                frontier.push(Frontier {
+                    estimate: new_distance + heuristic(&neighbour),
+                    distance: new_distance,
+                    node: neighbour,
+                });
+            }
+        }
+    }
+
+    search
+}
+
+// Entry in the A* frontier. BinaryHeap is a max-heap, so the ordering is reversed to pop the lowest estimate first.
+struct Frontier<N, C> {
+    estimate: C,
+    distance: C,
+    node: N,
+}
+
+impl<N, C: Ord> Ord for Frontier<N, C> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.cmp(&self.estimate)
+    }
+}
+
+impl<N, C: Ord> PartialOrd for Frontier<N, C> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<N, C: Ord> PartialEq for Frontier<N, C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.estimate == other.estimate
+    }
+}
+
+impl<N, C: Ord> Eq for Frontier<N, C> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::{Grid, Point};
+
+    //'#' is a wall, anything else is open.
+    fn maze(text: &str) -> Grid<char> {
+        text.parse().unwrap()
+    }
+
+    fn open_neighbours(maze: &Grid<char>, point: Point) -> Vec<Point> {
+        maze.neighbours(point)
+            .filter(|neighbour| maze[*neighbour] != '#')
+            .collect()
+    }
+
+    #[test]
+    fn test_bfs() {
+        let maze = maze(
+            "#######
+#S..#.#
+#.#.#.#
+#.#...#
+#...#E#
+#######",
+        );
+        let start = maze.find(|c| *c == 'S').unwrap();
+        let end = maze.find(|c| *c == 'E').unwrap();
+
+        let search = bfs(
+            [start],
+            |point| open_neighbours(&maze, *point),
+            |point| *point == end,
+        );
+        assert_eq!(Some(&end), search.goal());
+        assert_eq!(Some(7), search.goal_distance());
+        let path = search.goal_path().unwrap();
+        assert_eq!(8, path.len());
+        assert_eq!((start, end), (path[0], path[7]));
+        assert!(path
+            .windows(2)
+            .all(|step| step[0].manhattan_distance(step[1]) == 1));
+        assert_eq!(Some(&path[6]), search.predecessor(&end));
+
+        //Exploring everything.
+        let search = bfs([start], |point| open_neighbours(&maze, *point), |_| false);
+        assert_eq!(None, search.goal());
+        assert_eq!(15, search.distances().len());
+        assert_eq!(Some(&8), search.distances().values().max());
+        assert!(!search.reached(&Point::ORIGIN));
+        assert_eq!(None, search.path_to(&Point::ORIGIN));
+
+        //Several starts at once: distance to the nearest.
+        let search = bfs(
+            [start, end],
+            |point| open_neighbours(&maze, *point),
+            |_| false,
+        );
+        assert_eq!(Some(4), search.distances().values().max().copied());
+        assert_eq!(Some(vec![end]), search.path_to(&end));
+    }
+
+    #[test]
+    fn test_dijkstra_and_astar() {
+        //Digits cost that much to step onto; the cheapest route goes the long way round.
+        let maze = maze(
+            "#####
+#S91#
+#.#1#
+#111#
+#####",
+        );
+        let start = maze.find(|c| *c == 'S').unwrap();
+        let end = Point::new(3, 1);
+        let neighbours = |point: &Point| {
+            open_neighbours(&maze, *point)
+                .into_iter()
+                .map(|neighbour| (neighbour, maze[neighbour].to_digit(10).unwrap_or(1)))
+                .collect::<Vec<_>>()
+        };
+
+        let search = dijkstra([start], neighbours, |point| *point == end);
+        assert_eq!(Some(6), search.goal_distance());
+        assert_eq!(7, search.goal_path().unwrap().len());
+
+        let search = astar(
+            [start],
+            neighbours,
+            |point| point.manhattan_distance(end) as u32,
+            |point| *point == end,
+        );
+        assert_eq!(Some(6), search.goal_distance());
+        assert_eq!(7, search.goal_path().unwrap().len());
+
+        //No way through.
+        let search = dijkstra([start], neighbours, |point| *point == Point::ORIGIN);
+        assert_eq!(None, search.goal_distance());
+        assert_eq!(8, search.distances().len());
+    }
+}
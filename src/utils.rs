@@ -1,73 +1,243 @@
-use std::fmt::Debug;
+use std::fmt;
 use std::fs;
 use std::str;
 
+/// Something wrong with an input file. Lines and columns count from 1, columns in characters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    Read {
+        file: String,
+        reason: String,
+    },
+    BadToken {
+        file: String,
+        line: usize,
+        column: usize,
+        token: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Read { file, reason } => write!(f, "Couldn't read {}: {}", file, reason),
+            ParseError::BadToken {
+                file,
+                line,
+                column,
+                token,
+                reason,
+            } => write!(
+                f,
+                "{}:{}:{}: couldn't parse '{}' ({})",
+                file, line, column, token, reason
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn read(input_file: &str) -> Result<String, ParseError> {
+    fs::read_to_string(input_file).map_err(|err| ParseError::Read {
+        file: input_file.to_string(),
+        reason: err.to_string(),
+    })
+}
+
+/// The lines of input with trailing whitespace removed, and without any blank lines at the end. Each comes with its
+/// line number.
+fn trimmed_lines(input: &str) -> Vec<(usize, &str)> {
+    let mut lines: Vec<(usize, &str)> = input
+        .lines()
+        .map(str::trim_end)
+        .enumerate()
+        .map(|(index, line)| (index + 1, line))
+        .collect();
+    while lines.last().is_some_and(|(_, line)| line.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+/// Line and column of the character at byte offset in input.
+fn position(input: &str, offset: usize) -> (usize, usize) {
+    let before = &input[..offset];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+fn parse_token<T: str::FromStr>(
+    input_file: &str,
+    token: &str,
+    (line, column): (usize, usize),
+) -> Result<T, ParseError>
+where
+    <T as str::FromStr>::Err: fmt::Display,
+{
+    token.parse().map_err(|err: T::Err| ParseError::BadToken {
+        file: input_file.to_string(),
+        line,
+        column,
+        token: token.to_string(),
+        reason: err.to_string(),
+    })
+}
+
+fn parse_lines<T: str::FromStr>(input_file: &str, input: &str) -> Result<Vec<T>, ParseError>
+where
+    <T as str::FromStr>::Err: fmt::Display,
+{
+    trimmed_lines(input)
+        .into_iter()
+        .map(|(line_number, line)| parse_token(input_file, line, (line_number, 1)))
+        .collect()
+}
+
+fn parse_separated<T: str::FromStr>(
+    input_file: &str,
+    input: &str,
+    separator: char,
+) -> Result<Vec<T>, ParseError>
+where
+    <T as str::FromStr>::Err: fmt::Display,
+{
+    let mut offset = 0;
+    input
+        .trim_end()
+        .split(separator)
+        .map(|token| {
+            let start = offset + (token.len() - token.trim_start().len());
+            offset += token.len() + separator.len_utf8();
+            parse_token(input_file, token.trim(), position(input, start))
+        })
+        .collect()
+}
+
+fn parse_digits(input_file: &str, input: &str) -> Result<Vec<Vec<usize>>, ParseError> {
+    trimmed_lines(input)
+        .into_iter()
+        .map(|(line_number, line)| {
+            line.chars()
+                .enumerate()
+                .map(|(index, c)| {
+                    c.to_digit(10)
+                        .map(|digit| digit as usize)
+                        .ok_or_else(|| ParseError::BadToken {
+                            file: input_file.to_string(),
+                            line: line_number,
+                            column: index + 1,
+                            token: c.to_string(),
+                            reason: "not a digit".to_string(),
+                        })
+                })
+                .collect()
+        })
+        .collect()
+}
+
 /// Takes a file containing a list of strings, one per line, and returns the
 /// list as a vector of T
+pub fn try_parse_input<T: str::FromStr>(input_file: &str) -> Result<Vec<T>, ParseError>
+where
+    <T as str::FromStr>::Err: fmt::Display,
+{
+    parse_lines(input_file, &read(input_file)?)
+}
+
+/// As for try_parse_input, but panics with the error.
 pub fn parse_input<T: str::FromStr>(input_file: &str) -> Vec<T>
 where
-    <T as str::FromStr>::Err: Debug,
+    <T as str::FromStr>::Err: fmt::Display,
+{
+    try_parse_input(input_file).unwrap_or_else(|err| panic!("{err}"))
+}
+
+/// Input is a single line, with Ts separated by separator chars. Return a Vec of T. Whitespace around each T is
+/// ignored.
+pub fn try_parse_input_by_sep<T: str::FromStr>(
+    input_file: &str,
+    separator: char,
+) -> Result<Vec<T>, ParseError>
+where
+    <T as str::FromStr>::Err: fmt::Display,
 {
-    let input = fs::read_to_string(input_file).expect("Oh dear, couldn't read file!");
-    let vector: Vec<T> = input.lines().map(|line| line.parse().unwrap()).collect();
-    vector
+    parse_separated(input_file, &read(input_file)?, separator)
 }
 
-/// Input is a single line, with Ts separated by separator chars. Return a Vec of T.
+/// As for try_parse_input_by_sep, but panics with the error.
 pub fn parse_input_by_sep<T: str::FromStr>(input_file: &str, separator: char) -> Vec<T>
 where
-    <T as str::FromStr>::Err: Debug,
+    <T as str::FromStr>::Err: fmt::Display,
 {
-    let input = fs::read_to_string(input_file).expect("Oh dear, couldn't read file!");
-    let vector: Vec<T> = input.split(separator).map(|x| x.parse().unwrap()).collect();
-    vector
+    try_parse_input_by_sep(input_file, separator).unwrap_or_else(|err| panic!("{err}"))
 }
 
 /// Parse input by splitting on blank lines and returning as a vector of strings
+#[allow(dead_code)]
+pub fn try_parse_input_by_blank_lines(input_file: &str) -> Result<Vec<String>, ParseError> {
+    let input = read(input_file)?;
+    let lines: Vec<&str> = trimmed_lines(&input)
+        .into_iter()
+        .map(|(_, line)| line)
+        .collect();
+    Ok(lines
+        .split(|line| line.is_empty())
+        .map(|group| group.join("\n"))
+        .collect())
+}
+
 #[allow(dead_code)]
 pub fn parse_input_by_blank_lines(input_file: &str) -> Vec<String> {
-    let input = fs::read_to_string(input_file).expect("Oh dear, couldn't read file!");
-    let vector: Vec<String> = input.split("\n\n").map(|x| x.to_string()).collect();
-    vector
+    try_parse_input_by_blank_lines(input_file).unwrap_or_else(|err| panic!("{err}"))
 }
 
 /// Parse input by creating a vector of strings, one string per line. Then for each line convert each character to
 /// a digit and store as a usize. Each element of the resulting vector is a vector of usizes obtained from each character in the corresponding line.
+#[allow(dead_code)]
+pub fn try_parse_input_usizes(input_file: &str) -> Result<Vec<Vec<usize>>, ParseError> {
+    parse_digits(input_file, &read(input_file)?)
+}
+
 #[allow(dead_code)]
 pub fn parse_input_usizes(input_file: &str) -> Vec<Vec<usize>> {
-    let input = fs::read_to_string(input_file).expect("Oh dear, couldn't read file!");
-    let mut vector: Vec<String> = input.lines().map(|line| line.parse().unwrap()).collect();
-    vector
-        .iter_mut()
-        .map(|x: &mut String| {
-            x.chars()
-                .map(|y| y.to_digit(10).unwrap() as usize)
-                .collect()
-        })
-        .collect::<Vec<Vec<usize>>>()
+    try_parse_input_usizes(input_file).unwrap_or_else(|err| panic!("{err}"))
 }
 
 /// As for parse_input_usize, but return a vec of a vec of chars.
+#[allow(dead_code)]
+pub fn try_parse_input_chars(input_file: &str) -> Result<Vec<Vec<char>>, ParseError> {
+    Ok(trimmed_lines(&read(input_file)?)
+        .into_iter()
+        .map(|(_, line)| line.chars().collect())
+        .collect())
+}
+
 #[allow(dead_code)]
 pub fn parse_input_chars(input_file: &str) -> Vec<Vec<char>> {
-    let input = fs::read_to_string(input_file).expect("Oh dear, couldn't read file!");
-    let mut vector: Vec<String> = input.lines().map(|line| line.parse().unwrap()).collect();
-    vector
-        .iter_mut()
-        .map(|x: &mut String| x.chars().collect())
-        .collect::<Vec<Vec<char>>>()
+    try_parse_input_chars(input_file).unwrap_or_else(|err| panic!("{err}"))
 }
 
 /// Each line is a <separator>-separated list of strings, parse this into a Vec of Vec of strings - top-level vec
 /// is split by newlines, inner vec split by separator character.
+#[allow(dead_code)]
+pub fn try_parse_input_sep_strings(
+    input_file: &str,
+    separator: char,
+) -> Result<Vec<Vec<String>>, ParseError> {
+    Ok(trimmed_lines(&read(input_file)?)
+        .into_iter()
+        .map(|(_, line)| line.split(separator).map(|y: &str| y.to_string()).collect())
+        .collect())
+}
+
 #[allow(dead_code)]
 pub fn parse_input_sep_strings(input_file: &str, separator: char) -> Vec<Vec<String>> {
-    let input = fs::read_to_string(input_file).expect("Oh dear, couldn't read file!");
-    let mut vector: Vec<String> = input.lines().map(|line| line.to_string()).collect();
-    vector
-        .iter_mut()
-        .map(|x: &mut String| x.split(separator).map(|y: &str| y.to_string()).collect())
-        .collect::<Vec<Vec<String>>>()
+    try_parse_input_sep_strings(input_file, separator).unwrap_or_else(|err| panic!("{err}"))
 }
 
 /// Converts a string representing a binary number of up to 63 characters, e.g. "0100110110111" and converts to a usize.
@@ -94,3 +264,70 @@ pub fn string_binary_to_usize(binary_as_string: String) -> usize {
         .sum();
     number
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lines() {
+        assert_eq!(
+            Ok(vec![1, -2, 3]),
+            parse_lines::<isize>("f", "1\n-2  \n3\n\n  \n")
+        );
+        assert_eq!(
+            Err(ParseError::BadToken {
+                file: "f".to_string(),
+                line: 2,
+                column: 1,
+                token: "x2".to_string(),
+                reason: "invalid digit found in string".to_string()
+            }),
+            parse_lines::<usize>("f", "1\nx2\n3")
+        );
+        //Blank lines in the middle are still tokens.
+        assert!(parse_lines::<usize>("f", "1\n\n3").is_err());
+    }
+
+    #[test]
+    fn test_parse_separated() {
+        assert_eq!(
+            Ok(vec![1, 2, 3]),
+            parse_separated::<usize>("day2.txt", "1,2,3\n", ',')
+        );
+        assert_eq!(
+            Ok(vec!["bob".to_string(), "charlie".to_string()]),
+            parse_separated("f", " bob - charlie \r\n", '-')
+        );
+        let err = parse_separated::<isize>("day2.txt", "1,2,,3", ',').unwrap_err();
+        assert_eq!(
+            "day2.txt:1:5: couldn't parse '' (cannot parse integer from empty string)",
+            err.to_string()
+        );
+        let err = parse_separated::<isize>("f", "1,2,\n 3,4a", ',').unwrap_err();
+        assert_eq!(
+            "f:2:4: couldn't parse '4a' (invalid digit found in string)",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_parse_digits() {
+        assert_eq!(
+            Ok(vec![vec![1, 2], vec![3, 4]]),
+            parse_digits("f", "12\n34 \n")
+        );
+        assert_eq!(
+            "f:2:2: couldn't parse '.' (not a digit)",
+            parse_digits("f", "12\n3.\n").unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_read_error() {
+        match try_parse_input::<usize>("no/such/file.txt") {
+            Err(ParseError::Read { file, .. }) => assert_eq!("no/such/file.txt", file),
+            result => panic!("Expected a read error, got {:?}", result),
+        }
+    }
+}
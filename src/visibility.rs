@@ -0,0 +1,287 @@
+// Line of sight between the occupied cells of a grid, like day 10's asteroids. Seen from anywhere, the occupied cells
+// fall into lines by their Bearing, and only the nearest cell on each line can be seen - it hides the ones behind it.
+// A laser sweeping round from a cell destroys the nearest cell on each line as it passes, so it goes round once for
+// each cell on the most crowded line.
+#![allow(dead_code)]
+
+use crate::geometry::{Bearing, Rotation};
+use crate::grid::{Grid, Point};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+pub struct LineOfSight {
+    cells: Vec<Point>, //Occupied, in order
+}
+
+// viewer can't see hidden because blocker is in the way - the nearest occupied cell to hidden between the two.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Occlusion {
+    pub viewer: Point,
+    pub hidden: Point,
+    pub blocker: Point,
+}
+
+impl LineOfSight {
+    pub fn new(cells: impl IntoIterator<Item = Point>) -> LineOfSight {
+        let mut cells: Vec<Point> = cells.into_iter().collect();
+        cells.sort();
+        cells.dedup();
+        LineOfSight { cells }
+    }
+
+    pub fn from_grid<T>(grid: &Grid<T>, mut occupied: impl FnMut(&T) -> bool) -> LineOfSight {
+        LineOfSight::new(
+            grid.iter()
+                .filter(|(_, value)| occupied(value))
+                .map(|(point, _)| point),
+        )
+    }
+
+    pub fn cells(&self) -> &[Point] {
+        &self.cells
+    }
+
+    // Every occupied cell apart from viewer (which doesn't have to be occupied itself), by bearing from viewer and
+    // nearest first.
+    pub fn lines_from(&self, viewer: Point) -> BTreeMap<Bearing, Vec<Point>> {
+        let mut lines: BTreeMap<Bearing, Vec<Point>> = BTreeMap::new();
+        for cell in &self.cells {
+            if *cell != viewer {
+                lines
+                    .entry(Bearing::between(viewer, *cell))
+                    .or_default()
+                    .push(*cell);
+            }
+        }
+        for line in lines.values_mut() {
+            line.sort_by_key(|cell| cell.manhattan_distance(viewer));
+        }
+        lines
+    }
+
+    // The cells viewer can see, clockwise from straight up.
+    pub fn visible_from(&self, viewer: Point) -> Vec<Point> {
+        self.lines_from(viewer)
+            .values()
+            .map(|line| line[0])
+            .collect()
+    }
+
+    // How many cells each occupied cell can see.
+    pub fn visible_counts(&self) -> HashMap<Point, usize> {
+        self.cells
+            .iter()
+            .map(|cell| (*cell, self.lines_from(*cell).len()))
+            .collect()
+    }
+
+    // The occupied cell that can see the most others (the first in order, if there's a tie) and how many it sees.
+    pub fn best_viewpoint(&self) -> Option<(Point, usize)> {
+        self.visible_counts()
+            .into_iter()
+            .max_by_key(|(cell, count)| (*count, Reverse(*cell)))
+    }
+
+    // Every occupied cell that another can't see, and what's in the way.
+    pub fn occlusions(&self) -> Vec<Occlusion> {
+        let mut occlusions = vec![];
+        for viewer in &self.cells {
+            for line in self.lines_from(*viewer).values() {
+                for pair in line.windows(2) {
+                    occlusions.push(Occlusion {
+                        viewer: *viewer,
+                        hidden: pair[1],
+                        blocker: pair[0],
+                    });
+                }
+            }
+        }
+        occlusions
+    }
+
+    // The order a laser at station destroys every other occupied cell, sweeping round from start (which it hits
+    // first) in the direction given.
+    pub fn vaporisation_order(
+        &self,
+        station: Point,
+        start: Bearing,
+        rotation: Rotation,
+    ) -> Vaporisation {
+        let mut lines: Vec<(Bearing, Vec<Point>)> = self.lines_from(station).into_iter().collect();
+        lines.sort_by(|(a, _), (b, _)| a.cmp_from(b, start, rotation));
+        let lines: Vec<VecDeque<Point>> = lines.into_iter().map(|(_, line)| line.into()).collect();
+        Vaporisation {
+            remaining: lines.iter().map(VecDeque::len).sum(),
+            lines,
+            next_line: 0,
+        }
+    }
+}
+
+// Cells in the order the laser destroys them, worked out as it goes round.
+pub struct Vaporisation {
+    lines: Vec<VecDeque<Point>>, //In the order the laser gets to them, nearest cell first
+    next_line: usize,
+    remaining: usize,
+}
+
+impl Iterator for Vaporisation {
+    type Item = Point;
+
+    fn next(&mut self) -> Option<Point> {
+        if self.remaining == 0 {
+            return None;
+        }
+        loop {
+            if self.next_line == self.lines.len() {
+                //Round again, forgetting the lines that are all gone.
+                self.lines.retain(|line| !line.is_empty());
+                self.next_line = 0;
+            }
+            let line = &mut self.lines[self.next_line];
+            self.next_line += 1;
+            if let Some(cell) = line.pop_front() {
+                self.remaining -= 1;
+                return Some(cell);
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for Vaporisation {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line_of_sight(lines: &[&str]) -> LineOfSight {
+        let grid: Grid<char> = lines.join("\n").parse().unwrap();
+        LineOfSight::from_grid(&grid, |c| *c == '#')
+    }
+
+    #[test]
+    fn test_visible_counts() {
+        //The first example from day 10, with how many asteroids each one can see.
+        let asteroids = line_of_sight(&[".#..#", ".....", "#####", "....#", "...##"]);
+        let counts = asteroids.visible_counts();
+        let expected = [".7..7", ".....", "67775", "....7", "...87"];
+        for (y, row) in expected.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                assert_eq!(
+                    c.to_digit(10).map(|count| count as usize),
+                    counts.get(&Point::new(x as isize, y as isize)).copied()
+                );
+            }
+        }
+        assert_eq!(Some((Point::new(3, 4), 8)), asteroids.best_viewpoint());
+        assert_eq!(7, asteroids.visible_from(Point::new(1, 0)).len());
+        //From an empty cell, clockwise from straight up.
+        assert_eq!(
+            [
+                (4, 0),
+                (4, 2),
+                (4, 3),
+                (4, 4),
+                (3, 2),
+                (2, 2),
+                (1, 2),
+                (0, 2),
+                (1, 0)
+            ]
+            .iter()
+            .map(|(x, y)| Point::new(*x, *y))
+            .collect::<Vec<_>>(),
+            asteroids.visible_from(Point::new(3, 1))
+        );
+    }
+
+    #[test]
+    fn test_occlusions() {
+        let asteroids = line_of_sight(&["#.#.#", "....."]);
+        assert_eq!(
+            vec![
+                Occlusion {
+                    viewer: Point::new(0, 0),
+                    hidden: Point::new(4, 0),
+                    blocker: Point::new(2, 0)
+                },
+                Occlusion {
+                    viewer: Point::new(4, 0),
+                    hidden: Point::new(0, 0),
+                    blocker: Point::new(2, 0)
+                },
+            ],
+            asteroids.occlusions()
+        );
+
+        //Everything's either visible or hidden.
+        let asteroids = line_of_sight(&[".#..#", ".....", "#####", "....#", "...##"]);
+        let occlusions = asteroids.occlusions();
+        let counts = asteroids.visible_counts();
+        for viewer in asteroids.cells() {
+            let hidden = occlusions
+                .iter()
+                .filter(|occlusion| occlusion.viewer == *viewer)
+                .count();
+            assert_eq!(asteroids.cells().len() - 1, counts[viewer] + hidden);
+        }
+    }
+
+    #[test]
+    fn test_vaporisation_order() {
+        //Day 10's example, with the station at X.
+        let map = [
+            ".#....#####...#..",
+            "##...##.#####..##",
+            "##...#...#.#####.",
+            "..#.....X...###..",
+            "..#.#.....#....##",
+        ];
+        let station = Point::new(8, 3);
+        let asteroids = line_of_sight(&map);
+        let order: Vec<Point> = asteroids
+            .vaporisation_order(station, Bearing::UP, Rotation::Clockwise)
+            .collect();
+        assert_eq!(asteroids.cells().len(), order.len());
+        assert_eq!(
+            vec![
+                Point::new(8, 1),
+                Point::new(9, 0),
+                Point::new(9, 1),
+                Point::new(10, 0),
+                Point::new(9, 2),
+                Point::new(11, 1),
+                Point::new(12, 1),
+                Point::new(11, 2),
+                Point::new(15, 1),
+            ],
+            order[..9]
+        );
+        let mut laser = asteroids.vaporisation_order(station, Bearing::UP, Rotation::Clockwise);
+        assert_eq!(Some(Point::new(8, 1)), laser.next());
+        assert_eq!(asteroids.cells().len() - 1, laser.len());
+
+        //Anticlockwise is the same as clockwise in a mirror.
+        let width = map[0].len() as isize;
+        let mirror = |point: Point| Point::new(width - 1 - point.x, point.y);
+        let mirrored = LineOfSight::new(asteroids.cells().iter().map(|cell| mirror(*cell)));
+        let anticlockwise: Vec<Point> = mirrored
+            .vaporisation_order(mirror(station), Bearing::UP, Rotation::Anticlockwise)
+            .map(mirror)
+            .collect();
+        assert_eq!(order, anticlockwise);
+
+        //And starting to the right is the same as starting at the top with the map turned a quarter turn clockwise.
+        let height = map.len() as isize;
+        let turn = |point: Point| Point::new(height - 1 - point.y, point.x);
+        let turned = LineOfSight::new(asteroids.cells().iter().map(|cell| turn(*cell)));
+        let from_right: Vec<Point> = turned
+            .vaporisation_order(turn(station), Bearing::RIGHT, Rotation::Clockwise)
+            .collect();
+        assert_eq!(order.into_iter().map(turn).collect::<Vec<_>>(), from_right);
+    }
+}